@@ -0,0 +1,7 @@
+CREATE TABLE custom_emoji (
+  id SERIAL PRIMARY KEY,
+  shortcode VARCHAR NOT NULL UNIQUE,
+  file_id INTEGER NOT NULL REFERENCES files(id),
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
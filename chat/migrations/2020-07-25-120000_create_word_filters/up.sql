@@ -0,0 +1,15 @@
+CREATE TABLE word_filters (
+  id SERIAL PRIMARY KEY,
+  pattern VARCHAR NOT NULL,
+  replacement VARCHAR NOT NULL DEFAULT '',
+  action VARCHAR NOT NULL,
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE post_flags (
+  id SERIAL PRIMARY KEY,
+  post_id INTEGER NOT NULL REFERENCES posts(id),
+  word_filter_id INTEGER NOT NULL REFERENCES word_filters(id) ON DELETE CASCADE,
+  created_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,3 @@
+DROP TABLE reports;
+
+ALTER TABLE posts DROP COLUMN hidden;
@@ -0,0 +1,14 @@
+ALTER TABLE posts ADD COLUMN hidden BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE reports (
+  id SERIAL PRIMARY KEY,
+  post_id INTEGER NOT NULL REFERENCES posts(id),
+  reason VARCHAR NOT NULL,
+  reported_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  status VARCHAR NOT NULL DEFAULT 'open',
+  resolved_by CHAR(36),
+  resolved_at TIMESTAMP
+);
+
+CREATE INDEX reports_status_idx ON reports(status);
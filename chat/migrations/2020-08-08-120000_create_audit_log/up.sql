@@ -0,0 +1,25 @@
+CREATE TABLE audit_log (
+  id SERIAL PRIMARY KEY,
+  actor_uuid CHAR(36) NOT NULL,
+  action VARCHAR NOT NULL,
+  target_type VARCHAR NOT NULL,
+  target_id VARCHAR NOT NULL,
+  before JSONB,
+  after JSONB,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX audit_log_actor_uuid_idx ON audit_log(actor_uuid);
+CREATE INDEX audit_log_action_idx ON audit_log(action);
+CREATE INDEX audit_log_created_at_idx ON audit_log(created_at);
+
+-- The log is append-only: entries can't be changed or removed once written.
+CREATE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+  BEFORE UPDATE OR DELETE ON audit_log
+  FOR EACH ROW EXECUTE PROCEDURE audit_log_append_only();
@@ -0,0 +1,12 @@
+CREATE TABLE user_restrictions (
+  id SERIAL PRIMARY KEY,
+  user_uuid CHAR(36) NOT NULL,
+  kind VARCHAR NOT NULL,
+  reason VARCHAR NOT NULL,
+  expires_at TIMESTAMP,
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  lifted_at TIMESTAMP
+);
+
+CREATE INDEX user_restrictions_user_uuid_idx ON user_restrictions(user_uuid);
@@ -0,0 +1,8 @@
+CREATE TABLE blocked_files (
+  id SERIAL PRIMARY KEY,
+  md5 CHAR(32) NOT NULL UNIQUE,
+  phash BIGINT,
+  reason VARCHAR NOT NULL,
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
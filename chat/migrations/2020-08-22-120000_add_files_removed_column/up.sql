@@ -0,0 +1 @@
+ALTER TABLE files ADD COLUMN removed BOOLEAN NOT NULL DEFAULT FALSE;
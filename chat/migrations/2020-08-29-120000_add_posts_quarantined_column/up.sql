@@ -0,0 +1 @@
+ALTER TABLE posts ADD COLUMN quarantined BOOLEAN NOT NULL DEFAULT FALSE;
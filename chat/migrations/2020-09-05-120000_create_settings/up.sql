@@ -0,0 +1,6 @@
+CREATE TABLE settings (
+  key VARCHAR PRIMARY KEY,
+  value JSONB NOT NULL,
+  updated_by CHAR(36) NOT NULL,
+  updated_at TIMESTAMP NOT NULL
+);
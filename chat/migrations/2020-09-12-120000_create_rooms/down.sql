@@ -0,0 +1,3 @@
+ALTER TABLE posts DROP COLUMN room_id;
+
+DROP TABLE rooms;
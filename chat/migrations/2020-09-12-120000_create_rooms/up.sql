@@ -0,0 +1,18 @@
+CREATE TABLE rooms (
+  id SERIAL PRIMARY KEY,
+  slug VARCHAR NOT NULL UNIQUE,
+  title VARCHAR NOT NULL,
+  description VARCHAR NOT NULL DEFAULT '',
+  nsfw BOOLEAN NOT NULL DEFAULT FALSE,
+  allowed_file_types VARCHAR[] NOT NULL DEFAULT '{}',
+  created_at TIMESTAMP NOT NULL
+);
+
+INSERT INTO rooms (slug, title, created_at) VALUES ('general', 'General', NOW());
+
+-- Existing posts all belong to the default room.
+ALTER TABLE posts ADD COLUMN room_id INTEGER REFERENCES rooms(id);
+UPDATE posts SET room_id = (SELECT id FROM rooms WHERE slug = 'general');
+ALTER TABLE posts ALTER COLUMN room_id SET NOT NULL;
+
+CREATE INDEX posts_room_id_idx ON posts(room_id);
@@ -0,0 +1,8 @@
+DELETE FROM user_favorite_files WHERE file_id IN (SELECT id FROM files WHERE post_id IS NULL);
+DELETE FROM custom_emoji WHERE file_id IN (SELECT id FROM files WHERE post_id IS NULL);
+DELETE FROM files WHERE post_id IS NULL;
+ALTER TABLE files DROP CONSTRAINT files_owner_check;
+ALTER TABLE files DROP COLUMN direct_message_id;
+ALTER TABLE files ALTER COLUMN post_id SET NOT NULL;
+
+DROP TABLE direct_messages;
@@ -0,0 +1,19 @@
+CREATE TABLE direct_messages (
+  id SERIAL PRIMARY KEY,
+  sender_uuid CHAR(36) NOT NULL,
+  recipient_uuid CHAR(36) NOT NULL,
+  message TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  read_at TIMESTAMP
+);
+
+-- Conversations are looked up by the pair of participants regardless of direction.
+CREATE INDEX direct_messages_participants_idx
+  ON direct_messages(LEAST(sender_uuid, recipient_uuid), GREATEST(sender_uuid, recipient_uuid), id);
+CREATE INDEX direct_messages_unread_idx ON direct_messages(recipient_uuid) WHERE read_at IS NULL;
+
+-- Files are attached either to a post or to a direct message.
+ALTER TABLE files ALTER COLUMN post_id DROP NOT NULL;
+ALTER TABLE files ADD COLUMN direct_message_id INTEGER REFERENCES direct_messages(id);
+ALTER TABLE files ADD CONSTRAINT files_owner_check
+  CHECK ((post_id IS NULL) <> (direct_message_id IS NULL));
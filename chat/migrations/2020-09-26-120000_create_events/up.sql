@@ -0,0 +1,10 @@
+-- Events published by any web server instance, read back by all of them after a notification.
+-- The ids are also the sequence ids clients resume from.
+CREATE TABLE events (
+  id BIGSERIAL PRIMARY KEY,
+  audience JSONB NOT NULL,
+  payload TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX events_created_at_idx ON events(created_at);
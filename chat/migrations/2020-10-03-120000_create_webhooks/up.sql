@@ -0,0 +1,29 @@
+CREATE TABLE webhooks (
+  id SERIAL PRIMARY KEY,
+  url VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  event_types VARCHAR[] NOT NULL DEFAULT '{}',
+  message_filter VARCHAR,
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE webhook_deliveries (
+  id SERIAL PRIMARY KEY,
+  webhook_id INTEGER NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+  event_id BIGINT NOT NULL,
+  event_type VARCHAR NOT NULL,
+  payload TEXT NOT NULL,
+  status VARCHAR NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP NOT NULL,
+  response_status INTEGER,
+  error VARCHAR,
+  created_at TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP NOT NULL
+);
+
+-- Every instance receives the events shared through the database, only one delivery is created.
+CREATE UNIQUE INDEX webhook_deliveries_event_idx ON webhook_deliveries(webhook_id, event_id);
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries(next_attempt_at)
+  WHERE status = 'pending';
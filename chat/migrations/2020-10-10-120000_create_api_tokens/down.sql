@@ -0,0 +1,2 @@
+ALTER TABLE posts DROP COLUMN bot;
+DROP TABLE api_tokens;
@@ -0,0 +1,15 @@
+CREATE TABLE api_tokens (
+  id SERIAL PRIMARY KEY,
+  user_uuid CHAR(36) NOT NULL,
+  name VARCHAR NOT NULL,
+  token_hash CHAR(64) NOT NULL UNIQUE,
+  scopes VARCHAR[] NOT NULL DEFAULT '{}',
+  post_burst INTEGER,
+  posts_per_minute INTEGER,
+  created_by CHAR(36) NOT NULL,
+  created_at TIMESTAMP NOT NULL,
+  last_used_at TIMESTAMP,
+  revoked_at TIMESTAMP
+);
+
+ALTER TABLE posts ADD COLUMN bot BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE events ADD COLUMN quarantined BOOLEAN NOT NULL DEFAULT FALSE;
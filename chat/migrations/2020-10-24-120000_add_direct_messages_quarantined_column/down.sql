@@ -0,0 +1 @@
+ALTER TABLE direct_messages DROP COLUMN quarantined;
@@ -0,0 +1 @@
+ALTER TABLE direct_messages ADD COLUMN quarantined BOOLEAN NOT NULL DEFAULT FALSE;
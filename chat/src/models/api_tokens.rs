@@ -0,0 +1,285 @@
+use crate::schema::api_tokens;
+use chrono::prelude::*;
+use chrono::Duration;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Allows creating posts.
+pub const SCOPE_POST: &str = "post";
+/// Allows reading the notifications of the bot.
+pub const SCOPE_READ: &str = "read";
+pub const SCOPES: &[&str] = &[SCOPE_POST, SCOPE_READ];
+
+/// API tokens start with this prefix, which tells them apart from the JWTs in the same header.
+pub const TOKEN_PREFIX: &str = "bot_";
+const TOKEN_BYTES: usize = 32;
+
+const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_POST_BURST: i32 = 100;
+const MAX_POSTS_PER_MINUTE: i32 = 600;
+
+/// The time the token was last used is only updated this often, not on every request.
+const LAST_USED_INTERVAL_SECONDS: i64 = 60;
+
+#[derive(Insertable)]
+#[table_name = "api_tokens"]
+pub struct NewApiToken {
+    pub user_uuid: String,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub post_burst: Option<i32>,
+    pub posts_per_minute: Option<i32>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A long-lived token of a bot account. Only the hash of the token is stored, the token itself
+/// is shown once when it is created. Without its own post limits, the configured ones are used.
+#[derive(Identifiable, Queryable, Serialize, Clone, Debug)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_uuid: String,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<String>,
+    pub post_burst: Option<i32>,
+    pub posts_per_minute: Option<i32>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl ApiToken {
+    fn generate() -> String {
+        let bytes: [u8; TOKEN_BYTES] = thread_rng().gen();
+        format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+    }
+
+    /// Returns a random version 4 UUID for a new bot account.
+    fn generate_uuid() -> String {
+        let mut bytes: [u8; 16] = thread_rng().gen();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = hex::encode(bytes);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        )
+    }
+
+    fn validate_limit(value: Option<i32>, max: i32, field: &str) -> Result<(), String> {
+        match value {
+            Some(value) if value < 1 || value > max => {
+                Err(format!("{} must be between 1 and {}", field, max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_api_token(token: &str) -> bool {
+        token.starts_with(TOKEN_PREFIX)
+    }
+
+    /// Returns the hex encoded SHA-256 of the token, as stored in the database.
+    pub fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    /// Returns the new token along with the plain token to give to the bot. Passing a token of
+    /// an existing bot creates another token for the same account, e.g. to rotate it. Tokens can
+    /// only be created for bot accounts, never for the UUID of a user.
+    pub fn new(
+        name: &str,
+        scopes: Vec<String>,
+        post_burst: Option<i32>,
+        posts_per_minute: Option<i32>,
+        bot: Option<&ApiToken>,
+        created_by: &str,
+    ) -> Result<(NewApiToken, String), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(String::from("Name is required"));
+        }
+
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "Name must be at most {} characters long",
+                MAX_NAME_LENGTH
+            ));
+        }
+
+        if scopes.is_empty() {
+            return Err(String::from("At least one scope is required"));
+        }
+
+        if let Some(scope) = scopes
+            .iter()
+            .find(|scope| !SCOPES.contains(&scope.as_str()))
+        {
+            return Err(format!("Unknown scope: {}", scope));
+        }
+
+        ApiToken::validate_limit(post_burst, MAX_POST_BURST, "Post burst")?;
+        ApiToken::validate_limit(posts_per_minute, MAX_POSTS_PER_MINUTE, "Posts per minute")?;
+
+        let user_uuid = match bot {
+            Some(bot) => bot.user_uuid.clone(),
+            None => ApiToken::generate_uuid(),
+        };
+
+        let token = ApiToken::generate();
+        let new_token = NewApiToken {
+            user_uuid,
+            name: name.to_string(),
+            token_hash: ApiToken::hash(&token),
+            scopes,
+            post_burst,
+            posts_per_minute,
+            created_by: created_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        };
+
+        Ok((new_token, token))
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|item| item == scope)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Returns true if the stored time the token was last used is too old to keep.
+    pub fn needs_last_used_update(&self, now: NaiveDateTime) -> bool {
+        match self.last_used_at {
+            Some(last_used_at) => {
+                now - last_used_at >= Duration::seconds(LAST_USED_INTERVAL_SECONDS)
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scopes(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().copied().map(String::from).collect()
+    }
+
+    fn saved(new_token: NewApiToken) -> ApiToken {
+        ApiToken {
+            id: 1,
+            user_uuid: new_token.user_uuid,
+            name: new_token.name,
+            token_hash: new_token.token_hash,
+            scopes: new_token.scopes,
+            post_burst: new_token.post_burst,
+            posts_per_minute: new_token.posts_per_minute,
+            created_by: new_token.created_by,
+            created_at: new_token.created_at,
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    fn create(scopes: Vec<String>) -> Result<(NewApiToken, String), String> {
+        ApiToken::new("bot", scopes, None, None, None, "admin")
+    }
+
+    #[test]
+    fn new_token_is_stored_hashed() {
+        let (new_token, token) = create(scopes(&[SCOPE_POST])).unwrap();
+        assert!(ApiToken::is_api_token(&token));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_BYTES * 2);
+        assert_eq!(new_token.token_hash, ApiToken::hash(&token));
+        assert_eq!(new_token.token_hash.len(), 64);
+        assert_ne!(new_token.token_hash, token);
+    }
+
+    #[test]
+    fn new_tokens_are_different() {
+        let (first, first_token) = create(scopes(&[SCOPE_POST])).unwrap();
+        let (second, second_token) = create(scopes(&[SCOPE_POST])).unwrap();
+        assert_ne!(first_token, second_token);
+        assert_ne!(first.user_uuid, second.user_uuid);
+    }
+
+    #[test]
+    fn new_token_generates_uuid() {
+        let (new_token, _) = create(scopes(&[SCOPE_POST])).unwrap();
+        let uuid = new_token.user_uuid;
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+    }
+
+    #[test]
+    fn new_token_keeps_bot_uuid() {
+        let (first, _) = create(scopes(&[SCOPE_POST])).unwrap();
+        let bot = saved(first);
+        let (second, _) = ApiToken::new(
+            "bot",
+            scopes(&[SCOPE_READ]),
+            None,
+            None,
+            Some(&bot),
+            "admin",
+        )
+        .unwrap();
+        assert_eq!(second.user_uuid, bot.user_uuid);
+    }
+
+    #[test]
+    fn new_token_validates_input() {
+        assert!(ApiToken::new("  ", scopes(&[SCOPE_POST]), None, None, None, "admin").is_err());
+        assert!(create(vec![]).is_err());
+        assert!(create(scopes(&[SCOPE_POST, "react"])).is_err());
+        assert!(ApiToken::new("bot", scopes(&[SCOPE_POST]), Some(0), None, None, "admin").is_err());
+        assert!(
+            ApiToken::new("bot", scopes(&[SCOPE_POST]), None, Some(601), None, "admin").is_err()
+        );
+        assert!(ApiToken::new(
+            "bot",
+            scopes(&[SCOPE_POST]),
+            Some(5),
+            Some(10),
+            None,
+            "admin"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn has_scope() {
+        let (new_token, _) = create(scopes(&[SCOPE_READ])).unwrap();
+        let token = saved(new_token);
+        assert!(token.has_scope(SCOPE_READ));
+        assert!(!token.has_scope(SCOPE_POST));
+        assert!(!token.is_revoked());
+    }
+
+    #[test]
+    fn needs_last_used_update() {
+        let (new_token, _) = create(scopes(&[SCOPE_READ])).unwrap();
+        let mut token = saved(new_token);
+        let now = NaiveDateTime::from_timestamp(1000, 0);
+        assert!(token.needs_last_used_update(now));
+
+        token.last_used_at = Some(now);
+        assert!(!token.needs_last_used_update(now + Duration::seconds(59)));
+        assert!(token.needs_last_used_update(now + Duration::seconds(60)));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::schema::audit_log;
+use chrono::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+pub const ACTION_POST_HIDE: &str = "post.hide";
+pub const ACTION_FILE_REMOVE: &str = "file.remove";
+pub const ACTION_FILE_TAKE_DOWN: &str = "file.take_down";
+pub const ACTION_USER_RESTRICT: &str = "user.restrict";
+pub const ACTION_USER_UNRESTRICT: &str = "user.unrestrict";
+pub const ACTION_REPORT_RESOLVE: &str = "report.resolve";
+pub const ACTION_FLAG_DISMISS: &str = "flag.dismiss";
+pub const ACTION_EMOJI_CREATE: &str = "emoji.create";
+pub const ACTION_EMOJI_DELETE: &str = "emoji.delete";
+pub const ACTION_WORD_FILTER_CREATE: &str = "word_filter.create";
+pub const ACTION_WORD_FILTER_UPDATE: &str = "word_filter.update";
+pub const ACTION_WORD_FILTER_DELETE: &str = "word_filter.delete";
+pub const ACTION_FILE_BLOCK: &str = "file.block";
+pub const ACTION_FILE_UNBLOCK: &str = "file.unblock";
+pub const ACTION_SETTINGS_UPDATE: &str = "settings.update";
+pub const ACTION_ROOM_CREATE: &str = "room.create";
+pub const ACTION_ROOM_UPDATE: &str = "room.update";
+pub const ACTION_WEBHOOK_CREATE: &str = "webhook.create";
+pub const ACTION_WEBHOOK_UPDATE: &str = "webhook.update";
+pub const ACTION_WEBHOOK_DELETE: &str = "webhook.delete";
+pub const ACTION_API_TOKEN_CREATE: &str = "api_token.create";
+pub const ACTION_API_TOKEN_REVOKE: &str = "api_token.revoke";
+
+#[derive(Insertable)]
+#[table_name = "audit_log"]
+pub struct NewAuditLogEntry {
+    pub actor_uuid: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+#[table_name = "audit_log"]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub actor_uuid: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+impl AuditLogEntry {
+    pub fn new<T: Serialize>(
+        actor_uuid: &str,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> NewAuditLogEntry {
+        let to_json = |value: &T| serde_json::to_value(value).unwrap_or(Value::Null);
+
+        NewAuditLogEntry {
+            actor_uuid: actor_uuid.to_string(),
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id: target_id.to_string(),
+            before: before.map(to_json),
+            after: after.map(to_json),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        }
+    }
+}
+
+/// Conditions for listing the audit log, all optional.
+#[derive(Default)]
+pub struct AuditLogFilter {
+    pub actor_uuid: Option<String>,
+    pub action: Option<String>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub before_id: Option<i32>,
+}
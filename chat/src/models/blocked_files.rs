@@ -0,0 +1,113 @@
+use crate::schema::blocked_files;
+use chrono::prelude::*;
+use serde::Serialize;
+
+pub const MAX_REASON_LENGTH: usize = 1000;
+
+/// Maximum number of differing bits for two perceptual hashes to be considered the same image.
+pub const MAX_PHASH_DISTANCE: u32 = 6;
+
+#[derive(Insertable)]
+#[table_name = "blocked_files"]
+pub struct NewBlockedFile {
+    pub md5: String,
+    pub phash: Option<i64>,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+pub struct BlockedFile {
+    pub id: i32,
+    pub md5: String,
+    pub phash: Option<i64>,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl BlockedFile {
+    pub fn new(
+        md5: &str,
+        phash: Option<i64>,
+        reason: &str,
+        created_by: &str,
+    ) -> Result<NewBlockedFile, String> {
+        if reason.chars().count() > MAX_REASON_LENGTH {
+            return Err(format!(
+                "Reason must be at most {} characters long",
+                MAX_REASON_LENGTH
+            ));
+        }
+
+        Ok(NewBlockedFile {
+            md5: md5.to_string(),
+            phash,
+            reason: reason.trim().to_string(),
+            created_by: created_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+
+    pub fn phash_distance(a: i64, b: i64) -> u32 {
+        (a ^ b).count_ones()
+    }
+
+    /// Returns whether the perceptual hash is close to any of the blocked ones.
+    pub fn is_phash_blocked(blocked_phashes: &[i64], phash: i64) -> bool {
+        blocked_phashes
+            .iter()
+            .any(|&blocked| BlockedFile::phash_distance(blocked, phash) <= MAX_PHASH_DISTANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::files::File;
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    fn gradient(offset: u8) -> DynamicImage {
+        let image = ImageBuffer::from_fn(64, 64, |x, y| {
+            let value = ((x * 3 + y) % 200) as u8;
+            Luma([value.saturating_add(offset)])
+        });
+        DynamicImage::ImageLuma8(image)
+    }
+
+    #[test]
+    fn phash_distance() {
+        assert_eq!(BlockedFile::phash_distance(0, 0), 0);
+        assert_eq!(BlockedFile::phash_distance(0b1011, 0b0001), 2);
+        assert_eq!(BlockedFile::phash_distance(0, -1), 64);
+    }
+
+    #[test]
+    fn is_phash_blocked() {
+        let blocked_phashes = vec![0b1111, -1];
+        assert!(BlockedFile::is_phash_blocked(&blocked_phashes, 0b0111));
+        assert!(BlockedFile::is_phash_blocked(&blocked_phashes, !0b1));
+        assert!(!BlockedFile::is_phash_blocked(
+            &blocked_phashes,
+            0b1111 << 8
+        ));
+        assert!(!BlockedFile::is_phash_blocked(&[], 0));
+    }
+
+    #[test]
+    fn perceptual_hash_survives_small_changes() {
+        let original = File::get_perceptual_hash(&gradient(0));
+        let brightened = File::get_perceptual_hash(&gradient(10));
+        let resized = File::get_perceptual_hash(&gradient(0).thumbnail(32, 32));
+        assert!(BlockedFile::phash_distance(original, brightened) <= MAX_PHASH_DISTANCE);
+        assert!(BlockedFile::phash_distance(original, resized) <= MAX_PHASH_DISTANCE);
+    }
+
+    #[test]
+    fn perceptual_hash_differs_for_different_images() {
+        let original = File::get_perceptual_hash(&gradient(0));
+        let flipped = File::get_perceptual_hash(&gradient(0).fliph());
+        assert!(BlockedFile::phash_distance(original, flipped) > MAX_PHASH_DISTANCE);
+    }
+}
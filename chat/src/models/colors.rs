@@ -0,0 +1,575 @@
+use nom::branch::alt;
+use nom::bytes::complete::{tag_no_case, take_while_m_n};
+use nom::character::complete::{alpha1, char, digit1, space0};
+use nom::combinator::{all_consuming, map, map_opt, map_res, opt, recognize};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::IResult;
+
+/// CSS named colors, sorted by name so they can be binary searched.
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f64,
+}
+
+impl Rgba {
+    pub fn from_u32(value: u32) -> Rgba {
+        Rgba {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+            a: 1.0,
+        }
+    }
+
+    pub fn from_named(name: &str) -> Option<Rgba> {
+        let name = name.to_ascii_lowercase();
+        NAMED_COLORS
+            .binary_search_by(|&(item, _)| item.cmp(name.as_str()))
+            .ok()
+            .map(|index| Rgba::from_u32(NAMED_COLORS[index].1))
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Rgba> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()?;
+
+        let (r, g, b, a) = match digits.len() {
+            3 => (digits[0] * 17, digits[1] * 17, digits[2] * 17, 255),
+            4 => (
+                digits[0] * 17,
+                digits[1] * 17,
+                digits[2] * 17,
+                digits[3] * 17,
+            ),
+            6 => (
+                digits[0] * 16 + digits[1],
+                digits[2] * 16 + digits[3],
+                digits[4] * 16 + digits[5],
+                255,
+            ),
+            8 => (
+                digits[0] * 16 + digits[1],
+                digits[2] * 16 + digits[3],
+                digits[4] * 16 + digits[5],
+                digits[6] * 16 + digits[7],
+            ),
+            _ => return None,
+        };
+
+        Some(Rgba {
+            r,
+            g,
+            b,
+            a: a as f64 / 255.0,
+        })
+    }
+
+    pub fn from_hsla(hue: f64, saturation: f64, lightness: f64, a: f64) -> Rgba {
+        let hue = (hue % 360.0) / 360.0;
+        let q = if lightness < 0.5 {
+            lightness * (1.0 + saturation)
+        } else {
+            lightness + saturation - lightness * saturation
+        };
+        let p = 2.0 * lightness - q;
+
+        let channel = |t: f64| {
+            let t = if t < 0.0 {
+                t + 1.0
+            } else if t > 1.0 {
+                t - 1.0
+            } else {
+                t
+            };
+
+            let value = if t < 1.0 / 6.0 {
+                p + (q - p) * 6.0 * t
+            } else if t < 1.0 / 2.0 {
+                q
+            } else if t < 2.0 / 3.0 {
+                p + (q - p) * (2.0 / 3.0 - t) * 6.0
+            } else {
+                p
+            };
+
+            (value * 255.0).round() as u8
+        };
+
+        Rgba {
+            r: channel(hue + 1.0 / 3.0),
+            g: channel(hue),
+            b: channel(hue - 1.0 / 3.0),
+            a,
+        }
+    }
+
+    /// Parses any color syntax accepted by the `[color]` tag.
+    pub fn parse(input: &str) -> Option<Rgba> {
+        all_consuming(color)(input).ok().map(|(_, color)| color)
+    }
+
+    /// Composites this color over an opaque background.
+    pub fn blend(&self, background: &Rgba) -> Rgba {
+        let mix = |fg: u8, bg: u8| (fg as f64 * self.a + bg as f64 * (1.0 - self.a)).round() as u8;
+
+        Rgba {
+            r: mix(self.r, background.r),
+            g: mix(self.g, background.g),
+            b: mix(self.b, background.b),
+            a: 1.0,
+        }
+    }
+
+    /// Linear interpolation towards another opaque color.
+    fn mix(&self, other: &Rgba, t: f64) -> Rgba {
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+
+        Rgba {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: 1.0,
+        }
+    }
+
+    /// Relative luminance as defined by WCAG 2.0.
+    pub fn luminance(&self) -> f64 {
+        let channel = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    pub fn contrast_ratio(&self, other: &Rgba) -> f64 {
+        let (l1, l2) = (self.luminance(), other.luminance());
+        let (lighter, darker) = if l1 > l2 { (l1, l2) } else { (l2, l1) };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+
+    pub fn to_hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorContrastMode {
+    /// Drop the color and render the text with the default color.
+    Reject,
+    /// Darken or lighten the color until it is readable.
+    Clamp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorContrast {
+    pub background: Rgba,
+    pub min_ratio: f64,
+    pub mode: ColorContrastMode,
+}
+
+impl ColorContrast {
+    /// Returns the color to use for the `[color]` tag, or `None` if it should be ignored.
+    pub fn apply(&self, color: &str) -> Option<String> {
+        let rgba = Rgba::parse(color)?;
+        let effective = rgba.blend(&self.background);
+        if effective.contrast_ratio(&self.background) >= self.min_ratio {
+            return Some(color.to_string());
+        }
+
+        match self.mode {
+            ColorContrastMode::Reject => None,
+            ColorContrastMode::Clamp => Some(self.clamp(&effective).to_hex()),
+        }
+    }
+
+    fn clamp(&self, color: &Rgba) -> Rgba {
+        let black = Rgba::from_u32(0x000000);
+        let white = Rgba::from_u32(0xffffff);
+        let target =
+            if black.contrast_ratio(&self.background) > white.contrast_ratio(&self.background) {
+                black
+            } else {
+                white
+            };
+
+        // Find the smallest step towards the target that is readable.
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..16 {
+            let middle = (low + high) / 2.0;
+            if color.mix(&target, middle).contrast_ratio(&self.background) >= self.min_ratio {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        color.mix(&target, high)
+    }
+}
+
+fn number(input: &str) -> IResult<&str, f64> {
+    map_res(
+        recognize(pair(digit1, opt(pair(char('.'), digit1)))),
+        |s: &str| s.parse::<f64>(),
+    )(input)
+}
+
+fn percentage(input: &str) -> IResult<&str, f64> {
+    map_opt(terminated(number, char('%')), |value| {
+        if value <= 100.0 {
+            Some(value / 100.0)
+        } else {
+            None
+        }
+    })(input)
+}
+
+fn separator(input: &str) -> IResult<&str, char> {
+    delimited(space0, char(','), space0)(input)
+}
+
+fn channel(input: &str) -> IResult<&str, u8> {
+    alt((
+        map(percentage, |value| (value * 255.0).round() as u8),
+        map_opt(number, |value| {
+            if value <= 255.0 {
+                Some(value.round() as u8)
+            } else {
+                None
+            }
+        }),
+    ))(input)
+}
+
+fn alpha(input: &str) -> IResult<&str, f64> {
+    alt((
+        percentage,
+        map_opt(
+            number,
+            |value| if value <= 1.0 { Some(value) } else { None },
+        ),
+    ))(input)
+}
+
+fn hex(input: &str) -> IResult<&str, Rgba> {
+    map_opt(
+        preceded(
+            char('#'),
+            take_while_m_n(3, 8, |c: char| c.is_ascii_hexdigit()),
+        ),
+        Rgba::from_hex,
+    )(input)
+}
+
+fn rgb_function(input: &str) -> IResult<&str, Rgba> {
+    map(
+        tuple((
+            alt((tag_no_case("rgba("), tag_no_case("rgb("))),
+            space0,
+            channel,
+            separator,
+            channel,
+            separator,
+            channel,
+            opt(preceded(separator, alpha)),
+            space0,
+            char(')'),
+        )),
+        |(_, _, r, _, g, _, b, a, _, _)| Rgba {
+            r,
+            g,
+            b,
+            a: a.unwrap_or(1.0),
+        },
+    )(input)
+}
+
+fn hsl_function(input: &str) -> IResult<&str, Rgba> {
+    map(
+        tuple((
+            alt((tag_no_case("hsla("), tag_no_case("hsl("))),
+            space0,
+            terminated(number, opt(tag_no_case("deg"))),
+            separator,
+            percentage,
+            separator,
+            percentage,
+            opt(preceded(separator, alpha)),
+            space0,
+            char(')'),
+        )),
+        |(_, _, hue, _, saturation, _, lightness, a, _, _)| {
+            Rgba::from_hsla(hue, saturation, lightness, a.unwrap_or(1.0))
+        },
+    )(input)
+}
+
+fn named(input: &str) -> IResult<&str, Rgba> {
+    map_opt(alpha1, Rgba::from_named)(input)
+}
+
+/// Parses a CSS color: hexadecimal notation, `rgb()`/`rgba()`, `hsl()`/`hsla()` or a named color.
+pub fn color(input: &str) -> IResult<&str, Rgba> {
+    alt((hex, rgb_function, hsl_function, named))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorContrast, ColorContrastMode, Rgba, NAMED_COLORS};
+
+    #[test]
+    fn named_colors_are_sorted() {
+        for pair in NAMED_COLORS.windows(2) {
+            assert!(pair[0].0 < pair[1].0, "{} < {}", pair[0].0, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn parse_named() {
+        assert_eq!(Some(Rgba::from_u32(0x663399)), Rgba::parse("RebeccaPurple"));
+        assert_eq!(None, Rgba::parse("notacolor"));
+    }
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(Some(Rgba::from_u32(0xaabbcc)), Rgba::parse("#ABC"));
+        assert_eq!(Some(Rgba::from_u32(0xabcdef)), Rgba::parse("#abcdef"));
+        assert_eq!(None, Rgba::parse("#abcde"));
+    }
+
+    #[test]
+    fn parse_rgb() {
+        assert_eq!(
+            Some(Rgba::from_u32(0xff8000)),
+            Rgba::parse("rgb(255, 128, 0)")
+        );
+        assert_eq!(
+            Some(Rgba::from_u32(0xff0000)),
+            Rgba::parse("rgb(100%,0%,0%)")
+        );
+        assert_eq!(
+            Some(Rgba {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 0.5
+            }),
+            Rgba::parse("rgba(0, 0, 255, 0.5)")
+        );
+        assert_eq!(None, Rgba::parse("rgb(256, 0, 0)"));
+    }
+
+    #[test]
+    fn parse_hsl() {
+        assert_eq!(
+            Some(Rgba::from_u32(0xff0000)),
+            Rgba::parse("hsl(0, 100%, 50%)")
+        );
+        assert_eq!(
+            Some(Rgba::from_u32(0x00ff00)),
+            Rgba::parse("hsl(120deg, 100%, 50%)")
+        );
+        assert_eq!(
+            Some(Rgba::from_u32(0x808080)),
+            Rgba::parse("hsl(0, 0%, 50%)")
+        );
+        assert_eq!(None, Rgba::parse("hsl(0, 101%, 50%)"));
+    }
+
+    #[test]
+    fn contrast_ratio() {
+        let black = Rgba::from_u32(0x000000);
+        let white = Rgba::from_u32(0xffffff);
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 0.01);
+        assert!((white.contrast_ratio(&white) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn contrast_reject() {
+        let contrast = ColorContrast {
+            background: Rgba::from_u32(0xffffff),
+            min_ratio: 3.0,
+            mode: ColorContrastMode::Reject,
+        };
+
+        assert_eq!(Some("navy".to_string()), contrast.apply("navy"));
+        assert_eq!(None, contrast.apply("yellow"));
+        assert_eq!(None, contrast.apply("rgba(0, 0, 0, 0.1)"));
+    }
+
+    #[test]
+    fn contrast_clamp() {
+        let background = Rgba::from_u32(0xffffff);
+        let contrast = ColorContrast {
+            background,
+            min_ratio: 3.0,
+            mode: ColorContrastMode::Clamp,
+        };
+
+        let clamped = contrast.apply("yellow").unwrap();
+        let clamped = Rgba::parse(&clamped).unwrap();
+        assert!(clamped.contrast_ratio(&background) >= 3.0);
+        assert!(clamped.r == clamped.g && clamped.b == 0);
+    }
+}
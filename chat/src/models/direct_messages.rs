@@ -0,0 +1,178 @@
+use super::posts::{FieldError, Post, PostLimits};
+use crate::schema::direct_messages;
+use chrono::prelude::*;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+#[derive(Insertable)]
+#[table_name = "direct_messages"]
+pub struct NewDirectMessage {
+    pub sender_uuid: String,
+    pub recipient_uuid: String,
+    pub message: String,
+    pub created_at: NaiveDateTime,
+    /// Messages of quarantined users are only shown to them.
+    pub quarantined: bool,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+pub struct DirectMessage {
+    pub id: i32,
+    pub sender_uuid: String,
+    pub recipient_uuid: String,
+    pub message: String,
+    pub created_at: NaiveDateTime,
+    pub read_at: Option<NaiveDateTime>,
+    pub quarantined: bool,
+}
+
+/// The latest message exchanged with another user, seen from one of the participants.
+pub struct Conversation {
+    pub user_uuid: String,
+    pub last_message: DirectMessage,
+    pub unread_count: i64,
+}
+
+impl DirectMessage {
+    pub fn new(
+        sender_uuid: &str,
+        recipient_uuid: &str,
+        message: &str,
+        file_count: usize,
+        limits: &PostLimits,
+    ) -> Result<NewDirectMessage, Vec<FieldError>> {
+        if recipient_uuid.len() != 36 {
+            return Err(vec![FieldError::new(
+                "recipient",
+                "invalid",
+                String::from("Invalid user UUID"),
+            )]);
+        }
+
+        if sender_uuid == recipient_uuid {
+            return Err(vec![FieldError::new(
+                "recipient",
+                "invalid",
+                String::from("You can't send messages to yourself"),
+            )]);
+        }
+
+        Post::validate(message, file_count, limits)?;
+
+        Ok(NewDirectMessage {
+            sender_uuid: sender_uuid.to_string(),
+            recipient_uuid: recipient_uuid.to_string(),
+            message: message.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+            quarantined: false,
+        })
+    }
+
+    /// Returns the participant that isn't the given user.
+    pub fn get_other_uuid(&self, uuid: &str) -> &str {
+        if self.sender_uuid == uuid {
+            &self.recipient_uuid
+        } else {
+            &self.sender_uuid
+        }
+    }
+}
+
+impl Conversation {
+    /// Groups the messages by the other participant, keeping the latest message of each, and
+    /// orders the conversations from the most recent one.
+    pub fn from_messages(
+        uuid: &str,
+        messages: Vec<DirectMessage>,
+        unread_counts: &HashMap<String, i64>,
+    ) -> Vec<Conversation> {
+        let mut latest: HashMap<String, DirectMessage> = HashMap::new();
+        for message in messages {
+            let other_uuid = message.get_other_uuid(uuid).to_string();
+            match latest.get(&other_uuid) {
+                Some(existing) if existing.id > message.id => {}
+                _ => {
+                    latest.insert(other_uuid, message);
+                }
+            }
+        }
+
+        let mut conversations: Vec<Conversation> = latest
+            .into_iter()
+            .map(|(user_uuid, last_message)| Conversation {
+                unread_count: unread_counts.get(&user_uuid).cloned().unwrap_or(0),
+                user_uuid,
+                last_message,
+            })
+            .collect();
+
+        conversations.sort_unstable_by_key(|conversation| Reverse(conversation.last_message.id));
+        conversations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "00000000-0000-0000-0000-00000000000a";
+    const B: &str = "00000000-0000-0000-0000-00000000000b";
+
+    fn message(id: i32, sender_uuid: &str, recipient_uuid: &str) -> DirectMessage {
+        DirectMessage {
+            id,
+            sender_uuid: sender_uuid.to_string(),
+            recipient_uuid: recipient_uuid.to_string(),
+            message: String::from("Hello"),
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            read_at: None,
+            quarantined: false,
+        }
+    }
+
+    #[test]
+    fn new_rejects_invalid_recipient() {
+        let limits = PostLimits::default();
+        let errors = DirectMessage::new(A, "b", "Hello", 0, &limits)
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].field, "recipient");
+    }
+
+    #[test]
+    fn new_rejects_messages_to_self() {
+        let limits = PostLimits::default();
+        let errors = DirectMessage::new(A, A, "Hello", 0, &limits).err().unwrap();
+        assert_eq!(errors[0].field, "recipient");
+        assert!(DirectMessage::new(A, B, "Hello", 0, &limits).is_ok());
+    }
+
+    #[test]
+    fn new_validates_message() {
+        let limits = PostLimits::default();
+        let errors = DirectMessage::new(A, B, " ", 0, &limits).err().unwrap();
+        assert_eq!(errors[0].code, "empty");
+    }
+
+    #[test]
+    fn conversations_keep_latest_message_per_user() {
+        let messages = vec![
+            message(1, "a", "b"),
+            message(4, "b", "a"),
+            message(2, "c", "a"),
+            message(3, "a", "c"),
+        ];
+        let mut unread_counts = HashMap::new();
+        unread_counts.insert(String::from("b"), 2);
+
+        let conversations = Conversation::from_messages("a", messages, &unread_counts);
+        assert_eq!(conversations.len(), 2);
+        assert_eq!(conversations[0].user_uuid, "b");
+        assert_eq!(conversations[0].last_message.id, 4);
+        assert_eq!(conversations[0].unread_count, 2);
+        assert_eq!(conversations[1].user_uuid, "c");
+        assert_eq!(conversations[1].last_message.id, 3);
+        assert_eq!(conversations[1].unread_count, 0);
+    }
+}
@@ -0,0 +1,56 @@
+use super::files::File;
+use crate::schema::custom_emoji;
+use chrono::prelude::*;
+use serde::Serialize;
+
+pub const MAX_SHORTCODE_LENGTH: usize = 32;
+
+#[derive(Insertable)]
+#[table_name = "custom_emoji"]
+pub struct NewCustomEmoji {
+    pub shortcode: String,
+    pub file_id: i32,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Clone)]
+#[belongs_to(File)]
+#[table_name = "custom_emoji"]
+pub struct CustomEmoji {
+    pub id: i32,
+    pub shortcode: String,
+    pub file_id: i32,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl CustomEmoji {
+    pub fn is_shortcode_char(c: char) -> bool {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-' || c == '+'
+    }
+
+    pub fn new(shortcode: &str, file: &File, created_by: &str) -> Result<NewCustomEmoji, String> {
+        if shortcode.is_empty() || shortcode.len() > MAX_SHORTCODE_LENGTH {
+            return Err(format!(
+                "Shortcode must be from 1 to {} characters long",
+                MAX_SHORTCODE_LENGTH
+            ));
+        }
+
+        if !shortcode.chars().all(CustomEmoji::is_shortcode_char) {
+            return Err(String::from("Shortcode contains invalid characters"));
+        }
+
+        if !file.mimetype.starts_with("image/") {
+            return Err(String::from("Emoji must be an image"));
+        }
+
+        Ok(NewCustomEmoji {
+            shortcode: shortcode.to_string(),
+            file_id: file.id,
+            created_by: created_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use crate::schema::events;
+use chrono::prelude::*;
+use serde_json::Value;
+
+/// Channel notified when events are created.
+pub const EVENTS_CHANNEL: &str = "chat_events";
+
+#[derive(Insertable)]
+#[table_name = "events"]
+pub struct NewEvent {
+    pub audience: Value,
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+    pub quarantined: bool,
+}
+
+/// An event shared between the web server instances. The audience and the payload are defined
+/// by the web server, they are only stored here. Quarantined events must not leave the audience.
+#[derive(Queryable, Debug, Clone)]
+pub struct Event {
+    pub id: i64,
+    pub audience: Value,
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+    pub quarantined: bool,
+}
+
+impl Event {
+    pub fn new(audience: Value, payload: &str, quarantined: bool) -> NewEvent {
+        NewEvent {
+            audience,
+            payload: payload.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+            quarantined,
+        }
+    }
+}
@@ -1,7 +1,10 @@
+use super::direct_messages::DirectMessage;
 use super::posts::Post;
 use crate::schema::{files, user_favorite_files};
 use chrono::prelude::*;
+use image::imageops::{self, FilterType};
 use image::io::Reader;
+use image::DynamicImage;
 use infer::Infer;
 use serde::Serialize;
 use std::fs;
@@ -20,14 +23,16 @@ pub struct NewFile {
     pub mimetype: String,
     pub extension: String,
     pub created_at: NaiveDateTime,
-    pub post_id: i32,
+    pub post_id: Option<i32>,
     pub width: Option<i32>,
     pub height: Option<i32>,
     pub length: Option<i32>,
+    pub direct_message_id: Option<i32>,
 }
 
 #[derive(Identifiable, Queryable, Associations, Serialize, Clone)]
 #[belongs_to(Post)]
+#[belongs_to(DirectMessage)]
 pub struct File {
     pub id: i32,
     pub md5: String,
@@ -36,10 +41,12 @@ pub struct File {
     pub mimetype: String,
     pub extension: String,
     pub created_at: NaiveDateTime,
-    pub post_id: i32,
+    pub post_id: Option<i32>,
     pub width: Option<i32>,
     pub height: Option<i32>,
     pub length: Option<i32>,
+    pub removed: bool,
+    pub direct_message_id: Option<i32>,
 }
 
 impl File {
@@ -134,18 +141,127 @@ impl File {
         }
     }
 
-    pub fn new(file_name: Option<String>, path: PathBuf, post_id: i32) -> Result<NewFile, String> {
-        let name = file_name.unwrap_or(String::from(""));
+    pub fn get_md5(path: &PathBuf) -> Result<String, String> {
+        match fs::read(path) {
+            Ok(content) => Ok(format!("{:x}", md5::compute(content))),
+            Err(_) => Err(format!("Can't read file: {}", path.to_string_lossy())),
+        }
+    }
+
+    /// Computes a 64-bit difference hash of the image, which stays the same or close to it when
+    /// the image is re-encoded, resized or slightly recolored.
+    pub fn get_perceptual_hash(image: &DynamicImage) -> i64 {
+        let pixels = imageops::grayscale(&image.resize_exact(9, 8, FilterType::Triangle));
+
+        let mut hash: u64 = 0;
+        for y in 0..8 {
+            for x in 0..8 {
+                hash <<= 1;
+                if pixels.get_pixel(x, y)[0] < pixels.get_pixel(x + 1, y)[0] {
+                    hash |= 1;
+                }
+            }
+        }
+
+        hash as i64
+    }
+
+    /// Returns the perceptual hash of the file, if it is an image.
+    pub fn get_perceptual_hash_from_path(path: &Path) -> Option<i64> {
+        let reader = Reader::open(path).ok()?.with_guessed_format().ok()?;
+        let image = reader.decode().ok()?;
+
+        Some(File::get_perceptual_hash(&image))
+    }
+
+    pub fn get_source_path(&self) -> PathBuf {
+        let filename = format!("{}.{}", self.md5, self.extension);
+        let src_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/src");
+        Path::new(src_dir).join(filename)
+    }
+
+    /// Deletes the source and all generated thumbnails of the file from disk. Every removal is
+    /// attempted, the errors of the ones which failed are returned.
+    pub fn remove_media(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let src_path = self.get_source_path();
+        if src_path.exists() {
+            if let Err(msg) = fs::remove_file(&src_path) {
+                errors.push(format!("Can't remove file: {}", msg));
+            }
+        }
+
+        let thumb_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/thumb");
+        let thumb_prefix = format!("{}_", self.md5);
+        match fs::read_dir(thumb_dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(msg) => {
+                            errors.push(format!("Can't read thumbnails: {}", msg));
+                            continue;
+                        }
+                    };
 
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    if file_name.starts_with(&thumb_prefix) {
+                        if let Err(msg) = fs::remove_file(entry.path()) {
+                            errors.push(format!("Can't remove thumbnail {}: {}", file_name, msg));
+                        }
+                    }
+                }
+            }
+            Err(msg) => errors.push(format!("Can't read thumbnails: {}", msg)),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns the entry shown in place of a file that has been taken down.
+    pub fn to_placeholder(&self) -> File {
+        File {
+            id: self.id,
+            md5: String::new(),
+            size: 0,
+            name: String::new(),
+            mimetype: String::new(),
+            extension: String::new(),
+            created_at: self.created_at,
+            post_id: self.post_id,
+            width: None,
+            height: None,
+            length: None,
+            removed: true,
+            direct_message_id: self.direct_message_id,
+        }
+    }
+
+    /// Returns the extension and mimetype of the file, detected from its content.
+    pub fn get_file_type(path: &Path) -> Result<(String, String), String> {
         let info = Infer::new();
-        let file_type = match info.get_from_path(&path) {
-            Ok(Some(file_type)) => file_type,
-            Ok(None) => return Err(format!("Can't determine file type")),
-            Err(e) => return Err(format!("Can't determine file type: {}", e)),
-        };
+        match info.get_from_path(path) {
+            Ok(Some(file_type)) => Ok((file_type.ext, file_type.mime)),
+            Ok(None) => Err(format!("Can't determine file type")),
+            Err(e) => Err(format!("Can't determine file type: {}", e)),
+        }
+    }
 
-        let extension = file_type.ext;
-        let mime_type = file_type.mime;
+    /// Stores the uploaded file, attached to either a post or a direct message. The md5 is the
+    /// one computed on upload, so the file isn't read again for it.
+    fn from_path(
+        file_name: Option<String>,
+        path: PathBuf,
+        md5: String,
+        post_id: Option<i32>,
+        direct_message_id: Option<i32>,
+    ) -> Result<NewFile, String> {
+        let name = file_name.unwrap_or(String::from(""));
+        let (extension, mime_type) = File::get_file_type(&path)?;
 
         let created_at = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
 
@@ -160,15 +276,12 @@ impl File {
             return Err(format!("File is too large: {}", path.to_string_lossy()));
         }
 
-        let content = match fs::read(path.clone()) {
-            Ok(content) => content,
+        let size = match fs::metadata(&path) {
+            Ok(metadata) => metadata.len() as i64,
             Err(_) => return Err(format!("Can't read file: {}", path.to_string_lossy())),
         };
 
-        let size = content.len() as i64;
-        let hash = format!("{:x}", md5::compute(content));
-
-        let filename = format!("{}.{}", hash, extension);
+        let filename = format!("{}.{}", md5, extension);
         let dest_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/src");
         let dest_path = Path::new(dest_dir).join(filename);
         if let Err(msg) = fs::copy(path, dest_path) {
@@ -177,7 +290,7 @@ impl File {
 
         let result = NewFile {
             size,
-            md5: hash,
+            md5,
             name,
             mimetype: mime_type,
             extension,
@@ -186,10 +299,29 @@ impl File {
             width,
             height,
             length,
+            direct_message_id,
         };
 
         Ok(result)
     }
+
+    pub fn new(
+        file_name: Option<String>,
+        path: PathBuf,
+        md5: String,
+        post_id: i32,
+    ) -> Result<NewFile, String> {
+        File::from_path(file_name, path, md5, Some(post_id), None)
+    }
+
+    pub fn new_for_direct_message(
+        file_name: Option<String>,
+        path: PathBuf,
+        md5: String,
+        direct_message_id: i32,
+    ) -> Result<NewFile, String> {
+        File::from_path(file_name, path, md5, None, Some(direct_message_id))
+    }
 }
 
 #[derive(Insertable)]
@@ -1,13 +1,83 @@
+use super::colors::{self, ColorContrast};
+use super::emoji::{CustomEmoji, MAX_SHORTCODE_LENGTH};
 use nom::branch::alt;
-use nom::bytes::complete::{is_a, is_not, tag, tag_no_case};
-use nom::character::complete::{char, digit1, one_of};
+use nom::bytes::complete::{is_a, is_not, tag, take_while, take_while_m_n};
+use nom::character::complete::{alpha1, char, digit1, one_of};
 use nom::combinator::opt;
-use nom::combinator::{map, not, peek, recognize, value};
-use nom::multi::{many0, many_m_n};
+use nom::combinator::{map, map_res, not, peek, recognize, value};
 use nom::sequence::separated_pair;
 use nom::sequence::{delimited, pair, preceded, tuple};
 use nom::IResult;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Custom emoji shortcodes mapped to the md5 of their image file.
+pub type EmojiMap = HashMap<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct MessageParserOptions {
+    /// Tags that are recognized, e.g. `b` or `spoiler`. Other tags are left as plain text.
+    pub enabled_tags: Vec<String>,
+    /// Tags nested deeper than this are left as plain text.
+    pub max_nesting_depth: usize,
+    /// Dice rolls with more dice than this are left as plain text.
+    pub max_dice: u32,
+    /// URL schemes that are turned into links.
+    pub link_schemes: Vec<String>,
+    pub allow_colors: bool,
+    /// Whether clients may replace links with embedded players.
+    pub allow_embeds: bool,
+    /// Colors that are unreadable on the theme background are rejected or clamped.
+    pub color_contrast: Option<ColorContrast>,
+}
+
+impl MessageParserOptions {
+    pub const ALL_TAGS: [&'static str; 10] = [
+        "b",
+        "i",
+        "u",
+        "s",
+        "sup",
+        "sub",
+        "code",
+        "codeblock",
+        "spoiler",
+        "quote",
+    ];
+
+    fn is_tag_enabled(&self, name: &str) -> bool {
+        if name == "color" {
+            self.allow_colors
+        } else {
+            self.enabled_tags.iter().any(|tag| tag == name)
+        }
+    }
+
+    fn is_link_allowed(&self, url: &str) -> bool {
+        let scheme = url.split("://").next().unwrap_or("");
+        self.link_schemes
+            .iter()
+            .any(|item| item.eq_ignore_ascii_case(scheme))
+    }
+}
+
+impl Default for MessageParserOptions {
+    fn default() -> MessageParserOptions {
+        MessageParserOptions {
+            enabled_tags: MessageParserOptions::ALL_TAGS
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            max_nesting_depth: 16,
+            max_dice: 20,
+            link_schemes: vec![String::from("http"), String::from("https")],
+            allow_colors: true,
+            allow_embeds: true,
+            color_contrast: None,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OpeningTag {
@@ -25,6 +95,22 @@ pub enum OpeningTag {
 }
 
 impl OpeningTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpeningTag::Bold => "b",
+            OpeningTag::Italic => "i",
+            OpeningTag::Underline => "u",
+            OpeningTag::Strike => "s",
+            OpeningTag::Superscript => "sup",
+            OpeningTag::Subscript => "sub",
+            OpeningTag::Code => "code",
+            OpeningTag::CodeBlock => "codeblock",
+            OpeningTag::Spoiler => "spoiler",
+            OpeningTag::Color { color: _ } => "color",
+            OpeningTag::Quote => "quote",
+        }
+    }
+
     pub fn is_pair(self: &OpeningTag, closing: &ClosingTag) -> bool {
         match (self, closing) {
             (OpeningTag::Bold, ClosingTag::Bold) => true,
@@ -80,6 +166,24 @@ pub enum ClosingTag {
     Quote,
 }
 
+impl ClosingTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClosingTag::Bold => "b",
+            ClosingTag::Italic => "i",
+            ClosingTag::Underline => "u",
+            ClosingTag::Strike => "s",
+            ClosingTag::Superscript => "sup",
+            ClosingTag::Subscript => "sub",
+            ClosingTag::Code => "code",
+            ClosingTag::CodeBlock => "codeblock",
+            ClosingTag::Spoiler => "spoiler",
+            ClosingTag::Color => "color",
+            ClosingTag::Quote => "quote",
+        }
+    }
+}
+
 impl std::fmt::Display for ClosingTag {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         fmt.write_str(match self {
@@ -106,6 +210,7 @@ pub enum Token {
     RefLink(u32),
     Link(String),
     Dice(u32, u32),
+    Emoji(String),
     OpeningTag(OpeningTag),
     ClosingTag(ClosingTag),
 }
@@ -124,9 +229,10 @@ pub enum Style {
     Spoiler,
     Color { color: String },
     RefLink { id: u32 },
-    Link { url: String },
+    Link { url: String, embed: bool },
     Quote,
     Dice { count: u32, max: u32 },
+    Emoji { shortcode: String, md5: String },
 }
 
 impl Style {
@@ -178,6 +284,10 @@ impl Style {
             _ => None,
         }
     }
+
+    pub fn is_emoji(&self) -> bool {
+        matches!(self, Style::Emoji { .. })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Clone)]
@@ -195,6 +305,10 @@ impl Segment {
         let index = self.tags.iter().position(|tag| tag.is_ref_link());
         index.and_then(|i| self.tags[i].get_ref_link())
     }
+
+    pub fn has_emoji(&self) -> bool {
+        self.tags.iter().any(|tag| tag.is_emoji())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Clone)]
@@ -221,7 +335,7 @@ impl Markup {
                     children: c2,
                 },
             ) => {
-                if s1 == s2 {
+                if s1 == s2 && !s1.is_emoji() {
                     let mut children = c1.clone();
                     children.append(&mut c2.clone());
                     Some(Markup::Tag {
@@ -255,186 +369,7 @@ pub struct MessageParser();
 
 impl MessageParser {
     fn color(input: &str) -> IResult<&str, &str> {
-        alt((
-            // CSS Color Module Level 4.
-            tag_no_case("rebeccapurple"),
-            // CSS Color Module Level 3.
-            alt((
-                tag_no_case("yellowgreen"),
-                tag_no_case("whitesmoke"),
-                tag_no_case("wheat"),
-                tag_no_case("violet"),
-                tag_no_case("turquoise"),
-                tag_no_case("tomato"),
-                tag_no_case("thistle"),
-                tag_no_case("tan"),
-                tag_no_case("steelblue"),
-                tag_no_case("springgreen"),
-                tag_no_case("snow"),
-                tag_no_case("slategrey"),
-                tag_no_case("slategray"),
-                tag_no_case("slateblue"),
-                tag_no_case("skyblue"),
-                tag_no_case("sienna"),
-                tag_no_case("seashell"),
-                tag_no_case("seagreen"),
-                tag_no_case("sandybrown"),
-                tag_no_case("salmon"),
-                tag_no_case("saddlebrown"),
-            )),
-            alt((
-                tag_no_case("royalblue"),
-                tag_no_case("rosybrown"),
-                tag_no_case("powderblue"),
-                tag_no_case("plum"),
-                tag_no_case("pink"),
-                tag_no_case("peru"),
-                tag_no_case("peachpuff"),
-                tag_no_case("papayawhip"),
-                tag_no_case("palevioletred"),
-                tag_no_case("paleturquoise"),
-                tag_no_case("palegreen"),
-                tag_no_case("palegoldenrod"),
-                tag_no_case("orchid"),
-                tag_no_case("orangered"),
-                tag_no_case("olivedrab"),
-                tag_no_case("oldlace"),
-                tag_no_case("navajowhite"),
-                tag_no_case("moccasin"),
-                tag_no_case("mistyrose"),
-                tag_no_case("mintcream"),
-                tag_no_case("midnightblue"),
-            )),
-            alt((
-                tag_no_case("mediumvioletred"),
-                tag_no_case("mediumturquoise"),
-                tag_no_case("mediumspringgreen"),
-                tag_no_case("mediumslateblue"),
-                tag_no_case("mediumseagreen"),
-                tag_no_case("mediumpurple"),
-                tag_no_case("mediumorchid"),
-                tag_no_case("mediumblue"),
-                tag_no_case("mediumaquamarine"),
-                tag_no_case("magenta"),
-                tag_no_case("linen"),
-                tag_no_case("limegreen"),
-                tag_no_case("lightyellow"),
-                tag_no_case("lightsteelblue"),
-                tag_no_case("lightslategrey"),
-                tag_no_case("lightslategray"),
-                tag_no_case("lightskyblue"),
-                tag_no_case("lightseagreen"),
-                tag_no_case("lightsalmon"),
-                tag_no_case("lightpink"),
-                tag_no_case("lightgrey"),
-            )),
-            alt((
-                tag_no_case("lightgreen"),
-                tag_no_case("lightgray"),
-                tag_no_case("lightgoldenrodyellow"),
-                tag_no_case("lightcyan"),
-                tag_no_case("lightcoral"),
-                tag_no_case("lightblue"),
-                tag_no_case("lemonchiffon"),
-                tag_no_case("lawngreen"),
-                tag_no_case("lavenderblush"),
-                tag_no_case("lavender"),
-                tag_no_case("khaki"),
-                tag_no_case("ivory"),
-                tag_no_case("indigo"),
-                tag_no_case("indianred"),
-                tag_no_case("hotpink"),
-                tag_no_case("honeydew"),
-                tag_no_case("grey"),
-                tag_no_case("greenyellow"),
-                tag_no_case("goldenrod"),
-                tag_no_case("gold"),
-                tag_no_case("ghostwhite"),
-            )),
-            alt((
-                tag_no_case("gainsboro"),
-                tag_no_case("forestgreen"),
-                tag_no_case("floralwhite"),
-                tag_no_case("firebrick"),
-                tag_no_case("dodgerblue"),
-                tag_no_case("dimgrey"),
-                tag_no_case("dimgray"),
-                tag_no_case("deepskyblue"),
-                tag_no_case("deeppink"),
-                tag_no_case("darkviolet"),
-                tag_no_case("darkturquoise"),
-                tag_no_case("darkslategrey"),
-                tag_no_case("darkslategray"),
-                tag_no_case("darkslateblue"),
-                tag_no_case("darkseagreen"),
-                tag_no_case("darksalmon"),
-                tag_no_case("darkred"),
-                tag_no_case("darkorchid"),
-                tag_no_case("darkorange"),
-                tag_no_case("darkolivegreen"),
-                tag_no_case("darkmagenta"),
-            )),
-            alt((
-                tag_no_case("darkkhaki"),
-                tag_no_case("darkgrey"),
-                tag_no_case("darkgreen"),
-                tag_no_case("darkgray"),
-                tag_no_case("darkgoldenrod"),
-                tag_no_case("darkcyan"),
-                tag_no_case("darkblue"),
-                tag_no_case("cyan"),
-                tag_no_case("crimson"),
-                tag_no_case("cornsilk"),
-                tag_no_case("cornflowerblue"),
-                tag_no_case("coral"),
-                tag_no_case("chocolate"),
-                tag_no_case("chartreuse"),
-                tag_no_case("cadetblue"),
-                tag_no_case("burlywood"),
-                tag_no_case("brown"),
-                tag_no_case("blueviolet"),
-                tag_no_case("blanchedalmond"),
-                tag_no_case("bisque"),
-                tag_no_case("beige"),
-            )),
-            alt((
-                tag_no_case("azure"),
-                tag_no_case("aquamarine"),
-                tag_no_case("antiquewhite"),
-                tag_no_case("aliceblue"),
-            )),
-            // CSS Level 2 (Revision 1).
-            tag_no_case("orange"),
-            // CSS Level 1.
-            alt((
-                tag_no_case("yellow"),
-                tag_no_case("white"),
-                tag_no_case("teal"),
-                tag_no_case("silver"),
-                tag_no_case("red"),
-                tag_no_case("purple"),
-                tag_no_case("olive"),
-                tag_no_case("navy"),
-                tag_no_case("maroon"),
-                tag_no_case("lime"),
-                tag_no_case("green"),
-                tag_no_case("gray"),
-                tag_no_case("fuchsia"),
-                tag_no_case("blue"),
-                tag_no_case("black"),
-                tag_no_case("aqua"),
-            )),
-            // Hexadecimal notation.
-            recognize(preceded(
-                char('#'),
-                alt((
-                    many_m_n(8, 8, one_of("0123456789ABCDEFabcdef")),
-                    many_m_n(6, 6, one_of("0123456789ABCDEFabcdef")),
-                    many_m_n(4, 4, one_of("0123456789ABCDEFabcdef")),
-                    many_m_n(3, 3, one_of("0123456789ABCDEFabcdef")),
-                )),
-            )),
-        ))(input)
+        recognize(colors::color)(input)
     }
 
     fn opening_tag(input: &str) -> IResult<&str, Token> {
@@ -499,17 +434,25 @@ impl MessageParser {
         map(
             delimited(
                 tag("##"),
-                separated_pair(digit1, char('d'), digit1),
+                separated_pair(
+                    map_res(digit1, |s: &str| s.parse::<u32>()),
+                    char('d'),
+                    map_res(digit1, |s: &str| s.parse::<u32>()),
+                ),
                 tag("##"),
             ),
-            |(count, max): (&str, &str)| Token::Dice(count.parse().unwrap(), max.parse().unwrap()),
+            |(count, max): (u32, u32)| Token::Dice(count, max),
         )(input)
     }
 
     fn link(input: &str) -> IResult<&str, Token> {
         map(
             tuple((
-                alt((tag("http://"), tag("https://"))),
+                recognize(tuple((
+                    alpha1,
+                    take_while(|c: char| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+                    tag("://"),
+                ))),
                 is_not("/?# "),
                 is_not("[?# "),
                 opt(preceded(char('?'), is_not("[# "))),
@@ -533,8 +476,19 @@ impl MessageParser {
         )(input)
     }
 
+    fn emoji(input: &str) -> IResult<&str, Token> {
+        map(
+            delimited(
+                char(':'),
+                take_while_m_n(1, MAX_SHORTCODE_LENGTH, CustomEmoji::is_shortcode_char),
+                char(':'),
+            ),
+            |shortcode: &str| Token::Emoji(shortcode.to_string()),
+        )(input)
+    }
+
     fn text(input: &str) -> IResult<&str, Token> {
-        map(is_not("[># "), |s: &str| Token::Text(s.to_string()))(input)
+        map(is_not("[># :"), |s: &str| Token::Text(s.to_string()))(input)
     }
 
     fn ref_link(input: &str) -> IResult<&str, Token> {
@@ -543,60 +497,83 @@ impl MessageParser {
         })(input)
     }
 
-    fn inline(input: &str) -> IResult<&str, Vec<Token>> {
-        many0(alt((
+    fn inline(input: &str) -> IResult<&str, Token> {
+        alt((
             MessageParser::closing_tag,
             MessageParser::opening_tag,
             MessageParser::ref_link,
             MessageParser::link,
             MessageParser::dice,
+            MessageParser::emoji,
             MessageParser::text,
-            map(one_of("[># "), |c: char| Token::Text(c.to_string())),
-        )))(input)
+            map(one_of("[># :"), |c: char| Token::Text(c.to_string())),
+        ))(input)
     }
 
-    fn quote(input: &str) -> IResult<&str, Vec<Token>> {
-        preceded(
-            peek(alt((
-                value((), pair(tag(">>"), not(digit1))),
-                value((), pair(char('>'), not(char('>')))),
-            ))),
-            map(MessageParser::inline, |tokens| {
-                let mut result = vec![Token::OpeningTag(OpeningTag::Quote)];
-                result.append(&mut tokens.clone());
-                result.push(Token::ClosingTag(ClosingTag::Quote));
-                result
-            }),
-        )(input)
+    fn quote(input: &str) -> IResult<&str, ()> {
+        peek(alt((
+            value((), pair(tag(">>"), not(digit1))),
+            value((), pair(char('>'), not(char('>')))),
+        )))(input)
     }
 
-    fn block(input: &str) -> IResult<&str, Vec<Token>> {
-        alt((MessageParser::quote, MessageParser::inline))(input)
+    /// Tokenizes a line, along with the byte range of the line each token was parsed from.
+    /// The tags around quotes have empty ranges.
+    fn block(input: &str, offset: usize) -> Vec<(Token, Range<usize>)> {
+        let mut result = Vec::new();
+        let is_quote = MessageParser::quote(input).is_ok();
+        if is_quote {
+            result.push((Token::OpeningTag(OpeningTag::Quote), offset..offset));
+        }
+
+        let mut rest = input;
+        while let Ok((next, token)) = MessageParser::inline(rest) {
+            let start = offset + input.len() - rest.len();
+            let end = offset + input.len() - next.len();
+            result.push((token, start..end));
+            rest = next;
+        }
+
+        if is_quote {
+            let end = offset + input.len();
+            result.push((Token::ClosingTag(ClosingTag::Quote), end..end));
+        }
+
+        result
     }
 
-    pub fn tokenize(input: &str) -> Result<Vec<Token>, &str> {
-        let mut input = input.trim().to_string();
-        input = input.replace("\r\n", "\n");
+    /// Like `tokenize`, but also returns the byte range of the input each token was parsed
+    /// from.
+    pub fn tokenize_with_ranges(input: &str) -> Vec<(Token, Range<usize>)> {
+        let trimmed = input.trim();
+        let mut offset = input.len() - input.trim_start().len();
+        let mut result = Vec::new();
 
-        let lines: Vec<&str> = input.split("\n").collect();
+        let lines: Vec<&str> = trimmed.split('\n').collect();
         let len = lines.len();
-        let lines: Vec<Vec<Token>> = lines
-            .into_iter()
-            .map(|s| match MessageParser::block(s) {
-                Ok((_, o)) => o,
-                _ => Vec::new(),
-            })
-            .enumerate()
-            .map(|(index, mut tokens)| {
-                if index < len - 1 {
-                    tokens.push(Token::Text("\n".to_string()));
-                }
-                tokens
-            })
-            .collect();
+        for (index, line) in lines.into_iter().enumerate() {
+            let content = if index < len - 1 {
+                line.strip_suffix('\r').unwrap_or(line)
+            } else {
+                line
+            };
+
+            result.append(&mut MessageParser::block(content, offset));
+            offset += line.len();
+
+            if index < len - 1 {
+                let start = offset - (line.len() - content.len());
+                result.push((Token::Text("\n".to_string()), start..offset + 1));
+                offset += 1;
+            }
+        }
+
+        result
+    }
 
-        let lines: Vec<Token> = lines.into_iter().flatten().collect();
-        Ok(lines)
+    pub fn tokenize(input: &str) -> Result<Vec<Token>, &str> {
+        let tokens = MessageParser::tokenize_with_ranges(input);
+        Ok(tokens.into_iter().map(|(token, _)| token).collect())
     }
 
     fn optimize_segments(segments: Vec<Segment>) -> Vec<Segment> {
@@ -606,7 +583,7 @@ impl MessageParser {
                 let last = segments.pop();
                 match last {
                     Some(last) => {
-                        if last.tags == segment.tags && !last.has_ref_link() {
+                        if last.tags == segment.tags && !last.has_ref_link() && !last.has_emoji() {
                             segments.push(Segment {
                                 text: format!("{}{}", last.text, segment.text),
                                 tags: last.tags,
@@ -625,11 +602,61 @@ impl MessageParser {
             })
     }
 
-    pub fn to_segments(tokens: Vec<Token>) -> Vec<Segment> {
+    /// Turns tokens disabled by the options back into plain text.
+    fn apply_options(token: Token, options: &MessageParserOptions) -> Token {
+        match token {
+            Token::OpeningTag(tag) => {
+                if options.is_tag_enabled(tag.name()) {
+                    Token::OpeningTag(tag)
+                } else {
+                    Token::Text(tag.to_string())
+                }
+            }
+            Token::ClosingTag(tag) => {
+                if options.is_tag_enabled(tag.name()) {
+                    Token::ClosingTag(tag)
+                } else {
+                    Token::Text(tag.to_string())
+                }
+            }
+            Token::Link(url) => {
+                if options.is_link_allowed(&url) {
+                    Token::Link(url)
+                } else {
+                    Token::Text(url)
+                }
+            }
+            Token::Dice(count, max) => {
+                if count <= options.max_dice {
+                    Token::Dice(count, max)
+                } else {
+                    Token::Text(format!("##{}d{}##", count, max))
+                }
+            }
+            token => token,
+        }
+    }
+
+    fn apply_color_contrast(tag: OpeningTag, options: &MessageParserOptions) -> Option<OpeningTag> {
+        match (tag, &options.color_contrast) {
+            (OpeningTag::Color { color }, Some(contrast)) => contrast
+                .apply(&color)
+                .map(|color| OpeningTag::Color { color }),
+            (tag, _) => Some(tag),
+        }
+    }
+
+    pub fn to_segments(
+        tokens: Vec<Token>,
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> Vec<Segment> {
         let mut result = Vec::new();
         let mut active_styles = Vec::new();
+        // Tags nested too deep are kept as text, and so are their closing tags.
+        let mut too_deep_tags: Vec<OpeningTag> = Vec::new();
         for token in tokens.into_iter() {
-            match token {
+            match MessageParser::apply_options(token, options) {
                 Token::Text(text) => {
                     if active_styles.contains(&Style::CodeBlock) {
                         result.push(Segment {
@@ -680,7 +707,10 @@ impl MessageParser {
                         });
                     } else {
                         let mut tags = active_styles.clone();
-                        tags.push(Style::Link { url: url.clone() });
+                        tags.push(Style::Link {
+                            url: url.clone(),
+                            embed: options.allow_embeds,
+                        });
                         result.push(Segment { text: url, tags });
                     }
                 }
@@ -703,6 +733,31 @@ impl MessageParser {
                         result.push(Segment { text, tags });
                     }
                 }
+                Token::Emoji(shortcode) => {
+                    let text = format!(":{}:", shortcode);
+
+                    if active_styles.contains(&Style::CodeBlock) {
+                        result.push(Segment {
+                            text,
+                            tags: vec![Style::CodeBlock],
+                        });
+                    } else if active_styles.contains(&Style::Code) {
+                        result.push(Segment {
+                            text,
+                            tags: vec![Style::Code],
+                        });
+                    } else {
+                        let mut tags = active_styles.clone();
+                        if let Some(md5) = emoji.get(&shortcode) {
+                            tags.push(Style::Emoji {
+                                shortcode,
+                                md5: md5.clone(),
+                            });
+                        }
+
+                        result.push(Segment { text, tags });
+                    }
+                }
                 Token::OpeningTag(tag) => {
                     if active_styles.contains(&Style::CodeBlock) {
                         result.push(Segment {
@@ -714,7 +769,13 @@ impl MessageParser {
                             text: String::from(OpeningTag::to_string(&tag)),
                             tags: vec![Style::Code],
                         });
-                    } else {
+                    } else if active_styles.len() >= options.max_nesting_depth {
+                        result.push(Segment {
+                            text: OpeningTag::to_string(&tag),
+                            tags: active_styles.clone(),
+                        });
+                        too_deep_tags.push(tag);
+                    } else if let Some(tag) = MessageParser::apply_color_contrast(tag, options) {
                         active_styles.push(Style::from_opening_tag(&tag));
                     }
                 }
@@ -729,24 +790,88 @@ impl MessageParser {
                             text: String::from(ClosingTag::to_string(&tag)),
                             tags: vec![Style::Code],
                         });
+                    } else if let Some(index) =
+                        too_deep_tags.iter().rposition(|item| item.is_pair(&tag))
+                    {
+                        too_deep_tags.remove(index);
+                        result.push(Segment {
+                            text: ClosingTag::to_string(&tag),
+                            tags: active_styles.clone(),
+                        });
+                    } else if let Some(index) =
+                        active_styles.iter().rposition(|item| item.is_pair(&tag))
+                    {
+                        active_styles.remove(index);
+                    }
+                }
+            }
+        }
+
+        MessageParser::optimize_segments(result)
+    }
+
+    /// Returns the byte ranges of the input which are shown as plain text, skipping tags,
+    /// links, ref links, dice and emoji the same way `to_segments` turns them into markup.
+    pub fn text_ranges(
+        input: &str,
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> Vec<Range<usize>> {
+        let mut result: Vec<Range<usize>> = Vec::new();
+        let mut active_tags: Vec<OpeningTag> = Vec::new();
+        let mut too_deep_tags: Vec<OpeningTag> = Vec::new();
+        for (token, range) in MessageParser::tokenize_with_ranges(input) {
+            let in_code_block = active_tags.contains(&OpeningTag::CodeBlock);
+            let in_inline_code = active_tags.contains(&OpeningTag::Code);
+            let in_code = in_code_block || in_inline_code;
+            let is_text = match MessageParser::apply_options(token, options) {
+                Token::Text(_) => true,
+                Token::Emoji(shortcode) => in_code || !emoji.contains_key(&shortcode),
+                Token::OpeningTag(_) if in_code => true,
+                Token::OpeningTag(tag) if active_tags.len() >= options.max_nesting_depth => {
+                    too_deep_tags.push(tag);
+                    true
+                }
+                Token::OpeningTag(tag) => {
+                    if let Some(tag) = MessageParser::apply_color_contrast(tag, options) {
+                        active_tags.push(tag);
+                    }
+                    false
+                }
+                Token::ClosingTag(tag) if in_code_block && tag != ClosingTag::CodeBlock => true,
+                Token::ClosingTag(tag) if in_inline_code && tag != ClosingTag::Code => true,
+                Token::ClosingTag(tag) => {
+                    if let Some(index) = too_deep_tags.iter().rposition(|item| item.is_pair(&tag)) {
+                        too_deep_tags.remove(index);
+                        true
                     } else {
-                        if let Some(index) =
-                            active_styles.iter().rposition(|item| item.is_pair(&tag))
+                        if let Some(index) = active_tags.iter().rposition(|item| item.is_pair(&tag))
                         {
-                            active_styles.remove(index);
+                            active_tags.remove(index);
                         }
+                        false
                     }
                 }
+                _ => in_code,
+            };
+
+            if !is_text || range.is_empty() {
+                continue;
+            }
+
+            match result.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => result.push(range),
             }
         }
 
-        MessageParser::optimize_segments(result)
+        result
     }
 
-    pub fn str_to_segments(input: &str) -> Vec<Segment> {
+    pub fn str_to_segments(input: &str, options: &MessageParserOptions) -> Vec<Segment> {
         let tokens = MessageParser::tokenize(input);
         match tokens {
-            Ok(tokens) => MessageParser::to_segments(tokens),
+            Ok(tokens) => MessageParser::to_segments(tokens, &EmojiMap::new(), options),
             Err(_) => Vec::new(),
         }
     }
@@ -802,10 +927,21 @@ impl MessageParser {
         MessageParser::optimize_tree(tree)
     }
 
-    pub fn str_to_markup(input: &str) -> Vec<Markup> {
+    pub fn str_to_markup(input: &str, options: &MessageParserOptions) -> Vec<Markup> {
+        MessageParser::str_to_markup_with_emoji(input, &EmojiMap::new(), options)
+    }
+
+    pub fn str_to_markup_with_emoji(
+        input: &str,
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> Vec<Markup> {
         let tokens = MessageParser::tokenize(input);
         match tokens {
-            Ok(tokens) => MessageParser::segments_to_markup(MessageParser::to_segments(tokens)),
+            Ok(tokens) => {
+                let segments = MessageParser::to_segments(tokens, emoji, options);
+                MessageParser::segments_to_markup(segments)
+            }
             Err(_) => Vec::new(),
         }
     }
@@ -815,7 +951,10 @@ impl MessageParser {
 mod tests {
     use crate::models::message_parser::Segment;
 
-    use super::{ClosingTag, Markup, MessageParser, OpeningTag, Style, Token};
+    use super::{
+        ClosingTag, EmojiMap, Markup, MessageParser, MessageParserOptions, OpeningTag, Style, Token,
+    };
+    use crate::models::colors::{ColorContrast, ColorContrastMode, Rgba};
 
     #[test]
     fn tokenize_empty_string() {
@@ -862,6 +1001,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_with_ranges() {
+        let input = " >>1 a\r\n> b ";
+        assert_eq!(
+            vec!(
+                (Token::RefLink(1), 1..4),
+                (Token::Text(" ".to_string()), 4..5),
+                (Token::Text("a".to_string()), 5..6),
+                (Token::Text("\n".to_string()), 6..8),
+                (Token::OpeningTag(OpeningTag::Quote), 8..8),
+                (Token::Text(">".to_string()), 8..9),
+                (Token::Text(" ".to_string()), 9..10),
+                (Token::Text("b".to_string()), 10..11),
+                (Token::ClosingTag(ClosingTag::Quote), 11..11),
+            ),
+            MessageParser::tokenize_with_ranges(input)
+        );
+    }
+
+    #[test]
+    fn text_ranges() {
+        let mut emoji = EmojiMap::new();
+        emoji.insert(String::from("cat"), String::from("md5"));
+        let options = MessageParserOptions {
+            enabled_tags: vec![String::from("b"), String::from("code")],
+            ..MessageParserOptions::default()
+        };
+
+        let input = "[b]a[/b] [i]b[/i] :cat: :dog: [code][b]:cat:[/b][/code]";
+        let ranges: Vec<&str> = MessageParser::text_ranges(input, &emoji, &options)
+            .into_iter()
+            .map(|range| &input[range])
+            .collect();
+        assert_eq!(vec!["a", " [i]b[/i] ", " :dog: ", "[b]:cat:[/b]"], ranges);
+    }
+
     #[test]
     fn tokenize_ref_link() {
         let input = ">>12345";
@@ -1106,6 +1281,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tokenize_color_named() {
+        let input = "[color=RebeccaPurple]";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(Token::OpeningTag(OpeningTag::Color {
+                color: "RebeccaPurple".to_string()
+            }),)),
+            tokens
+        );
+    }
+
+    #[test]
+    fn tokenize_color_rgb() {
+        let input = "[color=rgb(255, 128, 0)]";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(Token::OpeningTag(OpeningTag::Color {
+                color: "rgb(255, 128, 0)".to_string()
+            }),)),
+            tokens
+        );
+    }
+
+    #[test]
+    fn tokenize_color_hsl() {
+        let input = "[color=\"hsla(120, 100%, 25%, 0.5)\"]";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(Token::OpeningTag(OpeningTag::Color {
+                color: "hsla(120, 100%, 25%, 0.5)".to_string()
+            }),)),
+            tokens
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_color_rgb() {
+        let input = "[color=rgb(256,0,0)]";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(
+                Token::Text("[".to_string()),
+                Token::Text("color=rgb(256,0,0)]".to_string()),
+            )),
+            tokens
+        );
+    }
+
     #[test]
     fn tokenize_unknown_opening_tag() {
         let input = "[lorem]";
@@ -1239,7 +1463,7 @@ mod tests {
     #[test]
     fn str_to_segments_text_without_tags() {
         let input = "Lorem ipsum dolor sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(Segment {
                 text: "Lorem ipsum dolor sit amet".to_string(),
@@ -1252,7 +1476,7 @@ mod tests {
     #[test]
     fn str_to_segments_tag_pair() {
         let input = "Lorem ip[b]sum dolor [/b]sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1275,7 +1499,7 @@ mod tests {
     #[test]
     fn str_to_segments_nested_tag_pairs() {
         let input = "Lorem ip[b]sum [i]dolor[/i] sit[/b] amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1306,7 +1530,7 @@ mod tests {
     #[test]
     fn str_to_segments_overlapping_tag_pairs() {
         let input = "Lorem ip[b]sum [i]dolor[/b] sit[/i] amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1337,7 +1561,7 @@ mod tests {
     #[test]
     fn str_to_segments_unclosed_tag_pair() {
         let input = "Lorem ip[b]sum dolor sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1356,7 +1580,7 @@ mod tests {
     #[test]
     fn str_to_segments_text_with_quote() {
         let input = ">Lorem ipsum\ndolor sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1375,7 +1599,7 @@ mod tests {
     #[test]
     fn str_to_segments_text_with_ref_link() {
         let input = ">>12345\nLorem ipsum dolor sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1394,7 +1618,7 @@ mod tests {
     #[test]
     fn str_to_segments_text_with_quote_and_ref_link() {
         let input = ">>12345\n>Lorem ipsum\ndolor sit amet";
-        let tokens = MessageParser::str_to_segments(input);
+        let tokens = MessageParser::str_to_segments(input, &MessageParserOptions::default());
         assert_eq!(
             vec!(
                 Segment {
@@ -1421,7 +1645,7 @@ mod tests {
     #[test]
     fn str_to_markup_empty_string() {
         let input = "";
-        let markup = MessageParser::str_to_markup(input);
+        let markup = MessageParser::str_to_markup(input, &MessageParserOptions::default());
         let expected: Vec<Markup> = Vec::new();
         assert_eq!(expected, markup);
     }
@@ -1429,7 +1653,7 @@ mod tests {
     #[test]
     fn str_to_markup_text() {
         let input = "Lorem ipsum";
-        let markup = MessageParser::str_to_markup(input);
+        let markup = MessageParser::str_to_markup(input, &MessageParserOptions::default());
         assert_eq!(
             vec![Markup::Text {
                 text: "Lorem ipsum".to_string()
@@ -1441,7 +1665,7 @@ mod tests {
     #[test]
     fn str_to_markup_tag() {
         let input = "Lorem [b]ipsum dolor[/b] sit";
-        let markup = MessageParser::str_to_markup(input);
+        let markup = MessageParser::str_to_markup(input, &MessageParserOptions::default());
         assert_eq!(
             vec![
                 Markup::Text {
@@ -1464,7 +1688,7 @@ mod tests {
     #[test]
     fn str_to_markup_nested_tag() {
         let input = "Lorem [b]ipsum [i]dolor[/i][/b] sit";
-        let markup = MessageParser::str_to_markup(input);
+        let markup = MessageParser::str_to_markup(input, &MessageParserOptions::default());
         assert_eq!(
             vec![
                 Markup::Text {
@@ -1491,4 +1715,320 @@ mod tests {
             markup
         );
     }
+
+    #[test]
+    fn tokenize_emoji() {
+        let input = "Lorem :smile: ipsum";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(
+                Token::Text("Lorem".to_string()),
+                Token::Text(" ".to_string()),
+                Token::Emoji("smile".to_string()),
+                Token::Text(" ".to_string()),
+                Token::Text("ipsum".to_string()),
+            )),
+            tokens
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_emoji() {
+        let input = ":Lorem ipsum:";
+        let tokens = MessageParser::tokenize(input);
+        assert_eq!(
+            Ok(vec!(
+                Token::Text(":".to_string()),
+                Token::Text("Lorem".to_string()),
+                Token::Text(" ".to_string()),
+                Token::Text("ipsum".to_string()),
+                Token::Text(":".to_string()),
+            )),
+            tokens
+        );
+    }
+
+    #[test]
+    fn str_to_markup_emoji() {
+        let input = "Lorem :smile::smile: ipsum";
+        let mut emoji = EmojiMap::new();
+        emoji.insert(
+            "smile".to_string(),
+            "0123456789abcdef0123456789abcdef".to_string(),
+        );
+
+        let markup = MessageParser::str_to_markup_with_emoji(
+            input,
+            &emoji,
+            &MessageParserOptions::default(),
+        );
+        let smile = Markup::Tag {
+            tag: Style::Emoji {
+                shortcode: "smile".to_string(),
+                md5: "0123456789abcdef0123456789abcdef".to_string(),
+            },
+            children: vec![Markup::Text {
+                text: ":smile:".to_string(),
+            }],
+        };
+
+        assert_eq!(
+            vec![
+                Markup::Text {
+                    text: "Lorem ".to_string()
+                },
+                smile.clone(),
+                smile,
+                Markup::Text {
+                    text: " ipsum".to_string()
+                },
+            ],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_unknown_emoji() {
+        let input = "Lorem :smile: ipsum";
+        let markup = MessageParser::str_to_markup_with_emoji(
+            input,
+            &EmojiMap::new(),
+            &MessageParserOptions::default(),
+        );
+        assert_eq!(
+            vec![Markup::Text {
+                text: "Lorem :smile: ipsum".to_string()
+            }],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_emoji_in_code() {
+        let input = "[code]:smile:[/code]";
+        let mut emoji = EmojiMap::new();
+        emoji.insert(
+            "smile".to_string(),
+            "0123456789abcdef0123456789abcdef".to_string(),
+        );
+
+        let markup = MessageParser::str_to_markup_with_emoji(
+            input,
+            &emoji,
+            &MessageParserOptions::default(),
+        );
+        assert_eq!(
+            vec![Markup::Tag {
+                tag: Style::Code,
+                children: vec![Markup::Text {
+                    text: ":smile:".to_string()
+                }],
+            }],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_color_contrast_reject() {
+        let input = "[color=yellow]Lorem[/color] [color=navy]ipsum[/color]";
+        let options = MessageParserOptions {
+            color_contrast: Some(ColorContrast {
+                background: Rgba::from_u32(0xffffff),
+                min_ratio: 3.0,
+                mode: ColorContrastMode::Reject,
+            }),
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup_with_emoji(input, &EmojiMap::new(), &options);
+        assert_eq!(
+            vec![
+                Markup::Text {
+                    text: "Lorem ".to_string()
+                },
+                Markup::Tag {
+                    tag: Style::Color {
+                        color: "navy".to_string()
+                    },
+                    children: vec![Markup::Text {
+                        text: "ipsum".to_string()
+                    }],
+                },
+            ],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_color_contrast_clamp() {
+        let input = "[color=#fff]Lorem[/color]";
+        let options = MessageParserOptions {
+            color_contrast: Some(ColorContrast {
+                background: Rgba::from_u32(0xffffff),
+                min_ratio: 21.0,
+                mode: ColorContrastMode::Clamp,
+            }),
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup_with_emoji(input, &EmojiMap::new(), &options);
+        assert_eq!(
+            vec![Markup::Tag {
+                tag: Style::Color {
+                    color: "#000000".to_string()
+                },
+                children: vec![Markup::Text {
+                    text: "Lorem".to_string()
+                }],
+            }],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_disabled_tag() {
+        let input = "[spoiler]Lorem [b]ipsum[/b][/spoiler]";
+        let options = MessageParserOptions {
+            enabled_tags: vec!["b".to_string()],
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![
+                Markup::Text {
+                    text: "[spoiler]Lorem ".to_string()
+                },
+                Markup::Tag {
+                    tag: Style::Bold,
+                    children: vec![Markup::Text {
+                        text: "ipsum".to_string()
+                    }],
+                },
+                Markup::Text {
+                    text: "[/spoiler]".to_string()
+                },
+            ],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_disabled_colors() {
+        let input = "[color=red]Lorem[/color]";
+        let options = MessageParserOptions {
+            allow_colors: false,
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![Markup::Text {
+                text: "[color=red]Lorem[/color]".to_string()
+            }],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_max_nesting_depth() {
+        let input = "[b][i][u]Lorem[/u][/i][/b]";
+        let options = MessageParserOptions {
+            max_nesting_depth: 2,
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![Markup::Tag {
+                tag: Style::Bold,
+                children: vec![Markup::Tag {
+                    tag: Style::Italic,
+                    children: vec![Markup::Text {
+                        text: "[u]Lorem[/u]".to_string()
+                    }],
+                }],
+            }],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_max_nesting_depth_same_tags() {
+        let input = "[b][b]Lorem[/b] ipsum[/b] dolor";
+        let options = MessageParserOptions {
+            max_nesting_depth: 1,
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![
+                Markup::Tag {
+                    tag: Style::Bold,
+                    children: vec![Markup::Text {
+                        text: "[b]Lorem[/b] ipsum".to_string()
+                    }],
+                },
+                Markup::Text {
+                    text: " dolor".to_string()
+                },
+            ],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_max_dice() {
+        let input = "##2d6## ##100d6##";
+        let options = MessageParserOptions {
+            max_dice: 10,
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![
+                Markup::Tag {
+                    tag: Style::Dice { count: 2, max: 6 },
+                    children: vec![Markup::Text {
+                        text: "##2d6##".to_string()
+                    }],
+                },
+                Markup::Text {
+                    text: " ##100d6##".to_string()
+                },
+            ],
+            markup
+        );
+    }
+
+    #[test]
+    fn str_to_markup_link_schemes() {
+        let input = "http://localhost/ ftp://localhost/";
+        let options = MessageParserOptions {
+            link_schemes: vec!["ftp".to_string()],
+            allow_embeds: false,
+            ..MessageParserOptions::default()
+        };
+
+        let markup = MessageParser::str_to_markup(input, &options);
+        assert_eq!(
+            vec![
+                Markup::Text {
+                    text: "http://localhost/ ".to_string()
+                },
+                Markup::Tag {
+                    tag: Style::Link {
+                        url: "ftp://localhost/".to_string(),
+                        embed: false,
+                    },
+                    children: vec![Markup::Text {
+                        text: "ftp://localhost/".to_string()
+                    }],
+                },
+            ],
+            markup
+        );
+    }
 }
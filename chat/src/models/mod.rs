@@ -1,4 +1,19 @@
+pub mod api_tokens;
+pub mod audit_log;
+pub mod blocked_files;
+pub mod colors;
+pub mod direct_messages;
+pub mod emoji;
+pub mod events;
 pub mod files;
 pub mod message_parser;
 pub mod notifications;
 pub mod posts;
+pub mod rate_limits;
+pub mod reports;
+pub mod restrictions;
+pub mod rooms;
+pub mod settings;
+pub mod spam;
+pub mod webhooks;
+pub mod word_filters;
@@ -1,9 +1,48 @@
+use super::message_parser::{ClosingTag, MessageParser, OpeningTag, Token};
 use crate::schema::posts;
 use chrono::prelude::*;
 use encoding_rs::SHIFT_JIS;
 use pwhash::unix_crypt;
 use serde::Serialize;
 
+#[derive(Debug, Clone)]
+pub struct PostLimits {
+    pub max_message_bytes: usize,
+    pub max_lines: usize,
+    pub max_nesting_depth: usize,
+    pub max_ref_links: usize,
+    pub max_links: usize,
+}
+
+impl Default for PostLimits {
+    fn default() -> PostLimits {
+        PostLimits {
+            max_message_bytes: 8000,
+            max_lines: 200,
+            max_nesting_depth: 16,
+            max_ref_links: 20,
+            max_links: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &str, message: String) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            code: code.to_string(),
+            message,
+        }
+    }
+}
+
 #[derive(Insertable)]
 #[table_name = "posts"]
 pub struct NewPost {
@@ -12,6 +51,16 @@ pub struct NewPost {
     pub message: String,
     pub created_at: NaiveDateTime,
     pub user_uuid: Option<String>,
+    pub quarantined: bool,
+    pub room_id: i32,
+    pub bot: bool,
+}
+
+/// Who is reading the posts. Quarantined posts are only visible to their author and moderators.
+#[derive(Debug, Clone, Default)]
+pub struct PostViewer {
+    pub user_uuid: Option<String>,
+    pub is_moderator: bool,
 }
 
 #[derive(Identifiable, Queryable, Serialize)]
@@ -22,6 +71,10 @@ pub struct Post {
     pub message: String,
     pub created_at: NaiveDateTime,
     pub user_uuid: Option<String>,
+    pub hidden: bool,
+    pub quarantined: bool,
+    pub room_id: i32,
+    pub bot: bool,
 }
 
 impl Post {
@@ -85,17 +138,130 @@ impl Post {
         }
     }
 
+    /// Returns the deepest level of nested tags, ignoring unmatched closing tags and tags
+    /// inside code, which are shown as text.
+    fn nesting_depth(tokens: &[Token]) -> usize {
+        let mut stack = Vec::new();
+        let mut max_depth = 0;
+        for token in tokens {
+            let in_code_block = stack.contains(&&OpeningTag::CodeBlock);
+            let in_code = stack.contains(&&OpeningTag::Code);
+            match token {
+                Token::OpeningTag(_) if in_code_block || in_code => {}
+                Token::ClosingTag(tag) if in_code_block && *tag != ClosingTag::CodeBlock => {}
+                Token::ClosingTag(tag) if in_code && *tag != ClosingTag::Code => {}
+                Token::OpeningTag(tag) => {
+                    stack.push(tag);
+                    max_depth = max_depth.max(stack.len());
+                }
+                Token::ClosingTag(tag) => {
+                    if let Some(index) = stack.iter().rposition(|opening| opening.is_pair(tag)) {
+                        stack.truncate(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        max_depth
+    }
+
+    pub fn validate(
+        message: &str,
+        file_count: usize,
+        limits: &PostLimits,
+    ) -> Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        if message.trim().is_empty() && file_count == 0 {
+            errors.push(FieldError::new(
+                "message",
+                "empty",
+                String::from("Message must not be empty"),
+            ));
+        }
+
+        if message.len() > limits.max_message_bytes {
+            errors.push(FieldError::new(
+                "message",
+                "too_long",
+                format!(
+                    "Message must be at most {} bytes long",
+                    limits.max_message_bytes
+                ),
+            ));
+        }
+
+        if message.lines().count() > limits.max_lines {
+            errors.push(FieldError::new(
+                "message",
+                "too_many_lines",
+                format!("Message must have at most {} lines", limits.max_lines),
+            ));
+        }
+
+        // Don't spend time tokenizing messages that are already rejected by size.
+        if errors.is_empty() {
+            let tokens = MessageParser::tokenize(message).unwrap_or_default();
+
+            if Post::nesting_depth(&tokens) > limits.max_nesting_depth {
+                errors.push(FieldError::new(
+                    "message",
+                    "too_deeply_nested",
+                    format!(
+                        "Tags must be nested at most {} levels deep",
+                        limits.max_nesting_depth
+                    ),
+                ));
+            }
+
+            let ref_links = tokens
+                .iter()
+                .filter(|token| matches!(token, Token::RefLink(_)))
+                .count();
+            if ref_links > limits.max_ref_links {
+                errors.push(FieldError::new(
+                    "message",
+                    "too_many_ref_links",
+                    format!(
+                        "Message must have at most {} post references",
+                        limits.max_ref_links
+                    ),
+                ));
+            }
+
+            let links = tokens
+                .iter()
+                .filter(|token| matches!(token, Token::Link(_)))
+                .count();
+            if links > limits.max_links {
+                errors.push(FieldError::new(
+                    "message",
+                    "too_many_links",
+                    format!("Message must have at most {} links", limits.max_links),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     pub fn new(
         name: &str,
         tripcode: &str,
         message: &str,
         user_uuid: Option<&str>,
-    ) -> Result<NewPost, String> {
-        let (name, tripcode) = Post::process_name(name, tripcode);
+        room_id: i32,
+        file_count: usize,
+        limits: &PostLimits,
+    ) -> Result<NewPost, Vec<FieldError>> {
+        Post::validate(message, file_count, limits)?;
 
-        if message.len() >= 8000 {
-            return Err(String::from("Message is too long"));
-        }
+        let (name, tripcode) = Post::process_name(name, tripcode);
 
         Ok(NewPost {
             name,
@@ -103,13 +269,16 @@ impl Post {
             message: message.to_string(),
             created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
             user_uuid: user_uuid.map(String::from),
+            quarantined: false,
+            room_id,
+            bot: false,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Post;
+    use super::{Post, PostLimits};
 
     #[test]
     fn create_tripcode() {
@@ -202,4 +371,86 @@ mod tests {
         assert_eq!("username", name);
         assert_eq!("LLLLLLLLL.", tripcode);
     }
+
+    fn error_codes(message: &str, file_count: usize, limits: &PostLimits) -> Vec<String> {
+        match Post::validate(message, file_count, limits) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|error| error.code).collect(),
+        }
+    }
+
+    #[test]
+    fn validate_valid_message() {
+        let limits = PostLimits::default();
+        assert!(Post::validate("[b]test[/b] >>1 https://example.com/", 0, &limits).is_ok());
+    }
+
+    #[test]
+    fn validate_empty_message() {
+        let limits = PostLimits::default();
+        assert_eq!(vec!["empty"], error_codes("  \n ", 0, &limits));
+        assert!(Post::validate("", 1, &limits).is_ok());
+    }
+
+    #[test]
+    fn validate_message_length() {
+        let limits = PostLimits {
+            max_message_bytes: 4,
+            ..PostLimits::default()
+        };
+        assert!(Post::validate("test", 0, &limits).is_ok());
+        assert_eq!(vec!["too_long"], error_codes("tests", 0, &limits));
+        assert_eq!(vec!["too_long"], error_codes("тест", 0, &limits));
+    }
+
+    #[test]
+    fn validate_line_count() {
+        let limits = PostLimits {
+            max_lines: 2,
+            ..PostLimits::default()
+        };
+        assert!(Post::validate("a\nb", 0, &limits).is_ok());
+        assert_eq!(vec!["too_many_lines"], error_codes("a\nb\nc", 0, &limits));
+    }
+
+    #[test]
+    fn validate_nesting_depth() {
+        let limits = PostLimits {
+            max_nesting_depth: 2,
+            ..PostLimits::default()
+        };
+        assert!(Post::validate("[b][i]a[/i][/b][u][s]b[/s][/u]", 0, &limits).is_ok());
+        assert_eq!(
+            vec!["too_deeply_nested"],
+            error_codes("[b][i][u]a[/u][/i][/b]", 0, &limits)
+        );
+        assert!(Post::validate("[b][code][i][u]a[/u][/i][/code][/b]", 0, &limits).is_ok());
+        assert!(Post::validate("[codeblock][code][/code][i][/codeblock]", 0, &limits).is_ok());
+    }
+
+    #[test]
+    fn validate_ref_link_count() {
+        let limits = PostLimits {
+            max_ref_links: 2,
+            ..PostLimits::default()
+        };
+        assert!(Post::validate(">>1 >>2", 0, &limits).is_ok());
+        assert_eq!(
+            vec!["too_many_ref_links"],
+            error_codes(">>1 >>2 >>3", 0, &limits)
+        );
+    }
+
+    #[test]
+    fn validate_link_count() {
+        let limits = PostLimits {
+            max_links: 1,
+            ..PostLimits::default()
+        };
+        assert!(Post::validate("https://example.com/", 0, &limits).is_ok());
+        assert_eq!(
+            vec!["too_many_links"],
+            error_codes("https://example.com/ https://example.org/", 0, &limits),
+        );
+    }
 }
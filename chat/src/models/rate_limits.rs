@@ -0,0 +1,219 @@
+use crate::schema::rate_limit_buckets;
+use chrono::prelude::*;
+
+/// Token bucket settings: up to `capacity` actions in a burst, refilled at
+/// `refill_per_second` tokens per second.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub capacity: f64,
+    pub refill_per_second: f64,
+}
+
+/// Tokens to take from the bucket stored under `key`, which has the given limit.
+#[derive(Debug, Clone)]
+pub struct RateLimitCharge {
+    pub key: String,
+    pub limit: RateLimit,
+    pub cost: f64,
+}
+
+impl RateLimitCharge {
+    pub fn new(key: &str, limit: &RateLimit, cost: f64) -> RateLimitCharge {
+        RateLimitCharge {
+            key: key.to_string(),
+            limit: *limit,
+            cost: cost.min(limit.capacity),
+        }
+    }
+}
+
+#[derive(Identifiable, Queryable, Insertable, AsChangeset, Debug, Clone)]
+#[table_name = "rate_limit_buckets"]
+#[primary_key(key)]
+pub struct RateLimitBucket {
+    pub key: String,
+    pub tokens: f64,
+    pub updated_at: NaiveDateTime,
+}
+
+impl RateLimitBucket {
+    pub fn new(key: &str, limit: &RateLimit, now: NaiveDateTime) -> RateLimitBucket {
+        RateLimitBucket {
+            key: key.to_string(),
+            tokens: limit.capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: NaiveDateTime) {
+        let elapsed = (now - self.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        self.tokens = (self.tokens + elapsed * limit.refill_per_second).min(limit.capacity);
+        self.updated_at = now;
+    }
+
+    /// Returns true if the bucket would be full at the given time, so it can be forgotten.
+    pub fn is_full(&self, limit: &RateLimit, now: NaiveDateTime) -> bool {
+        let mut bucket = self.clone();
+        bucket.refill(limit, now);
+        bucket.tokens >= limit.capacity
+    }
+
+    /// Takes `cost` tokens from the bucket. On failure returns the number of
+    /// seconds after which the same request would succeed.
+    pub fn take(&mut self, limit: &RateLimit, cost: f64, now: NaiveDateTime) -> Result<(), u64> {
+        let charge = RateLimitCharge::new(&self.key, limit, cost);
+        RateLimitBucket::take_all(std::slice::from_mut(self), &[charge], now)
+    }
+
+    /// Takes the tokens of each charge from the bucket at the same position if all of them
+    /// have enough, otherwise takes none. On failure returns the number of seconds after which
+    /// the same request would succeed.
+    pub fn take_all(
+        buckets: &mut [RateLimitBucket],
+        charges: &[RateLimitCharge],
+        now: NaiveDateTime,
+    ) -> Result<(), u64> {
+        let retry_after = buckets
+            .iter_mut()
+            .zip(charges)
+            .filter_map(|(bucket, charge)| {
+                bucket.refill(&charge.limit, now);
+                bucket.get_retry_after(&charge.limit, charge.cost)
+            })
+            .max();
+
+        if let Some(retry_after) = retry_after {
+            return Err(retry_after);
+        }
+
+        for (bucket, charge) in buckets.iter_mut().zip(charges) {
+            bucket.tokens -= charge.cost;
+        }
+
+        Ok(())
+    }
+
+    fn get_retry_after(&self, limit: &RateLimit, cost: f64) -> Option<u64> {
+        if self.tokens >= cost {
+            return None;
+        }
+
+        if limit.refill_per_second <= 0.0 {
+            return Some(u64::MAX);
+        }
+
+        let retry_after = (cost - self.tokens) / limit.refill_per_second;
+        Some(retry_after.ceil().max(1.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, RateLimitBucket, RateLimitCharge};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    const LIMIT: RateLimit = RateLimit {
+        capacity: 3.0,
+        refill_per_second: 0.5,
+    };
+
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 7, 18)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn take_until_empty() {
+        let mut bucket = RateLimitBucket::new("test", &LIMIT, now());
+
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 1.0, now()));
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 1.0, now()));
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 1.0, now()));
+        assert_eq!(Err(2), bucket.take(&LIMIT, 1.0, now()));
+    }
+
+    #[test]
+    fn take_after_refill() {
+        let mut bucket = RateLimitBucket::new("test", &LIMIT, now());
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 3.0, now()));
+
+        let later = now() + Duration::seconds(1);
+        assert_eq!(Err(1), bucket.take(&LIMIT, 1.0, later));
+
+        let later = now() + Duration::seconds(2);
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 1.0, later));
+    }
+
+    #[test]
+    fn refill_up_to_capacity() {
+        let mut bucket = RateLimitBucket::new("test", &LIMIT, now());
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 1.0, now()));
+        assert!(!bucket.is_full(&LIMIT, now()));
+
+        let later = now() + Duration::hours(1);
+        assert!(bucket.is_full(&LIMIT, later));
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 3.0, later));
+        assert_eq!(Err(2), bucket.take(&LIMIT, 1.0, later));
+    }
+
+    #[test]
+    fn take_all_or_nothing() {
+        let mut buckets = vec![
+            RateLimitBucket::new("user", &LIMIT, now()),
+            RateLimitBucket::new("ip", &LIMIT, now()),
+        ];
+        assert_eq!(Ok(()), buckets[1].take(&LIMIT, 3.0, now()));
+        let charges = vec![
+            RateLimitCharge::new("user", &LIMIT, 1.0),
+            RateLimitCharge::new("ip", &LIMIT, 1.0),
+        ];
+
+        let result = RateLimitBucket::take_all(&mut buckets, &charges, now());
+        assert_eq!(Err(2), result);
+        assert_eq!(3.0, buckets[0].tokens);
+        assert_eq!(0.0, buckets[1].tokens);
+
+        let later = now() + Duration::seconds(2);
+        let result = RateLimitBucket::take_all(&mut buckets, &charges, later);
+        assert_eq!(Ok(()), result);
+        assert_eq!(2.0, buckets[0].tokens);
+        assert_eq!(0.0, buckets[1].tokens);
+    }
+
+    #[test]
+    fn take_all_with_different_limits() {
+        let files = RateLimit {
+            capacity: 10.0,
+            refill_per_second: 1.0,
+        };
+        let mut buckets = vec![
+            RateLimitBucket::new("posts", &LIMIT, now()),
+            RateLimitBucket::new("files", &files, now()),
+        ];
+        let charges = vec![
+            RateLimitCharge::new("posts", &LIMIT, 1.0),
+            RateLimitCharge::new("files", &files, 6.0),
+        ];
+
+        assert_eq!(
+            Ok(()),
+            RateLimitBucket::take_all(&mut buckets, &charges, now())
+        );
+        assert_eq!(
+            Err(2),
+            RateLimitBucket::take_all(&mut buckets, &charges, now())
+        );
+        assert_eq!(2.0, buckets[0].tokens);
+        assert_eq!(4.0, buckets[1].tokens);
+    }
+
+    #[test]
+    fn take_more_than_capacity() {
+        let mut bucket = RateLimitBucket::new("test", &LIMIT, now());
+        assert_eq!(Ok(()), bucket.take(&LIMIT, 5.0, now()));
+        assert_eq!(Err(6), bucket.take(&LIMIT, 5.0, now()));
+    }
+}
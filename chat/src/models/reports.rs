@@ -0,0 +1,59 @@
+use super::posts::Post;
+use crate::schema::reports;
+use chrono::prelude::*;
+use serde::Serialize;
+
+pub const MAX_REASON_LENGTH: usize = 1000;
+
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_RESOLVED: &str = "resolved";
+pub const STATUS_DISMISSED: &str = "dismissed";
+
+#[derive(Insertable)]
+#[table_name = "reports"]
+pub struct NewReport {
+    pub post_id: i32,
+    pub reason: String,
+    pub reported_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Clone)]
+#[belongs_to(Post)]
+pub struct Report {
+    pub id: i32,
+    pub post_id: i32,
+    pub reason: String,
+    pub reported_by: String,
+    pub created_at: NaiveDateTime,
+    pub status: String,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<NaiveDateTime>,
+}
+
+impl Report {
+    pub fn new(post_id: i32, reason: &str, reported_by: &str) -> Result<NewReport, String> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(String::from("Reason must not be empty"));
+        }
+
+        if reason.chars().count() > MAX_REASON_LENGTH {
+            return Err(format!(
+                "Reason must be at most {} characters long",
+                MAX_REASON_LENGTH
+            ));
+        }
+
+        Ok(NewReport {
+            post_id,
+            reason: reason.to_string(),
+            reported_by: reported_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+
+    pub fn is_valid_resolution(status: &str) -> bool {
+        status == STATUS_RESOLVED || status == STATUS_DISMISSED
+    }
+}
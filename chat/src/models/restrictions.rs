@@ -0,0 +1,116 @@
+use crate::schema::user_restrictions;
+use chrono::prelude::*;
+use serde::Serialize;
+
+pub const MAX_REASON_LENGTH: usize = 1000;
+
+/// The user can't post at all.
+pub const KIND_BAN: &str = "ban";
+
+/// The user can post, but the posts are only visible to the user and moderators.
+pub const KIND_QUARANTINE: &str = "quarantine";
+
+#[derive(Insertable)]
+#[table_name = "user_restrictions"]
+pub struct NewUserRestriction {
+    pub user_uuid: String,
+    pub kind: String,
+    pub reason: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+#[table_name = "user_restrictions"]
+pub struct UserRestriction {
+    pub id: i32,
+    pub user_uuid: String,
+    pub kind: String,
+    pub reason: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub lifted_at: Option<NaiveDateTime>,
+}
+
+impl UserRestriction {
+    pub fn is_valid_kind(kind: &str) -> bool {
+        kind == KIND_BAN || kind == KIND_QUARANTINE
+    }
+
+    pub fn new(
+        user_uuid: &str,
+        kind: &str,
+        reason: &str,
+        expires_at: Option<NaiveDateTime>,
+        created_by: &str,
+    ) -> Result<NewUserRestriction, String> {
+        if user_uuid.len() != 36 {
+            return Err(String::from("Invalid user UUID"));
+        }
+
+        if !UserRestriction::is_valid_kind(kind) {
+            return Err(format!("Unknown restriction kind: {}", kind));
+        }
+
+        if reason.chars().count() > MAX_REASON_LENGTH {
+            return Err(format!(
+                "Reason must be at most {} characters long",
+                MAX_REASON_LENGTH
+            ));
+        }
+
+        let created_at = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        if let Some(expires_at) = expires_at {
+            if expires_at <= created_at {
+                return Err(String::from("Expiration time must be in the future"));
+            }
+        }
+
+        Ok(NewUserRestriction {
+            user_uuid: user_uuid.to_string(),
+            kind: kind.to_string(),
+            reason: reason.trim().to_string(),
+            expires_at,
+            created_by: created_by.to_string(),
+            created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+    #[test]
+    fn new_accepts_ban() {
+        let restriction = UserRestriction::new(UUID, KIND_BAN, " spam ", None, UUID).unwrap();
+        assert_eq!(restriction.kind, KIND_BAN);
+        assert_eq!(restriction.reason, "spam");
+    }
+
+    #[test]
+    fn new_accepts_quarantine() {
+        assert!(UserRestriction::new(UUID, KIND_QUARANTINE, "", None, UUID).is_ok());
+    }
+
+    #[test]
+    fn new_rejects_unknown_kind() {
+        assert!(UserRestriction::new(UUID, "mute", "", None, UUID).is_err());
+    }
+
+    #[test]
+    fn new_rejects_invalid_uuid() {
+        assert!(UserRestriction::new("user", KIND_BAN, "", None, UUID).is_err());
+    }
+
+    #[test]
+    fn new_rejects_past_expiration() {
+        let expires_at = Utc::now().naive_utc() - Duration::hours(1);
+        assert!(UserRestriction::new(UUID, KIND_BAN, "", Some(expires_at), UUID).is_err());
+    }
+}
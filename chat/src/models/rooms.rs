@@ -0,0 +1,163 @@
+use crate::schema::rooms;
+use chrono::prelude::*;
+use serde::Serialize;
+
+/// The room created by the migration, which takes the posts made without a room.
+pub const DEFAULT_ROOM_SLUG: &str = "general";
+
+const MAX_SLUG_LENGTH: usize = 32;
+const MAX_TITLE_LENGTH: usize = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+#[derive(Insertable)]
+#[table_name = "rooms"]
+pub struct NewRoom {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub nsfw: bool,
+    pub allowed_file_types: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+pub struct Room {
+    pub id: i32,
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub nsfw: bool,
+    pub allowed_file_types: Vec<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Room {
+    fn is_valid_slug(slug: &str) -> bool {
+        !slug.is_empty()
+            && slug.len() <= MAX_SLUG_LENGTH
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    }
+
+    /// File types are either full mimetypes like `image/png` or prefixes like `image/`.
+    fn is_valid_file_type(file_type: &str) -> bool {
+        match file_type.find('/') {
+            Some(index) => index > 0 && !file_type.contains(char::is_whitespace),
+            None => false,
+        }
+    }
+
+    pub fn new(
+        slug: &str,
+        title: &str,
+        description: &str,
+        nsfw: bool,
+        allowed_file_types: Vec<String>,
+    ) -> Result<NewRoom, String> {
+        if !Room::is_valid_slug(slug) {
+            return Err(format!(
+                "Slug must be 1 to {} lowercase letters, digits or dashes",
+                MAX_SLUG_LENGTH
+            ));
+        }
+
+        let title = title.trim();
+        if title.is_empty() || title.chars().count() > MAX_TITLE_LENGTH {
+            return Err(format!(
+                "Title must be 1 to {} characters long",
+                MAX_TITLE_LENGTH
+            ));
+        }
+
+        let description = description.trim();
+        if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(format!(
+                "Description must be at most {} characters long",
+                MAX_DESCRIPTION_LENGTH
+            ));
+        }
+
+        if let Some(file_type) = allowed_file_types
+            .iter()
+            .find(|file_type| !Room::is_valid_file_type(file_type))
+        {
+            return Err(format!("Invalid file type: {}", file_type));
+        }
+
+        Ok(NewRoom {
+            slug: slug.to_string(),
+            title: title.to_string(),
+            description: description.to_string(),
+            nsfw,
+            allowed_file_types,
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+
+    /// Rooms without a list of file types allow any file.
+    pub fn allows_file_type(&self, mimetype: &str) -> bool {
+        self.allowed_file_types.is_empty()
+            || self.allowed_file_types.iter().any(|file_type| {
+                if file_type.ends_with('/') {
+                    mimetype.starts_with(file_type.as_str())
+                } else {
+                    mimetype == file_type
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(allowed_file_types: Vec<&str>) -> Room {
+        Room {
+            id: 1,
+            slug: String::from("test"),
+            title: String::from("Test"),
+            description: String::new(),
+            nsfw: false,
+            allowed_file_types: allowed_file_types.into_iter().map(String::from).collect(),
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+        }
+    }
+
+    #[test]
+    fn new_validates_slug() {
+        assert!(Room::new("music-2", "Music", "", false, vec![]).is_ok());
+        assert!(Room::new("", "Music", "", false, vec![]).is_err());
+        assert!(Room::new("Music", "Music", "", false, vec![]).is_err());
+        assert!(Room::new("a/b", "Music", "", false, vec![]).is_err());
+    }
+
+    #[test]
+    fn new_validates_title() {
+        assert!(Room::new("music", "  ", "", false, vec![]).is_err());
+        let room = Room::new("music", " Music ", "", false, vec![]).unwrap();
+        assert_eq!(room.title, "Music");
+    }
+
+    #[test]
+    fn new_validates_file_types() {
+        let valid = vec![String::from("image/"), String::from("video/webm")];
+        assert!(Room::new("music", "Music", "", false, valid).is_ok());
+        let invalid = vec![String::from("image")];
+        assert!(Room::new("music", "Music", "", false, invalid).is_err());
+    }
+
+    #[test]
+    fn allows_any_file_type_by_default() {
+        assert!(room(vec![]).allows_file_type("application/zip"));
+    }
+
+    #[test]
+    fn allows_file_type_by_prefix_or_exact_match() {
+        let room = room(vec!["image/", "video/webm"]);
+        assert!(room.allows_file_type("image/png"));
+        assert!(room.allows_file_type("video/webm"));
+        assert!(!room.allows_file_type("video/mp4"));
+        assert!(!room.allows_file_type("audio/mpeg"));
+    }
+}
@@ -0,0 +1,182 @@
+use super::posts::FieldError;
+use crate::schema::settings;
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const KEY_SLOW_MODE_SECONDS: &str = "slow_mode_seconds";
+pub const KEY_READ_ONLY: &str = "read_only";
+
+#[derive(Identifiable, Queryable, Insertable, AsChangeset, Clone)]
+#[table_name = "settings"]
+#[primary_key(key)]
+pub struct Setting {
+    pub key: String,
+    pub value: Value,
+    pub updated_by: String,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Instance-wide switches changeable by admins at runtime.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettings {
+    /// Minimum number of seconds between posts of the same user, 0 to disable.
+    pub slow_mode_seconds: i64,
+    /// Only moderators can post.
+    pub read_only: bool,
+}
+
+impl ChatSettings {
+    /// Builds the settings from the stored rows, keeping defaults for missing or invalid ones.
+    pub fn from_rows(rows: &[Setting]) -> ChatSettings {
+        let mut settings = ChatSettings::default();
+        for row in rows {
+            match row.key.as_str() {
+                KEY_SLOW_MODE_SECONDS => {
+                    if let Some(value) = row.value.as_i64() {
+                        settings.slow_mode_seconds = value.max(0);
+                    }
+                }
+                KEY_READ_ONLY => {
+                    if let Some(value) = row.value.as_bool() {
+                        settings.read_only = value;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        settings
+    }
+
+    pub fn to_rows(&self, updated_by: &str) -> Vec<Setting> {
+        let updated_at = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        let row = |key: &str, value: Value| Setting {
+            key: key.to_string(),
+            value,
+            updated_by: updated_by.to_string(),
+            updated_at,
+        };
+
+        vec![
+            row(KEY_SLOW_MODE_SECONDS, Value::from(self.slow_mode_seconds)),
+            row(KEY_READ_ONLY, Value::from(self.read_only)),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.slow_mode_seconds < 0 || self.slow_mode_seconds > 24 * 60 * 60 {
+            return Err(String::from(
+                "Slow mode must be between 0 and 86400 seconds",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a user may post now. Moderators are exempt from both modes.
+    pub fn check_post(
+        &self,
+        is_moderator: bool,
+        last_post_at: Option<NaiveDateTime>,
+        now: NaiveDateTime,
+    ) -> Result<(), FieldError> {
+        if is_moderator {
+            return Ok(());
+        }
+
+        if self.read_only {
+            return Err(FieldError::new(
+                "message",
+                "read_only",
+                String::from("Chat is in read-only mode"),
+            ));
+        }
+
+        if let Some(last_post_at) = last_post_at {
+            let elapsed = (now - last_post_at).num_seconds();
+            if elapsed < self.slow_mode_seconds {
+                return Err(FieldError::new(
+                    "message",
+                    "slow_mode",
+                    format!(
+                        "Slow mode is enabled, wait {} seconds before posting again",
+                        self.slow_mode_seconds - elapsed
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(1_600_000_000, 0)
+    }
+
+    #[test]
+    fn rows_round_trip() {
+        let settings = ChatSettings {
+            slow_mode_seconds: 30,
+            read_only: true,
+        };
+        let rows = settings.to_rows("uuid");
+        assert_eq!(ChatSettings::from_rows(&rows), settings);
+    }
+
+    #[test]
+    fn from_rows_ignores_invalid_values() {
+        let mut rows = ChatSettings::default().to_rows("uuid");
+        rows[0].value = Value::from("fast");
+        rows.push(Setting {
+            key: String::from("unknown"),
+            value: Value::Null,
+            updated_by: String::new(),
+            updated_at: now(),
+        });
+        assert_eq!(ChatSettings::from_rows(&rows), ChatSettings::default());
+    }
+
+    #[test]
+    fn validate_slow_mode_range() {
+        let mut settings = ChatSettings {
+            slow_mode_seconds: -1,
+            ..ChatSettings::default()
+        };
+        assert!(settings.validate().is_err());
+        settings.slow_mode_seconds = 60;
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn check_post_read_only() {
+        let settings = ChatSettings {
+            slow_mode_seconds: 0,
+            read_only: true,
+        };
+        let error = settings.check_post(false, None, now()).unwrap_err();
+        assert_eq!(error.code, "read_only");
+        assert!(settings.check_post(true, None, now()).is_ok());
+    }
+
+    #[test]
+    fn check_post_slow_mode() {
+        let settings = ChatSettings {
+            slow_mode_seconds: 30,
+            read_only: false,
+        };
+        let recent = Some(now() - Duration::seconds(10));
+        let old = Some(now() - Duration::seconds(30));
+        let error = settings.check_post(false, recent, now()).unwrap_err();
+        assert_eq!(error.code, "slow_mode");
+        assert!(settings.check_post(false, old, now()).is_ok());
+        assert!(settings.check_post(false, None, now()).is_ok());
+        assert!(settings.check_post(true, recent, now()).is_ok());
+    }
+}
@@ -0,0 +1,201 @@
+use super::message_parser::{MessageParser, Token};
+use super::posts::FieldError;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone)]
+pub struct SpamOptions {
+    /// How far back to look for the previous posts of the same user.
+    pub window_seconds: i64,
+    /// Messages shorter than this after normalization are never considered duplicates,
+    /// so that short replies like "ok" can be repeated.
+    pub min_message_length: usize,
+    /// Number of characters in a shingle.
+    pub shingle_size: usize,
+    /// Jaccard similarity of shingle sets above which messages are considered duplicates.
+    pub similarity_threshold: f64,
+    /// How many times the same file can be posted within the window.
+    pub max_repeated_files: usize,
+}
+
+impl Default for SpamOptions {
+    fn default() -> SpamOptions {
+        SpamOptions {
+            window_seconds: 300,
+            min_message_length: 16,
+            shingle_size: 4,
+            similarity_threshold: 0.8,
+            max_repeated_files: 3,
+        }
+    }
+}
+
+pub struct SpamFilter();
+
+impl SpamFilter {
+    /// Removes markup, lowercases the message and strips everything except letters and digits,
+    /// so that changes in punctuation, markup or whitespace don't make a message look different.
+    pub fn normalize(message: &str) -> String {
+        let tokens = MessageParser::tokenize(message).unwrap_or_default();
+        let text: String = tokens
+            .into_iter()
+            .map(|token| match token {
+                Token::Text(text) | Token::Link(text) | Token::Emoji(text) => text,
+                Token::RefLink(id) => id.to_string(),
+                Token::Dice(count, max) => format!("{}d{}", count, max),
+                Token::OpeningTag(_) | Token::ClosingTag(_) => String::new(),
+            })
+            .collect();
+
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(|c| c.to_lowercase())
+            .collect()
+    }
+
+    pub fn shingles(message: &str, size: usize) -> HashSet<u64> {
+        let chars: Vec<char> = message.chars().collect();
+        let size = size.max(1).min(chars.len().max(1));
+
+        chars
+            .windows(size)
+            .map(|window| {
+                let mut hasher = DefaultHasher::new();
+                window.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect()
+    }
+
+    pub fn similarity(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+        let union = a.union(b).count();
+        if union == 0 {
+            return 1.0;
+        }
+
+        a.intersection(b).count() as f64 / union as f64
+    }
+
+    /// Checks the message against the recent messages of the same user.
+    pub fn check_message(
+        message: &str,
+        recent_messages: &[String],
+        options: &SpamOptions,
+    ) -> Result<(), FieldError> {
+        let normalized = SpamFilter::normalize(message);
+        if normalized.chars().count() < options.min_message_length {
+            return Ok(());
+        }
+
+        let shingles = SpamFilter::shingles(&normalized, options.shingle_size);
+        let is_duplicate = recent_messages.iter().any(|recent| {
+            let recent = SpamFilter::normalize(recent);
+            if recent == normalized {
+                return true;
+            }
+
+            let recent = SpamFilter::shingles(&recent, options.shingle_size);
+            SpamFilter::similarity(&shingles, &recent) >= options.similarity_threshold
+        });
+
+        if is_duplicate {
+            Err(FieldError::new(
+                "message",
+                "duplicate",
+                String::from("You have already posted this message recently"),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks how many times each file has been posted recently by the same user.
+    pub fn check_files(
+        recent_file_counts: &[usize],
+        options: &SpamOptions,
+    ) -> Result<(), FieldError> {
+        if recent_file_counts
+            .iter()
+            .any(|&count| count >= options.max_repeated_files)
+        {
+            Err(FieldError::new(
+                "files",
+                "duplicate",
+                String::from("You have already posted this file too many times recently"),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpamFilter, SpamOptions};
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            "buycheapwatches",
+            SpamFilter::normalize("[b]Buy CHEAP[/b]  watches!!!")
+        );
+        assert_eq!("привет", SpamFilter::normalize("ПРИВЕТ."));
+    }
+
+    #[test]
+    fn check_identical_message() {
+        let options = SpamOptions::default();
+        let recent = vec![String::from("Buy cheap watches at example.com")];
+
+        assert!(
+            SpamFilter::check_message("buy cheap watches at example.com!", &recent, &options)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn check_near_identical_message() {
+        let options = SpamOptions::default();
+        let recent = vec![String::from("Buy cheap watches at example.com right now")];
+
+        assert!(SpamFilter::check_message(
+            "Buy cheap watches at example.com right now 2",
+            &recent,
+            &options
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn check_different_message() {
+        let options = SpamOptions::default();
+        let recent = vec![String::from("Buy cheap watches at example.com")];
+
+        assert!(SpamFilter::check_message(
+            "Has anyone seen the new episode yet?",
+            &recent,
+            &options
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn check_short_message() {
+        let options = SpamOptions::default();
+        let recent = vec![String::from("ok"), String::from("ok")];
+
+        assert!(SpamFilter::check_message("ok", &recent, &options).is_ok());
+    }
+
+    #[test]
+    fn check_files() {
+        let options = SpamOptions {
+            max_repeated_files: 2,
+            ..SpamOptions::default()
+        };
+
+        assert!(SpamFilter::check_files(&[0, 1], &options).is_ok());
+        assert!(SpamFilter::check_files(&[0, 2], &options).is_err());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::schema::{webhook_deliveries, webhooks};
+use chrono::prelude::*;
+use chrono::Duration;
+use hmac::{Hmac, Mac, NewMac};
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Events that can be delivered to webhooks. Only public events are sent out, so notifications,
+/// reports and direct messages, which are meant for some users only, are not among them.
+pub const EVENT_TYPES: &[&str] = &["post_created", "post_hidden", "settings_changed"];
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_DELIVERED: &str = "delivered";
+pub const STATUS_FAILED: &str = "failed";
+
+/// Header carrying the hex encoded HMAC-SHA256 of the body, prefixed with `sha256=`.
+pub const SIGNATURE_HEADER: &str = "X-Chat-Signature";
+pub const EVENT_HEADER: &str = "X-Chat-Event";
+pub const DELIVERY_HEADER: &str = "X-Chat-Delivery";
+
+/// Deliveries are given up after this many failed attempts.
+pub const MAX_ATTEMPTS: i32 = 8;
+const FIRST_RETRY_SECONDS: i64 = 30;
+const MAX_RETRY_SECONDS: i64 = 60 * 60;
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+
+const MIN_SECRET_LENGTH: usize = 16;
+const MAX_FILTER_LENGTH: usize = 200;
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "webhooks"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewWebhook {
+    pub url: String,
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub message_filter: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// An endpoint receiving the events. The secret is never sent back by the API nor written to the
+/// audit log.
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub message_filter: Option<String>,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_deliveries"]
+pub struct NewWebhookDelivery {
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Clone, Debug)]
+#[belongs_to(Webhook)]
+#[table_name = "webhook_deliveries"]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// The state of a delivery after an attempt.
+#[derive(AsChangeset, Debug, PartialEq)]
+#[table_name = "webhook_deliveries"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct WebhookAttempt {
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// What the receiver answered, if anything.
+#[derive(Debug, PartialEq)]
+pub struct DeliveryResult {
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+impl DeliveryResult {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Webhook {
+    fn is_valid_url(url: &str) -> bool {
+        let rest = match url.strip_prefix("https://") {
+            Some(rest) => rest,
+            None => match url.strip_prefix("http://") {
+                Some(rest) => rest,
+                None => return false,
+            },
+        };
+
+        !rest.is_empty() && !rest.starts_with('/') && !url.contains(char::is_whitespace)
+    }
+
+    pub fn new(
+        url: &str,
+        secret: &str,
+        event_types: Vec<String>,
+        message_filter: Option<&str>,
+        created_by: &str,
+    ) -> Result<NewWebhook, String> {
+        let url = url.trim();
+        if !Webhook::is_valid_url(url) {
+            return Err(String::from("URL must be an absolute http or https URL"));
+        }
+
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(format!(
+                "Secret must be at least {} characters long",
+                MIN_SECRET_LENGTH
+            ));
+        }
+
+        if event_types.is_empty() {
+            return Err(String::from("At least one event type is required"));
+        }
+
+        if let Some(event_type) = event_types
+            .iter()
+            .find(|event_type| !EVENT_TYPES.contains(&event_type.as_str()))
+        {
+            return Err(format!("Unknown event type: {}", event_type));
+        }
+
+        let message_filter = message_filter
+            .map(str::trim)
+            .filter(|filter| !filter.is_empty());
+        if let Some(filter) = message_filter {
+            if filter.chars().count() > MAX_FILTER_LENGTH {
+                return Err(format!(
+                    "Message filter must be at most {} characters long",
+                    MAX_FILTER_LENGTH
+                ));
+            }
+        }
+
+        Ok(NewWebhook {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            event_types,
+            message_filter: message_filter.map(str::to_string),
+            created_by: created_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+
+    /// Whether the event should be delivered. With a message filter, only events carrying a
+    /// message that contains it, ignoring case, are delivered.
+    pub fn matches(&self, event_type: &str, message: Option<&str>) -> bool {
+        if !self.event_types.iter().any(|item| item == event_type) {
+            return false;
+        }
+
+        match (&self.message_filter, message) {
+            (None, _) => true,
+            (Some(filter), Some(message)) => {
+                message.to_lowercase().contains(&filter.to_lowercase())
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Signs the body with the secret, in the format of the signature header.
+    pub fn sign(secret: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+impl WebhookDelivery {
+    pub fn new(
+        webhook_id: i32,
+        event_id: i64,
+        event_type: &str,
+        payload: &str,
+    ) -> NewWebhookDelivery {
+        let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        NewWebhookDelivery {
+            webhook_id,
+            event_id,
+            event_type: event_type.to_string(),
+            payload: payload.to_string(),
+            status: STATUS_PENDING.to_string(),
+            attempts: 0,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Delay before the next attempt after the given number of failed ones, doubling every time.
+    pub fn get_retry_delay(attempts: i32) -> Duration {
+        let exponent = (attempts.max(1) - 1).min(16) as u32;
+        let seconds = FIRST_RETRY_SECONDS.saturating_mul(2i64.pow(exponent));
+        Duration::seconds(seconds.min(MAX_RETRY_SECONDS))
+    }
+
+    /// POSTs the payload to the webhook. Anything but a 2xx response is a failure.
+    pub fn send(&self, webhook: &Webhook) -> DeliveryResult {
+        let response = ureq::post(&webhook.url)
+            .timeout(std::time::Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+            .set("Content-Type", "application/json")
+            .set(EVENT_HEADER, &self.event_type)
+            .set(DELIVERY_HEADER, &self.id.to_string())
+            .set(
+                SIGNATURE_HEADER,
+                &Webhook::sign(&webhook.secret, &self.payload),
+            )
+            .send_string(&self.payload);
+
+        if let Some(error) = response.synthetic_error() {
+            return DeliveryResult {
+                response_status: None,
+                error: Some(error.to_string()),
+            };
+        }
+
+        let status = response.status();
+        DeliveryResult {
+            response_status: Some(status as i32),
+            error: if (200..300).contains(&status) {
+                None
+            } else {
+                Some(format!("Unexpected response status {}", status))
+            },
+        }
+    }
+
+    /// Returns the state of the delivery after an attempt made at `now`: delivered, scheduled for
+    /// a retry, or failed once out of attempts.
+    pub fn attempted(&self, result: DeliveryResult, now: NaiveDateTime) -> WebhookAttempt {
+        let attempts = self.attempts + 1;
+        let (status, next_attempt_at) = if result.is_success() {
+            (STATUS_DELIVERED, now)
+        } else if attempts >= MAX_ATTEMPTS {
+            (STATUS_FAILED, now)
+        } else {
+            (
+                STATUS_PENDING,
+                now + WebhookDelivery::get_retry_delay(attempts),
+            )
+        };
+
+        WebhookAttempt {
+            status: status.to_string(),
+            attempts,
+            next_attempt_at,
+            response_status: result.response_status,
+            error: result.error,
+            updated_at: now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(event_types: Vec<&str>, message_filter: Option<&str>) -> Webhook {
+        Webhook {
+            id: 1,
+            url: String::from("https://example.com/hook"),
+            secret: String::from("0123456789abcdef"),
+            event_types: event_types.into_iter().map(String::from).collect(),
+            message_filter: message_filter.map(String::from),
+            created_by: String::from("00000000-0000-0000-0000-000000000000"),
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+        }
+    }
+
+    fn delivery(attempts: i32) -> WebhookDelivery {
+        WebhookDelivery {
+            id: 1,
+            webhook_id: 1,
+            event_id: 1,
+            event_type: String::from("post_created"),
+            payload: String::from("{}"),
+            status: String::from(STATUS_PENDING),
+            attempts,
+            next_attempt_at: NaiveDateTime::from_timestamp(0, 0),
+            response_status: None,
+            error: None,
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            updated_at: NaiveDateTime::from_timestamp(0, 0),
+        }
+    }
+
+    fn failure() -> DeliveryResult {
+        DeliveryResult {
+            response_status: Some(500),
+            error: Some(String::from("Unexpected response status 500")),
+        }
+    }
+
+    #[test]
+    fn new_validates_url() {
+        let types = || vec![String::from("post_created")];
+        let user = "00000000-0000-0000-0000-000000000000";
+        let secret = "0123456789abcdef";
+        assert!(Webhook::new("https://example.com/hook", secret, types(), None, user).is_ok());
+        assert!(Webhook::new("http://localhost:8080", secret, types(), None, user).is_ok());
+        assert!(Webhook::new("ftp://example.com", secret, types(), None, user).is_err());
+        assert!(Webhook::new("https://", secret, types(), None, user).is_err());
+        assert!(Webhook::new("https://a b", secret, types(), None, user).is_err());
+    }
+
+    #[test]
+    fn new_validates_secret_and_event_types() {
+        let url = "https://example.com/hook";
+        let user = "00000000-0000-0000-0000-000000000000";
+        let secret = "0123456789abcdef";
+        let types = vec![String::from("post_created")];
+        assert!(Webhook::new(url, "short", types.clone(), None, user).is_err());
+        assert!(Webhook::new(url, secret, vec![], None, user).is_err());
+        let unknown = vec![String::from("direct_message_created")];
+        assert!(Webhook::new(url, secret, unknown, None, user).is_err());
+        let private = vec![String::from("report_created")];
+        assert!(Webhook::new(url, secret, private, None, user).is_err());
+
+        let webhook = Webhook::new(url, secret, types, Some("  "), user).unwrap();
+        assert_eq!(webhook.message_filter, None);
+    }
+
+    #[test]
+    fn matches_event_types() {
+        let webhook = webhook(vec!["post_created", "post_hidden"], None);
+        assert!(webhook.matches("post_created", Some("hello")));
+        assert!(webhook.matches("post_hidden", None));
+        assert!(!webhook.matches("notification_created", Some("hello")));
+    }
+
+    #[test]
+    fn matches_message_filter() {
+        let webhook = webhook(vec!["post_created", "post_hidden"], Some("Deploy"));
+        assert!(webhook.matches("post_created", Some("please deploy now")));
+        assert!(!webhook.matches("post_created", Some("hello")));
+        assert!(!webhook.matches("post_hidden", None));
+    }
+
+    #[test]
+    fn sign_matches_known_value() {
+        // HMAC-SHA256 test case 2 from RFC 4231.
+        let signature = Webhook::sign("Jefe", "what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_limit() {
+        assert_eq!(WebhookDelivery::get_retry_delay(1), Duration::seconds(30));
+        assert_eq!(WebhookDelivery::get_retry_delay(2), Duration::seconds(60));
+        assert_eq!(WebhookDelivery::get_retry_delay(3), Duration::seconds(120));
+        assert_eq!(WebhookDelivery::get_retry_delay(20), Duration::hours(1));
+    }
+
+    #[test]
+    fn attempted_schedules_retries() {
+        let now = NaiveDateTime::from_timestamp(1000, 0);
+        let attempt = delivery(1).attempted(failure(), now);
+        assert_eq!(attempt.status, STATUS_PENDING);
+        assert_eq!(attempt.attempts, 2);
+        assert_eq!(attempt.next_attempt_at, now + Duration::seconds(60));
+        assert_eq!(attempt.response_status, Some(500));
+    }
+
+    #[test]
+    fn attempted_fails_after_max_attempts() {
+        let now = NaiveDateTime::from_timestamp(1000, 0);
+        let attempt = delivery(MAX_ATTEMPTS - 1).attempted(failure(), now);
+        assert_eq!(attempt.status, STATUS_FAILED);
+        assert_eq!(attempt.attempts, MAX_ATTEMPTS);
+    }
+
+    #[test]
+    fn attempted_marks_delivered() {
+        let now = NaiveDateTime::from_timestamp(1000, 0);
+        let result = DeliveryResult {
+            response_status: Some(204),
+            error: None,
+        };
+        let attempt = delivery(3).attempted(result, now);
+        assert_eq!(attempt.status, STATUS_DELIVERED);
+        assert_eq!(attempt.attempts, 4);
+        assert_eq!(attempt.error, None);
+    }
+}
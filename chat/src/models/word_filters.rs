@@ -0,0 +1,432 @@
+use super::message_parser::{EmojiMap, MessageParser, MessageParserOptions};
+use super::posts::Post;
+use crate::schema::{post_flags, word_filters};
+use chrono::prelude::*;
+use serde::Serialize;
+use std::ops::Range;
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+pub const ACTION_REPLACE: &str = "replace";
+pub const ACTION_REJECT: &str = "reject";
+pub const ACTION_FLAG: &str = "flag";
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "word_filters"]
+pub struct NewWordFilter {
+    pub pattern: String,
+    pub replacement: String,
+    pub action: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Serialize, Clone)]
+pub struct WordFilter {
+    pub id: i32,
+    pub pattern: String,
+    pub replacement: String,
+    pub action: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct WordFilterResult {
+    pub message: String,
+    pub rejected: bool,
+    pub flagged_by: Vec<i32>,
+}
+
+/// A character of the folded text along with the byte range of the original character
+/// it was produced from.
+struct FoldedChar {
+    c: char,
+    range: Range<usize>,
+}
+
+impl WordFilter {
+    pub fn new(
+        pattern: &str,
+        replacement: &str,
+        action: &str,
+        created_by: &str,
+    ) -> Result<NewWordFilter, String> {
+        if WordFilter::fold(pattern).is_empty() {
+            return Err(String::from("Pattern must not be empty"));
+        }
+
+        if ![ACTION_REPLACE, ACTION_REJECT, ACTION_FLAG].contains(&action) {
+            return Err(format!(
+                "Action must be one of: {}, {}, {}",
+                ACTION_REPLACE, ACTION_REJECT, ACTION_FLAG
+            ));
+        }
+
+        Ok(NewWordFilter {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            action: action.to_string(),
+            created_by: created_by.to_string(),
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        })
+    }
+
+    /// Maps characters commonly used to disguise latin letters to the letters themselves.
+    fn unconfuse(c: char) -> char {
+        match c {
+            'а' | 'α' | '@' | '4' => 'a',
+            'в' | 'β' => 'b',
+            'с' | 'ϲ' => 'c',
+            'е' | 'ε' | '3' => 'e',
+            'һ' => 'h',
+            'і' | 'ι' | '1' => 'i',
+            'ј' => 'j',
+            'к' | 'κ' => 'k',
+            'м' => 'm',
+            'п' | 'η' => 'n',
+            'о' | 'ο' | 'σ' | '0' => 'o',
+            'р' | 'ρ' => 'p',
+            'ѕ' | '$' | '5' => 's',
+            'т' | 'τ' | '7' => 't',
+            'υ' => 'u',
+            'ν' => 'v',
+            'ш' | 'ω' => 'w',
+            'х' | 'χ' => 'x',
+            'у' | 'γ' => 'y',
+            c => c,
+        }
+    }
+
+    /// Folds a character for comparison: applies compatibility decomposition, drops
+    /// diacritics and lowercases.
+    fn fold_char(c: char, mut f: impl FnMut(char)) {
+        decompose_compatible(c, |c| {
+            if is_combining_mark(c) {
+                return;
+            }
+
+            for c in c.to_lowercase() {
+                f(c);
+            }
+        });
+    }
+
+    /// Replaces homoglyphs only in words which contain latin letters, so that numbers and
+    /// words in other scripts aren't read as disguised latin words.
+    fn unconfuse_words(text: &mut [FoldedChar]) {
+        for word in text.split_mut(|folded| folded.c.is_whitespace()) {
+            if word.iter().any(|folded| folded.c.is_ascii_lowercase()) {
+                for folded in word {
+                    folded.c = WordFilter::unconfuse(folded.c);
+                }
+            }
+        }
+    }
+
+    fn fold(text: &str) -> String {
+        let result: String = WordFilter::fold_with_ranges(text, 0)
+            .into_iter()
+            .map(|folded| folded.c)
+            .collect();
+
+        result.trim().to_string()
+    }
+
+    fn fold_with_ranges(text: &str, offset: usize) -> Vec<FoldedChar> {
+        let mut result = Vec::new();
+        for (index, c) in text.char_indices() {
+            let range = (offset + index)..(offset + index + c.len_utf8());
+            WordFilter::fold_char(c, |c| {
+                result.push(FoldedChar {
+                    c,
+                    range: range.clone(),
+                })
+            });
+        }
+
+        WordFilter::unconfuse_words(&mut result);
+        result
+    }
+
+    /// Finds the occurrences of the pattern which are not parts of longer words.
+    fn find_matches(text: &[FoldedChar], pattern: &[char]) -> Vec<Range<usize>> {
+        let is_word_char = |index: usize| text[index].c.is_alphanumeric();
+        let mut matches = Vec::new();
+        let mut index = 0;
+
+        while index + pattern.len() <= text.len() {
+            let end = index + pattern.len();
+            let is_match = text[index..end]
+                .iter()
+                .zip(pattern)
+                .all(|(folded, &c)| folded.c == c)
+                && (index == 0 || !is_word_char(index - 1) || !pattern[0].is_alphanumeric())
+                && (end == text.len()
+                    || !is_word_char(end)
+                    || !pattern[pattern.len() - 1].is_alphanumeric());
+
+            if is_match {
+                matches.push(text[index].range.start..text[end - 1].range.end);
+                index = end;
+            } else {
+                index += 1;
+            }
+        }
+
+        matches
+    }
+
+    /// Applies the filters to the text of the message, leaving alone the markup the parser
+    /// would make of it with the given options and emoji.
+    pub fn apply_all(
+        message: &str,
+        filters: &[WordFilter],
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> WordFilterResult {
+        let mut rejected = false;
+        let mut flagged_by = Vec::new();
+        let mut message = message.to_string();
+
+        for filter in filters {
+            let pattern: Vec<char> = WordFilter::fold(&filter.pattern).chars().collect();
+            if pattern.is_empty() {
+                continue;
+            }
+
+            let mut matches = Vec::new();
+            for range in MessageParser::text_ranges(&message, emoji, options) {
+                let text = WordFilter::fold_with_ranges(&message[range.clone()], range.start);
+                matches.extend(WordFilter::find_matches(&text, &pattern));
+            }
+
+            if matches.is_empty() {
+                continue;
+            }
+
+            match filter.action.as_str() {
+                ACTION_REJECT => rejected = true,
+                ACTION_FLAG => flagged_by.push(filter.id),
+                _ => {
+                    for range in matches.into_iter().rev() {
+                        message.replace_range(range, &filter.replacement);
+                    }
+                }
+            }
+        }
+
+        WordFilterResult {
+            message,
+            rejected,
+            flagged_by,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "post_flags"]
+pub struct NewPostFlag {
+    pub post_id: i32,
+    pub word_filter_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Clone)]
+#[belongs_to(Post)]
+#[belongs_to(WordFilter)]
+pub struct PostFlag {
+    pub id: i32,
+    pub post_id: i32,
+    pub word_filter_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl PostFlag {
+    pub fn new(post_id: i32, word_filter_id: i32) -> NewPostFlag {
+        NewPostFlag {
+            post_id,
+            word_filter_id,
+            created_at: NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WordFilter, WordFilterResult, ACTION_FLAG, ACTION_REJECT, ACTION_REPLACE};
+    use crate::models::message_parser::{EmojiMap, MessageParserOptions};
+    use chrono::prelude::*;
+
+    fn filter(id: i32, pattern: &str, replacement: &str, action: &str) -> WordFilter {
+        WordFilter {
+            id,
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            action: action.to_string(),
+            created_by: String::new(),
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+        }
+    }
+
+    fn apply_all(message: &str, filters: &[WordFilter]) -> WordFilterResult {
+        let mut emoji = EmojiMap::new();
+        emoji.insert(String::from("cat"), String::from("md5"));
+        WordFilter::apply_all(message, filters, &emoji, &MessageParserOptions::default())
+    }
+
+    fn replace(message: &str, filters: &[WordFilter]) -> String {
+        apply_all(message, filters).message
+    }
+
+    #[test]
+    fn replace_word() {
+        let filters = [filter(1, "heck", "***", ACTION_REPLACE)];
+
+        assert_eq!("what the ***", replace("what the heck", &filters));
+        assert_eq!("*** ***!", replace("heck heck!", &filters));
+    }
+
+    #[test]
+    fn replace_whole_words_only() {
+        let filters = [filter(1, "ass", "butt", ACTION_REPLACE)];
+
+        assert_eq!("classic butt", replace("classic ass", &filters));
+    }
+
+    #[test]
+    fn replace_phrase() {
+        let filters = [filter(1, "free money", "[removed]", ACTION_REPLACE)];
+
+        assert_eq!("get [removed] now", replace("get Free Money now", &filters));
+        assert_eq!("get [removed] now", replace("get free money now", &filters));
+    }
+
+    #[test]
+    fn replace_case_insensitive() {
+        let filters = [filter(1, "heck", "***", ACTION_REPLACE)];
+
+        assert_eq!("*** ***", replace("HECK Heck", &filters));
+        assert_eq!("***", replace("ＨＥＣＫ", &filters));
+    }
+
+    #[test]
+    fn replace_homoglyphs() {
+        let filters = [filter(1, "heck", "***", ACTION_REPLACE)];
+
+        // Cyrillic "е" and "с", a digit instead of "e" and a diacritic.
+        assert_eq!("*** *** ***", replace("hеcк h3ck hêck", &filters));
+    }
+
+    #[test]
+    fn replace_homoglyphs_only_in_latin_words() {
+        let filters = [
+            filter(1, "ass", "***", ACTION_REPLACE),
+            filter(2, "cop", "***", ACTION_REPLACE),
+        ];
+
+        assert_eq!("room 455 at 10:00", replace("room 455 at 10:00", &filters));
+        assert_eq!("сор на полу", replace("сор на полу", &filters));
+        assert_eq!("*** ***", replace("a55 @ss", &filters));
+    }
+
+    #[test]
+    fn replace_non_latin() {
+        let filters = [filter(1, "тест", "test", ACTION_REPLACE)];
+
+        assert_eq!("это test", replace("это ТЕСТ", &filters));
+    }
+
+    #[test]
+    fn replace_keeps_tags() {
+        let filters = [
+            filter(1, "b", "x", ACTION_REPLACE),
+            filter(2, "spoiler", "x", ACTION_REPLACE),
+        ];
+
+        assert_eq!(
+            "[b]x[/b] [spoiler]x[/spoiler]",
+            replace("[b]b[/b] [spoiler]spoiler[/spoiler]", &filters)
+        );
+    }
+
+    #[test]
+    fn replace_keeps_ref_links_and_links() {
+        let filters = [
+            filter(1, "123", "x", ACTION_REPLACE),
+            filter(2, "example", "x", ACTION_REPLACE),
+        ];
+
+        assert_eq!(
+            ">>123 x https://example.com/ x",
+            replace(">>123 123 https://example.com/ example", &filters)
+        );
+    }
+
+    #[test]
+    fn replace_keeps_emoji() {
+        let filters = [filter(1, "cat", "dog", ACTION_REPLACE)];
+
+        assert_eq!(":cat: dog", replace(":cat: cat", &filters));
+    }
+
+    #[test]
+    fn replace_in_text_that_looks_like_markup() {
+        let filters = [filter(1, "heck", "***", ACTION_REPLACE)];
+
+        assert_eq!("[***] :***:", replace("[heck] :heck:", &filters));
+        assert_eq!(
+            "[code][b]***[/b] :cat:[/code]",
+            replace("[code][b]heck[/b] :cat:[/code]", &filters)
+        );
+    }
+
+    #[test]
+    fn replace_in_disabled_tags() {
+        let filters = [filter(1, "b", "x", ACTION_REPLACE)];
+        let options = MessageParserOptions {
+            enabled_tags: vec![String::from("i")],
+            ..MessageParserOptions::default()
+        };
+
+        assert_eq!(
+            "[x]x[/x] [i]x[/i]",
+            WordFilter::apply_all("[b]b[/b] [i]b[/i]", &filters, &EmojiMap::new(), &options)
+                .message
+        );
+    }
+
+    #[test]
+    fn reject() {
+        let filters = [filter(1, "spam", "", ACTION_REJECT)];
+
+        let result = apply_all("buy SPAM", &filters);
+        assert!(result.rejected);
+
+        let result = apply_all("buy eggs", &filters);
+        assert!(!result.rejected);
+    }
+
+    #[test]
+    fn flag() {
+        let filters = [
+            filter(1, "spam", "", ACTION_FLAG),
+            filter(2, "eggs", "", ACTION_FLAG),
+        ];
+
+        assert_eq!(
+            WordFilterResult {
+                message: String::from("spam and ham"),
+                rejected: false,
+                flagged_by: vec![1],
+            },
+            apply_all("spam and ham", &filters)
+        );
+    }
+
+    #[test]
+    fn new_with_invalid_action() {
+        assert!(WordFilter::new("spam", "", "delete", "").is_err());
+        assert!(WordFilter::new(" ", "", ACTION_REJECT, "").is_err());
+        assert!(WordFilter::new("spam", "", ACTION_REJECT, "").is_ok());
+    }
+}
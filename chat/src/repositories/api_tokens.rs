@@ -0,0 +1,85 @@
+use crate::models::api_tokens::{ApiToken, NewApiToken};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct ApiTokenRepository();
+
+impl ApiTokenRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        api_tokens.order(id.asc()).load(conn).unwrap()
+    }
+
+    pub fn get_one(conn: &PgConnection, token_id: i32) -> Option<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        let items: Vec<ApiToken> = api_tokens
+            .filter(id.eq(token_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    /// Returns any token of the bot, revoked or not, if the UUID belongs to a bot account.
+    pub fn get_one_by_user_uuid(conn: &PgConnection, uuid: &str) -> Option<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        let items: Vec<ApiToken> = api_tokens
+            .filter(user_uuid.eq(uuid))
+            .order(id.asc())
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    /// Returns the token with the given hash unless it was revoked.
+    pub fn get_active_by_hash(conn: &PgConnection, hash: &str) -> Option<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        let items: Vec<ApiToken> = api_tokens
+            .filter(token_hash.eq(hash))
+            .filter(revoked_at.is_null())
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(conn: &PgConnection, token: &NewApiToken) -> QueryResult<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        diesel::insert_into(api_tokens)
+            .values(token)
+            .get_result(conn)
+    }
+
+    pub fn revoke(
+        conn: &PgConnection,
+        token: &ApiToken,
+        now: NaiveDateTime,
+    ) -> QueryResult<ApiToken> {
+        use crate::schema::api_tokens::dsl::*;
+
+        let source = api_tokens.filter(id.eq(token.id));
+        diesel::update(source)
+            .set(revoked_at.eq(now))
+            .get_result(conn)
+    }
+
+    pub fn set_last_used(conn: &PgConnection, token: &ApiToken, now: NaiveDateTime) -> usize {
+        use crate::schema::api_tokens::dsl::*;
+
+        let source = api_tokens.filter(id.eq(token.id));
+        diesel::update(source)
+            .set(last_used_at.eq(now))
+            .execute(conn)
+            .unwrap()
+    }
+}
@@ -0,0 +1,38 @@
+use crate::models::audit_log::{AuditLogEntry, AuditLogFilter, NewAuditLogEntry};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct AuditLogRepository();
+
+impl AuditLogRepository {
+    pub fn get_filtered(conn: &PgConnection, filter: &AuditLogFilter) -> Vec<AuditLogEntry> {
+        use crate::schema::audit_log::dsl::*;
+
+        let mut query = audit_log.into_boxed();
+        if let Some(uuid) = &filter.actor_uuid {
+            query = query.filter(actor_uuid.eq(uuid));
+        }
+        if let Some(name) = &filter.action {
+            query = query.filter(action.eq(name));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(created_at.ge(from));
+        }
+        if let Some(to) = filter.to {
+            query = query.filter(created_at.lt(to));
+        }
+        if let Some(before_id) = filter.before_id {
+            query = query.filter(id.lt(before_id));
+        }
+
+        query.order(id.desc()).limit(100).load(conn).unwrap()
+    }
+
+    pub fn create(conn: &PgConnection, entry: &NewAuditLogEntry) -> QueryResult<AuditLogEntry> {
+        use crate::schema::audit_log::dsl::*;
+
+        diesel::insert_into(audit_log)
+            .values(entry)
+            .get_result(conn)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::models::blocked_files::{BlockedFile, NewBlockedFile};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct BlockedFileRepository();
+
+impl BlockedFileRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<BlockedFile> {
+        use crate::schema::blocked_files::dsl::*;
+
+        blocked_files.order(id.desc()).load(conn).unwrap()
+    }
+
+    pub fn get_one(conn: &PgConnection, blocked_id: i32) -> Option<BlockedFile> {
+        use crate::schema::blocked_files::dsl::*;
+
+        let items: Vec<BlockedFile> = blocked_files
+            .filter(id.eq(blocked_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn get_one_by_md5(conn: &PgConnection, hash: &str) -> Option<BlockedFile> {
+        use crate::schema::blocked_files::dsl::*;
+
+        let items: Vec<BlockedFile> = blocked_files
+            .filter(md5.eq(hash))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    /// Returns the perceptual hashes of the blocked images.
+    pub fn get_phashes(conn: &PgConnection) -> Vec<i64> {
+        use crate::schema::blocked_files::dsl::*;
+
+        let items: Vec<Option<i64>> = blocked_files
+            .filter(phash.is_not_null())
+            .select(phash)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().flatten().collect()
+    }
+
+    pub fn create(conn: &PgConnection, blocked: &NewBlockedFile) -> QueryResult<BlockedFile> {
+        use crate::schema::blocked_files::dsl::*;
+
+        diesel::insert_into(blocked_files)
+            .values(blocked)
+            .get_result(conn)
+    }
+
+    pub fn delete(conn: &PgConnection, blocked: &BlockedFile) -> QueryResult<BlockedFile> {
+        use crate::schema::blocked_files::dsl::*;
+
+        let source = blocked_files.filter(id.eq(blocked.id));
+        diesel::delete(source).get_result(conn)
+    }
+}
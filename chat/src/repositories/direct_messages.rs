@@ -0,0 +1,111 @@
+use crate::models::direct_messages::{Conversation, DirectMessage, NewDirectMessage};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+pub struct DirectMessageRepository();
+
+impl DirectMessageRepository {
+    /// Returns the conversations of the user with the latest message and unread count of each.
+    /// Quarantined messages are only seen by their sender.
+    pub fn get_conversations_for_user(conn: &PgConnection, uuid: &str) -> Vec<Conversation> {
+        use crate::schema::direct_messages::dsl::*;
+        use diesel::dsl::*;
+
+        // The latest message in each direction, merged into conversations afterwards.
+        let latest_ids: Vec<Option<i32>> = direct_messages
+            .select(max(id))
+            .filter(
+                sender_uuid
+                    .eq(uuid)
+                    .or(recipient_uuid.eq(uuid).and(quarantined.eq(false))),
+            )
+            .group_by((sender_uuid, recipient_uuid))
+            .load(conn)
+            .unwrap();
+
+        let messages: Vec<DirectMessage> = direct_messages
+            .filter(id.eq_any(latest_ids.into_iter().flatten().collect::<Vec<i32>>()))
+            .load(conn)
+            .unwrap();
+
+        let unread_senders: Vec<String> = direct_messages
+            .select(sender_uuid)
+            .filter(recipient_uuid.eq(uuid))
+            .filter(quarantined.eq(false))
+            .filter(read_at.is_null())
+            .load(conn)
+            .unwrap();
+
+        let mut unread_counts: HashMap<String, i64> = HashMap::new();
+        for sender in unread_senders {
+            *unread_counts.entry(sender).or_insert(0) += 1;
+        }
+
+        Conversation::from_messages(uuid, messages, &unread_counts)
+    }
+
+    /// Returns up to 100 messages between the two users, oldest first, leaving out the
+    /// quarantined messages of the other user.
+    pub fn get_between(
+        conn: &PgConnection,
+        uuid: &str,
+        other_uuid: &str,
+        before_id: Option<i32>,
+    ) -> Vec<DirectMessage> {
+        use crate::schema::direct_messages::dsl::*;
+
+        let sent = sender_uuid.eq(uuid).and(recipient_uuid.eq(other_uuid));
+        let received = sender_uuid
+            .eq(other_uuid)
+            .and(recipient_uuid.eq(uuid))
+            .and(quarantined.eq(false));
+        let mut query = direct_messages.filter(sent.or(received)).into_boxed();
+
+        if let Some(before_id) = before_id {
+            query = query.filter(id.lt(before_id));
+        }
+
+        let mut items: Vec<DirectMessage> = query.order(id.desc()).limit(100).load(conn).unwrap();
+
+        items.reverse();
+        items
+    }
+
+    pub fn count_unread_for_user(conn: &PgConnection, uuid: &str) -> i64 {
+        use crate::schema::direct_messages::dsl::*;
+
+        direct_messages
+            .filter(recipient_uuid.eq(uuid))
+            .filter(quarantined.eq(false))
+            .filter(read_at.is_null())
+            .count()
+            .get_result(conn)
+            .unwrap()
+    }
+
+    pub fn create(conn: &PgConnection, new_message: &NewDirectMessage) -> DirectMessage {
+        use crate::schema::direct_messages::dsl::*;
+
+        diesel::insert_into(direct_messages)
+            .values(new_message)
+            .get_result(conn)
+            .unwrap()
+    }
+
+    /// Marks the messages the user received from the other user as read.
+    pub fn mark_read(conn: &PgConnection, uuid: &str, other_uuid: &str) -> usize {
+        use crate::schema::direct_messages::dsl::*;
+
+        let source = direct_messages
+            .filter(recipient_uuid.eq(uuid))
+            .filter(sender_uuid.eq(other_uuid))
+            .filter(read_at.is_null());
+        let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        diesel::update(source)
+            .set(read_at.eq(now))
+            .execute(conn)
+            .unwrap()
+    }
+}
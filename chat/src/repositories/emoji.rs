@@ -0,0 +1,62 @@
+use crate::models::emoji::{CustomEmoji, NewCustomEmoji};
+use crate::models::files::File;
+use crate::models::message_parser::EmojiMap;
+use crate::schema::files;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct EmojiRepository();
+
+impl EmojiRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<(CustomEmoji, File)> {
+        use crate::schema::custom_emoji::dsl::*;
+
+        custom_emoji
+            .inner_join(files::table)
+            .filter(files::removed.eq(false))
+            .order(shortcode.asc())
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn get_map(conn: &PgConnection) -> EmojiMap {
+        use crate::schema::custom_emoji::dsl::*;
+
+        let items: Vec<(String, String)> = custom_emoji
+            .inner_join(files::table)
+            .filter(files::removed.eq(false))
+            .select((shortcode, files::md5))
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().collect()
+    }
+
+    pub fn get_one_by_shortcode(conn: &PgConnection, code: &str) -> Option<(CustomEmoji, File)> {
+        use crate::schema::custom_emoji::dsl::*;
+
+        let items = custom_emoji
+            .inner_join(files::table)
+            .filter(shortcode.eq(code))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(conn: &PgConnection, emoji: &NewCustomEmoji) -> QueryResult<CustomEmoji> {
+        use crate::schema::custom_emoji::dsl::*;
+
+        diesel::insert_into(custom_emoji)
+            .values(emoji)
+            .get_result(conn)
+    }
+
+    pub fn delete(conn: &PgConnection, emoji: &CustomEmoji) -> QueryResult<CustomEmoji> {
+        use crate::schema::custom_emoji::dsl::*;
+
+        let source = custom_emoji.filter(id.eq(emoji.id));
+        diesel::delete(source).get_result(conn)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::models::events::{Event, NewEvent, EVENTS_CHANNEL};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct EventRepository();
+
+impl EventRepository {
+    /// Stores the event and notifies the listeners once the transaction is committed.
+    pub fn create(conn: &PgConnection, event: &NewEvent) -> Event {
+        use crate::schema::events::dsl::*;
+
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let event = diesel::insert_into(events).values(event).get_result(conn)?;
+            diesel::sql_query(format!("NOTIFY {}", EVENTS_CHANNEL)).execute(conn)?;
+
+            Ok(event)
+        })
+        .unwrap()
+    }
+
+    pub fn get_last_id(conn: &PgConnection) -> i64 {
+        use crate::schema::events::dsl::*;
+
+        events
+            .select(diesel::dsl::max(id))
+            .first::<Option<i64>>(conn)
+            .unwrap()
+            .unwrap_or(0)
+    }
+
+    pub fn get_after(conn: &PgConnection, after_id: i64) -> Vec<Event> {
+        use crate::schema::events::dsl::*;
+
+        events
+            .filter(id.gt(after_id))
+            .order(id.asc())
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn delete_before(conn: &PgConnection, before: NaiveDateTime) -> usize {
+        use crate::schema::events::dsl::*;
+
+        diesel::delete(events.filter(created_at.lt(before)))
+            .execute(conn)
+            .unwrap()
+    }
+}
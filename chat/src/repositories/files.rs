@@ -1,6 +1,8 @@
+use crate::models::direct_messages::DirectMessage;
 use crate::models::files::{File, NewFile};
 use crate::models::files::{NewUserFavoriteFile, UserFavoriteFile};
 use crate::models::posts::Post;
+use chrono::prelude::*;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 
@@ -18,10 +20,33 @@ impl FileRepository {
             .grouped_by(&posts)
     }
 
+    pub fn get_belonging_to_direct_messages(
+        conn: &PgConnection,
+        messages: &Vec<DirectMessage>,
+    ) -> Vec<Vec<File>> {
+        File::belonging_to(messages)
+            .load(conn)
+            .unwrap()
+            .grouped_by(messages)
+    }
+
+    pub fn get_one(conn: &PgConnection, file_id: i32) -> Option<File> {
+        use crate::schema::files::dsl::*;
+
+        let items: Vec<File> = files.filter(id.eq(file_id)).limit(1).load(conn).unwrap();
+
+        items.into_iter().next()
+    }
+
     pub fn get_one_by_md5(conn: &PgConnection, hash: &str) -> Option<File> {
         use crate::schema::files::dsl::*;
 
-        let items: Vec<File> = files.filter(md5.eq(hash)).limit(1).load(conn).unwrap();
+        let items: Vec<File> = files
+            .filter(md5.eq(hash))
+            .order(id.desc())
+            .limit(1)
+            .load(conn)
+            .unwrap();
 
         items.into_iter().next()
     }
@@ -39,12 +64,34 @@ impl FileRepository {
 
         files
             .filter(id.nullable().eq_any(subquery))
+            .filter(removed.eq(false))
             .order(id.desc())
             .limit(100)
             .load(conn)
             .unwrap()
     }
 
+    pub fn count_recent_for_user_by_md5(
+        conn: &PgConnection,
+        uuid: &str,
+        hash: &str,
+        since: NaiveDateTime,
+    ) -> usize {
+        use crate::schema::files::dsl::*;
+        use crate::schema::posts;
+
+        let count: i64 = files
+            .inner_join(posts::table)
+            .filter(posts::user_uuid.eq(uuid))
+            .filter(md5.eq(hash))
+            .filter(created_at.ge(since))
+            .count()
+            .get_result(conn)
+            .unwrap();
+
+        count as usize
+    }
+
     pub fn get_favorites_for_user(conn: &PgConnection, uuid: &str) -> Vec<File> {
         use crate::schema::files::dsl::*;
         use crate::schema::user_favorite_files;
@@ -53,6 +100,7 @@ impl FileRepository {
             .inner_join(files)
             .select(files::all_columns())
             .filter(user_favorite_files::user_uuid.eq(uuid))
+            .filter(removed.eq(false))
             .order(user_favorite_files::id.desc())
             .limit(100)
             .load(conn)
@@ -103,4 +151,38 @@ impl FileRepository {
         let source = user_favorite_files.filter(id.eq(file.id));
         diesel::delete(source).get_result(conn).unwrap()
     }
+
+    /// Marks every record of the file as removed, as the posts share the same media on disk.
+    pub fn set_removed_by_md5(conn: &PgConnection, hash: &str) -> QueryResult<Vec<File>> {
+        use crate::schema::files::dsl::*;
+
+        let source = files.filter(md5.eq(hash)).filter(removed.eq(false));
+        diesel::update(source)
+            .set(removed.eq(true))
+            .get_results(conn)
+    }
+
+    pub fn is_used_by_emoji(conn: &PgConnection, file: &File) -> bool {
+        use crate::schema::custom_emoji::dsl::*;
+        use diesel::dsl::*;
+
+        select(exists(custom_emoji.filter(file_id.eq(file.id))))
+            .get_result(conn)
+            .unwrap()
+    }
+
+    /// Deletes the file record along with the favorites pointing to it. The file on disk is kept,
+    /// as other posts may share it.
+    pub fn delete(conn: &PgConnection, file: &File) -> QueryResult<File> {
+        use crate::schema::files::dsl::*;
+        use crate::schema::user_favorite_files;
+
+        conn.transaction(|| {
+            let favorites =
+                user_favorite_files::table.filter(user_favorite_files::file_id.eq(file.id));
+            diesel::delete(favorites).execute(conn)?;
+
+            diesel::delete(files.filter(id.eq(file.id))).get_result(conn)
+        })
+    }
 }
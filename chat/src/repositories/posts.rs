@@ -1,14 +1,32 @@
-use crate::models::posts::{NewPost, Post};
-use diesel::pg::PgConnection;
+use crate::models::posts::{NewPost, Post, PostViewer};
+use crate::schema::posts::BoxedQuery;
+use chrono::prelude::*;
+use diesel::pg::{Pg, PgConnection};
 use diesel::prelude::*;
 
 pub struct PostRepository();
 
 impl PostRepository {
-    pub fn get_latest(conn: &PgConnection) -> Vec<Post> {
+    /// Selects the posts that aren't hidden and are visible to the viewer.
+    fn visible_to(viewer: &PostViewer) -> BoxedQuery<'_, Pg> {
         use crate::schema::posts::dsl::*;
 
-        let mut items = posts
+        let query = posts.filter(hidden.eq(false)).into_boxed();
+        if viewer.is_moderator {
+            return query;
+        }
+
+        match &viewer.user_uuid {
+            Some(uuid) => query.filter(quarantined.eq(false).or(user_uuid.eq(uuid))),
+            None => query.filter(quarantined.eq(false)),
+        }
+    }
+
+    pub fn get_latest(conn: &PgConnection, viewer: &PostViewer, post_room_id: i32) -> Vec<Post> {
+        use crate::schema::posts::dsl::*;
+
+        let mut items = PostRepository::visible_to(viewer)
+            .filter(room_id.eq(post_room_id))
             .order(created_at.desc())
             .limit(100)
             .load(conn)
@@ -18,10 +36,16 @@ impl PostRepository {
         items
     }
 
-    pub fn get_before(conn: &PgConnection, before_id: i32) -> Vec<Post> {
+    pub fn get_before(
+        conn: &PgConnection,
+        viewer: &PostViewer,
+        post_room_id: i32,
+        before_id: i32,
+    ) -> Vec<Post> {
         use crate::schema::posts::dsl::*;
 
-        let mut items = posts
+        let mut items = PostRepository::visible_to(viewer)
+            .filter(room_id.eq(post_room_id))
             .filter(id.lt(before_id))
             .order(created_at.desc())
             .limit(100)
@@ -38,7 +62,20 @@ impl PostRepository {
         posts.filter(id.eq_any(ids)).load(conn).unwrap()
     }
 
-    pub fn get_one(conn: &PgConnection, post_id: i32) -> Option<Post> {
+    pub fn get_one(conn: &PgConnection, viewer: &PostViewer, post_id: i32) -> Option<Post> {
+        use crate::schema::posts::dsl::*;
+
+        let items: Vec<Post> = PostRepository::visible_to(viewer)
+            .filter(id.eq(post_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    /// Returns the post even if it is hidden, for moderation.
+    pub fn get_one_including_hidden(conn: &PgConnection, post_id: i32) -> Option<Post> {
         use crate::schema::posts::dsl::*;
 
         let items: Vec<Post> = posts.filter(id.eq(post_id)).limit(1).load(conn).unwrap();
@@ -46,6 +83,46 @@ impl PostRepository {
         items.into_iter().next()
     }
 
+    pub fn set_hidden(conn: &PgConnection, post: &Post, value: bool) -> QueryResult<Post> {
+        use crate::schema::posts::dsl::*;
+
+        let source = posts.filter(id.eq(post.id));
+        diesel::update(source)
+            .set(hidden.eq(value))
+            .get_result(conn)
+    }
+
+    pub fn get_recent_messages_for_user(
+        conn: &PgConnection,
+        uuid: &str,
+        since: NaiveDateTime,
+    ) -> Vec<String> {
+        use crate::schema::posts::dsl::*;
+
+        posts
+            .select(message)
+            .filter(user_uuid.eq(uuid))
+            .filter(created_at.ge(since))
+            .order(created_at.desc())
+            .limit(50)
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn get_last_created_at_for_user(conn: &PgConnection, uuid: &str) -> Option<NaiveDateTime> {
+        use crate::schema::posts::dsl::*;
+
+        let items: Vec<NaiveDateTime> = posts
+            .select(created_at)
+            .filter(user_uuid.eq(uuid))
+            .order(created_at.desc())
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
     pub fn create(conn: &PgConnection, post: &NewPost) -> Post {
         use crate::schema::posts::dsl::*;
 
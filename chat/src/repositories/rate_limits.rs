@@ -0,0 +1,59 @@
+use crate::models::rate_limits::{RateLimitBucket, RateLimitCharge};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct RateLimitRepository();
+
+impl RateLimitRepository {
+    /// Takes the tokens of every charge from its bucket, or from none of them if any is short,
+    /// locking their rows so that concurrent instances see consistent values.
+    pub fn take(conn: &PgConnection, charges: &[RateLimitCharge]) -> Result<(), u64> {
+        use crate::schema::rate_limit_buckets::dsl::*;
+
+        if charges.is_empty() {
+            return Ok(());
+        }
+
+        // Rows are always locked in the same order, so that concurrent requests can't deadlock.
+        let mut charges = charges.to_vec();
+        charges.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        charges.dedup_by(|a, b| a.key == b.key);
+        let bucket_keys: Vec<&str> = charges.iter().map(|charge| charge.key.as_str()).collect();
+
+        conn.transaction::<_, diesel::result::Error, _>(|| {
+            let now = Utc::now().naive_utc();
+
+            let new_buckets: Vec<RateLimitBucket> = charges
+                .iter()
+                .map(|charge| RateLimitBucket::new(&charge.key, &charge.limit, now))
+                .collect();
+            diesel::insert_into(rate_limit_buckets)
+                .values(&new_buckets)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+
+            let mut buckets: Vec<RateLimitBucket> = rate_limit_buckets
+                .filter(key.eq_any(&bucket_keys))
+                .order(key.asc())
+                .for_update()
+                .load(conn)?;
+
+            let result = RateLimitBucket::take_all(&mut buckets, &charges, now);
+            for bucket in &buckets {
+                diesel::update(bucket).set(bucket).execute(conn)?;
+            }
+
+            Ok(result)
+        })
+        .unwrap()
+    }
+
+    /// Removes buckets which haven't been touched since `before`.
+    pub fn delete_stale(conn: &PgConnection, before: NaiveDateTime) -> usize {
+        use crate::schema::rate_limit_buckets::dsl::*;
+
+        let source = rate_limit_buckets.filter(updated_at.lt(before));
+        diesel::delete(source).execute(conn).unwrap()
+    }
+}
@@ -0,0 +1,83 @@
+use crate::models::posts::Post;
+use crate::models::reports::{NewReport, Report, STATUS_OPEN};
+use crate::schema::posts;
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct ReportRepository();
+
+impl ReportRepository {
+    pub fn get_open(conn: &PgConnection) -> Vec<(Report, Post)> {
+        use crate::schema::reports::dsl::*;
+
+        reports
+            .inner_join(posts::table)
+            .filter(status.eq(STATUS_OPEN))
+            .order(id.asc())
+            .limit(100)
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn get_one(conn: &PgConnection, report_id: i32) -> Option<(Report, Post)> {
+        use crate::schema::reports::dsl::*;
+
+        let items = reports
+            .inner_join(posts::table)
+            .filter(id.eq(report_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn get_open_by_user(
+        conn: &PgConnection,
+        report_post_id: i32,
+        uuid: &str,
+    ) -> Option<Report> {
+        use crate::schema::reports::dsl::*;
+
+        let items: Vec<Report> = reports
+            .filter(post_id.eq(report_post_id))
+            .filter(reported_by.eq(uuid))
+            .filter(status.eq(STATUS_OPEN))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(conn: &PgConnection, report: &NewReport) -> Report {
+        use crate::schema::reports::dsl::*;
+
+        diesel::insert_into(reports)
+            .values(report)
+            .get_result(conn)
+            .unwrap()
+    }
+
+    /// Closes all open reports of the post, so that it doesn't show up in the queue again.
+    pub fn resolve_for_post(
+        conn: &PgConnection,
+        report_post_id: i32,
+        new_status: &str,
+        uuid: &str,
+    ) -> QueryResult<Vec<Report>> {
+        use crate::schema::reports::dsl::*;
+
+        let source = reports
+            .filter(post_id.eq(report_post_id))
+            .filter(status.eq(STATUS_OPEN));
+        diesel::update(source)
+            .set((
+                status.eq(new_status),
+                resolved_by.eq(uuid),
+                resolved_at.eq(NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0)),
+            ))
+            .get_results(conn)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::models::restrictions::{NewUserRestriction, UserRestriction};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct RestrictionRepository();
+
+impl RestrictionRepository {
+    pub fn get_active(conn: &PgConnection) -> Vec<UserRestriction> {
+        use crate::schema::user_restrictions::dsl::*;
+
+        let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        user_restrictions
+            .filter(lifted_at.is_null())
+            .filter(expires_at.is_null().or(expires_at.gt(now)))
+            .order(id.desc())
+            .limit(100)
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn get_active_for_user(
+        conn: &PgConnection,
+        uuid: &str,
+        restriction_kind: &str,
+    ) -> Option<UserRestriction> {
+        use crate::schema::user_restrictions::dsl::*;
+
+        let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        let items: Vec<UserRestriction> = user_restrictions
+            .filter(user_uuid.eq(uuid))
+            .filter(kind.eq(restriction_kind))
+            .filter(lifted_at.is_null())
+            .filter(expires_at.is_null().or(expires_at.gt(now)))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn get_one(conn: &PgConnection, restriction_id: i32) -> Option<UserRestriction> {
+        use crate::schema::user_restrictions::dsl::*;
+
+        let items: Vec<UserRestriction> = user_restrictions
+            .filter(id.eq(restriction_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(
+        conn: &PgConnection,
+        restriction: &NewUserRestriction,
+    ) -> QueryResult<UserRestriction> {
+        use crate::schema::user_restrictions::dsl::*;
+
+        diesel::insert_into(user_restrictions)
+            .values(restriction)
+            .get_result(conn)
+    }
+
+    pub fn lift(
+        conn: &PgConnection,
+        restriction: &UserRestriction,
+    ) -> QueryResult<UserRestriction> {
+        use crate::schema::user_restrictions::dsl::*;
+
+        let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+        diesel::update(restriction)
+            .set(lifted_at.eq(now))
+            .get_result(conn)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::models::rooms::{NewRoom, Room, DEFAULT_ROOM_SLUG};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct RoomRepository();
+
+impl RoomRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<Room> {
+        use crate::schema::rooms::dsl::*;
+
+        rooms.order(id.asc()).load(conn).unwrap()
+    }
+
+    pub fn get_one_by_slug(conn: &PgConnection, room_slug: &str) -> Option<Room> {
+        use crate::schema::rooms::dsl::*;
+
+        let items: Vec<Room> = rooms
+            .filter(slug.eq(room_slug))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn get_default(conn: &PgConnection) -> Room {
+        RoomRepository::get_one_by_slug(conn, DEFAULT_ROOM_SLUG).unwrap()
+    }
+
+    pub fn create(conn: &PgConnection, room: &NewRoom) -> QueryResult<Room> {
+        use crate::schema::rooms::dsl::*;
+
+        diesel::insert_into(rooms).values(room).get_result(conn)
+    }
+
+    /// Updates everything but the slug and the creation time.
+    pub fn update(conn: &PgConnection, room: &Room, data: &NewRoom) -> QueryResult<Room> {
+        use crate::schema::rooms::dsl::*;
+
+        diesel::update(room)
+            .set((
+                title.eq(&data.title),
+                description.eq(&data.description),
+                nsfw.eq(data.nsfw),
+                allowed_file_types.eq(&data.allowed_file_types),
+            ))
+            .get_result(conn)
+    }
+}
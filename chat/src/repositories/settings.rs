@@ -0,0 +1,33 @@
+use crate::models::settings::{ChatSettings, Setting};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct SettingRepository();
+
+impl SettingRepository {
+    pub fn get(conn: &PgConnection) -> QueryResult<ChatSettings> {
+        use crate::schema::settings::dsl::*;
+
+        let rows: Vec<Setting> = settings.load(conn)?;
+        Ok(ChatSettings::from_rows(&rows))
+    }
+
+    pub fn update(
+        conn: &PgConnection,
+        data: &ChatSettings,
+        uuid: &str,
+    ) -> QueryResult<ChatSettings> {
+        use crate::schema::settings::dsl::*;
+
+        for row in data.to_rows(uuid) {
+            diesel::insert_into(settings)
+                .values(&row)
+                .on_conflict(key)
+                .do_update()
+                .set(&row)
+                .execute(conn)?;
+        }
+
+        SettingRepository::get(conn)
+    }
+}
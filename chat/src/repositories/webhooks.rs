@@ -0,0 +1,132 @@
+use crate::models::webhooks::{
+    NewWebhook, NewWebhookDelivery, Webhook, WebhookAttempt, WebhookDelivery, STATUS_PENDING,
+};
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct WebhookRepository();
+
+impl WebhookRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<Webhook> {
+        use crate::schema::webhooks::dsl::*;
+
+        webhooks.order(id.asc()).load(conn).unwrap()
+    }
+
+    pub fn get_one(conn: &PgConnection, webhook_id: i32) -> Option<Webhook> {
+        use crate::schema::webhooks::dsl::*;
+
+        let items: Vec<Webhook> = webhooks
+            .filter(id.eq(webhook_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(conn: &PgConnection, webhook: &NewWebhook) -> QueryResult<Webhook> {
+        use crate::schema::webhooks::dsl::*;
+
+        diesel::insert_into(webhooks)
+            .values(webhook)
+            .get_result(conn)
+    }
+
+    pub fn update(
+        conn: &PgConnection,
+        webhook: &Webhook,
+        data: &NewWebhook,
+    ) -> QueryResult<Webhook> {
+        use crate::schema::webhooks::dsl::*;
+
+        let source = webhooks.filter(id.eq(webhook.id));
+        diesel::update(source).set(data).get_result(conn)
+    }
+
+    pub fn delete(conn: &PgConnection, webhook: &Webhook) -> QueryResult<Webhook> {
+        use crate::schema::webhooks::dsl::*;
+
+        let source = webhooks.filter(id.eq(webhook.id));
+        diesel::delete(source).get_result(conn)
+    }
+
+    /// Returns `None` if a delivery of the event to the webhook already exists, which happens
+    /// when several instances receive the same event.
+    pub fn create_delivery(
+        conn: &PgConnection,
+        delivery: &NewWebhookDelivery,
+    ) -> Option<WebhookDelivery> {
+        use crate::schema::webhook_deliveries::dsl::*;
+
+        diesel::insert_into(webhook_deliveries)
+            .values(delivery)
+            .on_conflict_do_nothing()
+            .get_result(conn)
+            .optional()
+            .unwrap()
+    }
+
+    /// Returns the latest deliveries to the webhook.
+    pub fn get_deliveries(conn: &PgConnection, webhook: &Webhook) -> Vec<WebhookDelivery> {
+        use crate::schema::webhook_deliveries::dsl::*;
+
+        WebhookDelivery::belonging_to(webhook)
+            .order(id.desc())
+            .limit(100)
+            .load(conn)
+            .unwrap()
+    }
+
+    /// Takes the pending deliveries due at `now`, pushing their next attempt to `lease_until`
+    /// so that other workers skip them while they are being sent. A delivery taken by another
+    /// worker in between is left out, as it is no longer due.
+    pub fn claim_due_deliveries(
+        conn: &PgConnection,
+        now: NaiveDateTime,
+        lease_until: NaiveDateTime,
+        limit: i64,
+    ) -> Vec<WebhookDelivery> {
+        use crate::schema::webhook_deliveries::dsl::*;
+
+        let due = webhook_deliveries
+            .filter(status.eq(STATUS_PENDING))
+            .filter(next_attempt_at.le(now));
+        let ids: Vec<i32> = due
+            .select(id)
+            .order(next_attempt_at.asc())
+            .limit(limit)
+            .load(conn)
+            .unwrap();
+
+        diesel::update(due.filter(id.eq_any(ids)))
+            .set(next_attempt_at.eq(lease_until))
+            .get_results(conn)
+            .unwrap()
+    }
+
+    pub fn update_delivery(
+        conn: &PgConnection,
+        delivery: &WebhookDelivery,
+        attempt: &WebhookAttempt,
+    ) -> WebhookDelivery {
+        use crate::schema::webhook_deliveries::dsl::*;
+
+        let source = webhook_deliveries.filter(id.eq(delivery.id));
+        diesel::update(source)
+            .set(attempt)
+            .get_result(conn)
+            .unwrap()
+    }
+
+    /// Deletes the finished deliveries last updated before the given time.
+    pub fn delete_deliveries_before(conn: &PgConnection, before: NaiveDateTime) -> usize {
+        use crate::schema::webhook_deliveries::dsl::*;
+
+        let source = webhook_deliveries
+            .filter(status.ne(STATUS_PENDING))
+            .filter(updated_at.lt(before));
+        diesel::delete(source).execute(conn).unwrap()
+    }
+}
@@ -0,0 +1,93 @@
+use crate::models::posts::Post;
+use crate::models::word_filters::{NewPostFlag, NewWordFilter, PostFlag, WordFilter};
+use crate::schema::posts;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+
+pub struct WordFilterRepository();
+
+impl WordFilterRepository {
+    pub fn get_all(conn: &PgConnection) -> Vec<WordFilter> {
+        use crate::schema::word_filters::dsl::*;
+
+        word_filters.order(id.asc()).load(conn).unwrap()
+    }
+
+    pub fn get_one(conn: &PgConnection, filter_id: i32) -> Option<WordFilter> {
+        use crate::schema::word_filters::dsl::*;
+
+        let items: Vec<WordFilter> = word_filters
+            .filter(id.eq(filter_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create(conn: &PgConnection, filter: &NewWordFilter) -> QueryResult<WordFilter> {
+        use crate::schema::word_filters::dsl::*;
+
+        diesel::insert_into(word_filters)
+            .values(filter)
+            .get_result(conn)
+    }
+
+    pub fn update(
+        conn: &PgConnection,
+        filter: &WordFilter,
+        data: &NewWordFilter,
+    ) -> QueryResult<WordFilter> {
+        use crate::schema::word_filters::dsl::*;
+
+        let source = word_filters.filter(id.eq(filter.id));
+        diesel::update(source).set(data).get_result(conn)
+    }
+
+    pub fn delete(conn: &PgConnection, filter: &WordFilter) -> QueryResult<WordFilter> {
+        use crate::schema::word_filters::dsl::*;
+
+        let source = word_filters.filter(id.eq(filter.id));
+        diesel::delete(source).get_result(conn)
+    }
+
+    pub fn get_flags(conn: &PgConnection) -> Vec<(PostFlag, Post)> {
+        use crate::schema::post_flags::dsl::*;
+
+        post_flags
+            .inner_join(posts::table)
+            .order(id.desc())
+            .limit(100)
+            .load(conn)
+            .unwrap()
+    }
+
+    pub fn get_one_flag(conn: &PgConnection, flag_id: i32) -> Option<(PostFlag, Post)> {
+        use crate::schema::post_flags::dsl::*;
+
+        let items = post_flags
+            .inner_join(posts::table)
+            .filter(id.eq(flag_id))
+            .limit(1)
+            .load(conn)
+            .unwrap();
+
+        items.into_iter().next()
+    }
+
+    pub fn create_flag(conn: &PgConnection, flag: &NewPostFlag) -> PostFlag {
+        use crate::schema::post_flags::dsl::*;
+
+        diesel::insert_into(post_flags)
+            .values(flag)
+            .get_result(conn)
+            .unwrap()
+    }
+
+    pub fn delete_flag(conn: &PgConnection, flag: &PostFlag) -> QueryResult<PostFlag> {
+        use crate::schema::post_flags::dsl::*;
+
+        let source = post_flags.filter(id.eq(flag.id));
+        diesel::delete(source).get_result(conn)
+    }
+}
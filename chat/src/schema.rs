@@ -1,3 +1,75 @@
+table! {
+    api_tokens (id) {
+        id -> Int4,
+        user_uuid -> Bpchar,
+        name -> Varchar,
+        token_hash -> Bpchar,
+        scopes -> Array<Varchar>,
+        post_burst -> Nullable<Int4>,
+        posts_per_minute -> Nullable<Int4>,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Int4,
+        actor_uuid -> Bpchar,
+        action -> Varchar,
+        target_type -> Varchar,
+        target_id -> Varchar,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    blocked_files (id) {
+        id -> Int4,
+        md5 -> Bpchar,
+        phash -> Nullable<Int8>,
+        reason -> Varchar,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    custom_emoji (id) {
+        id -> Int4,
+        shortcode -> Varchar,
+        file_id -> Int4,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    direct_messages (id) {
+        id -> Int4,
+        sender_uuid -> Bpchar,
+        recipient_uuid -> Bpchar,
+        message -> Text,
+        created_at -> Timestamp,
+        read_at -> Nullable<Timestamp>,
+        quarantined -> Bool,
+    }
+}
+
+table! {
+    events (id) {
+        id -> Int8,
+        audience -> Jsonb,
+        payload -> Text,
+        created_at -> Timestamp,
+        quarantined -> Bool,
+    }
+}
+
 table! {
     files (id) {
         id -> Int4,
@@ -7,10 +79,12 @@ table! {
         mimetype -> Varchar,
         extension -> Varchar,
         created_at -> Timestamp,
-        post_id -> Int4,
+        post_id -> Nullable<Int4>,
         width -> Nullable<Int4>,
         height -> Nullable<Int4>,
         length -> Nullable<Int4>,
+        removed -> Bool,
+        direct_message_id -> Nullable<Int4>,
     }
 }
 
@@ -23,6 +97,15 @@ table! {
     }
 }
 
+table! {
+    post_flags (id) {
+        id -> Int4,
+        post_id -> Int4,
+        word_filter_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     posts (id) {
         id -> Int4,
@@ -31,6 +114,52 @@ table! {
         message -> Text,
         created_at -> Timestamp,
         user_uuid -> Nullable<Bpchar>,
+        hidden -> Bool,
+        quarantined -> Bool,
+        room_id -> Int4,
+        bot -> Bool,
+    }
+}
+
+table! {
+    reports (id) {
+        id -> Int4,
+        post_id -> Int4,
+        reason -> Varchar,
+        reported_by -> Bpchar,
+        created_at -> Timestamp,
+        status -> Varchar,
+        resolved_by -> Nullable<Bpchar>,
+        resolved_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    rate_limit_buckets (key) {
+        key -> Varchar,
+        tokens -> Float8,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    rooms (id) {
+        id -> Int4,
+        slug -> Varchar,
+        title -> Varchar,
+        description -> Varchar,
+        nsfw -> Bool,
+        allowed_file_types -> Array<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    settings (key) {
+        key -> Varchar,
+        value -> Jsonb,
+        updated_by -> Bpchar,
+        updated_at -> Timestamp,
     }
 }
 
@@ -42,13 +171,88 @@ table! {
     }
 }
 
+table! {
+    user_restrictions (id) {
+        id -> Int4,
+        user_uuid -> Bpchar,
+        kind -> Varchar,
+        reason -> Varchar,
+        expires_at -> Nullable<Timestamp>,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+        lifted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    webhook_deliveries (id) {
+        id -> Int4,
+        webhook_id -> Int4,
+        event_id -> Int8,
+        event_type -> Varchar,
+        payload -> Text,
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        response_status -> Nullable<Int4>,
+        error -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Int4,
+        url -> Varchar,
+        secret -> Varchar,
+        event_types -> Array<Varchar>,
+        message_filter -> Nullable<Varchar>,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    word_filters (id) {
+        id -> Int4,
+        pattern -> Varchar,
+        replacement -> Varchar,
+        action -> Varchar,
+        created_by -> Bpchar,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(custom_emoji -> files (file_id));
+joinable!(files -> direct_messages (direct_message_id));
 joinable!(files -> posts (post_id));
 joinable!(notifications -> posts (post_id));
+joinable!(post_flags -> posts (post_id));
+joinable!(post_flags -> word_filters (word_filter_id));
+joinable!(posts -> rooms (room_id));
+joinable!(reports -> posts (post_id));
 joinable!(user_favorite_files -> files (file_id));
+joinable!(webhook_deliveries -> webhooks (webhook_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_log,
+    blocked_files,
+    custom_emoji,
+    direct_messages,
+    events,
     files,
     notifications,
+    post_flags,
     posts,
+    rate_limit_buckets,
+    reports,
+    rooms,
+    settings,
     user_favorite_files,
+    user_restrictions,
+    webhook_deliveries,
+    webhooks,
+    word_filters,
 );
@@ -0,0 +1,253 @@
+use chat::models::webhooks::{
+    Webhook, WebhookDelivery, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, STATUS_DELIVERED,
+    STATUS_FAILED, STATUS_PENDING,
+};
+use chrono::prelude::*;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const SECRET: &str = "0123456789abcdef";
+const PAYLOAD: &str = r#"{"event":"post_created","data":{"item":{"id":1,"message":"hello"}}}"#;
+
+/// A request as seen by the stand-in receiver. Header names are lowercased.
+struct ReceivedRequest {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// Stands in for a webhook endpoint on a local port, answering the requests with the given
+/// statuses in order and passing them back to the test.
+struct StandInReceiver {
+    url: String,
+    requests: Receiver<ReceivedRequest>,
+}
+
+impl StandInReceiver {
+    fn start(statuses: Vec<u16>) -> StandInReceiver {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let request = StandInReceiver::handle(stream, status);
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+        });
+
+        StandInReceiver { url, requests }
+    }
+
+    fn handle(mut stream: TcpStream, status: u16) -> ReceivedRequest {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let path = parts.next().unwrap_or("").to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+
+            let mut header = line.splitn(2, ':');
+            let name = header.next().unwrap().trim().to_lowercase();
+            let value = header.next().unwrap_or("").trim().to_string();
+            headers.insert(name, value);
+        }
+
+        let length = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+
+        let response = format!(
+            "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+
+        ReceivedRequest {
+            method,
+            path,
+            headers,
+            body: String::from_utf8(body).unwrap(),
+        }
+    }
+
+    fn next_request(&self) -> ReceivedRequest {
+        self.requests.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+}
+
+fn webhook(url: &str) -> Webhook {
+    Webhook {
+        id: 1,
+        url: url.to_string(),
+        secret: SECRET.to_string(),
+        event_types: vec![String::from("post_created")],
+        message_filter: None,
+        created_by: String::from("00000000-0000-0000-0000-000000000000"),
+        created_at: NaiveDateTime::from_timestamp(0, 0),
+    }
+}
+
+fn delivery() -> WebhookDelivery {
+    let now = NaiveDateTime::from_timestamp(1000, 0);
+    WebhookDelivery {
+        id: 42,
+        webhook_id: 1,
+        event_id: 7,
+        event_type: String::from("post_created"),
+        payload: PAYLOAD.to_string(),
+        status: String::from(STATUS_PENDING),
+        attempts: 0,
+        next_attempt_at: now,
+        response_status: None,
+        error: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// Makes attempts the way the worker does until the delivery is finished.
+fn deliver_until_finished(webhook: &Webhook, mut delivery: WebhookDelivery) -> WebhookDelivery {
+    while delivery.status == STATUS_PENDING {
+        let now = delivery.next_attempt_at;
+        let attempt = delivery.attempted(delivery.send(webhook), now);
+        delivery.status = attempt.status;
+        delivery.attempts = attempt.attempts;
+        delivery.next_attempt_at = attempt.next_attempt_at;
+        delivery.response_status = attempt.response_status;
+        delivery.error = attempt.error;
+        delivery.updated_at = attempt.updated_at;
+    }
+
+    delivery
+}
+
+#[test]
+fn delivers_signed_payload() {
+    let receiver = StandInReceiver::start(vec![204]);
+    let webhook = webhook(&receiver.url);
+
+    let result = delivery().send(&webhook);
+    assert!(result.is_success());
+    assert_eq!(result.response_status, Some(204));
+
+    let request = receiver.next_request();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/hook");
+    assert_eq!(request.body, PAYLOAD);
+    assert_eq!(request.headers["content-type"], "application/json");
+    assert_eq!(
+        request.headers[&EVENT_HEADER.to_lowercase()],
+        "post_created"
+    );
+    assert_eq!(request.headers[&DELIVERY_HEADER.to_lowercase()], "42");
+    assert_eq!(
+        request.headers[&SIGNATURE_HEADER.to_lowercase()],
+        Webhook::sign(SECRET, PAYLOAD)
+    );
+}
+
+#[test]
+fn signature_depends_on_secret() {
+    let receiver = StandInReceiver::start(vec![200]);
+    let mut webhook = webhook(&receiver.url);
+    webhook.secret = String::from("another secret value");
+
+    assert!(delivery().send(&webhook).is_success());
+    let request = receiver.next_request();
+    assert_ne!(
+        request.headers[&SIGNATURE_HEADER.to_lowercase()],
+        Webhook::sign(SECRET, PAYLOAD)
+    );
+}
+
+#[test]
+fn error_status_is_retried_later() {
+    let receiver = StandInReceiver::start(vec![500]);
+    let webhook = webhook(&receiver.url);
+    let delivery = delivery();
+
+    let result = delivery.send(&webhook);
+    assert!(!result.is_success());
+    assert_eq!(result.response_status, Some(500));
+
+    let attempt = delivery.attempted(result, delivery.next_attempt_at);
+    assert_eq!(attempt.status, STATUS_PENDING);
+    assert_eq!(attempt.attempts, 1);
+    assert_eq!(
+        attempt.next_attempt_at,
+        delivery.next_attempt_at + WebhookDelivery::get_retry_delay(1)
+    );
+    receiver.next_request();
+}
+
+#[test]
+fn retries_with_backoff_until_delivered() {
+    let receiver = StandInReceiver::start(vec![503, 502, 200]);
+    let webhook = webhook(&receiver.url);
+    let started_at = delivery().next_attempt_at;
+
+    let delivery = deliver_until_finished(&webhook, delivery());
+    assert_eq!(delivery.status, STATUS_DELIVERED);
+    assert_eq!(delivery.attempts, 3);
+    assert_eq!(delivery.response_status, Some(200));
+    assert_eq!(delivery.error, None);
+
+    let expected_delay = WebhookDelivery::get_retry_delay(1) + WebhookDelivery::get_retry_delay(2);
+    assert_eq!(delivery.updated_at, started_at + expected_delay);
+
+    // Every attempt carries the same delivery id and signature, so receivers can deduplicate.
+    for _ in 0..3 {
+        let request = receiver.next_request();
+        assert_eq!(request.headers[&DELIVERY_HEADER.to_lowercase()], "42");
+        assert_eq!(request.body, PAYLOAD);
+    }
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let receiver = StandInReceiver::start(vec![500; 8]);
+    let webhook = webhook(&receiver.url);
+
+    let delivery = deliver_until_finished(&webhook, delivery());
+    assert_eq!(delivery.status, STATUS_FAILED);
+    assert_eq!(delivery.attempts, 8);
+    assert_eq!(delivery.response_status, Some(500));
+    assert!(delivery.error.is_some());
+}
+
+#[test]
+fn unreachable_receiver_is_an_error() {
+    // Take a free port and close it again, so that nothing listens there.
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let webhook = webhook(&format!("http://{}/hook", address));
+
+    let result = delivery().send(&webhook);
+    assert!(!result.is_success());
+    assert_eq!(result.response_status, None);
+    assert!(result.error.is_some());
+}
@@ -0,0 +1,385 @@
+use crate::routes::types::{
+    DirectMessageWithFiles, NotificationWithPost, PostWithFiles, ReportWithPost,
+};
+use chat::models::events::{Event, EVENTS_CHANNEL};
+use chat::models::settings::ChatSettings;
+use chat::repositories::events::EventRepository;
+use chrono::prelude::*;
+use chrono::Duration as ChronoDuration;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::{Client, NoTls};
+use rocket::Config;
+use rocket_contrib::databases::database_config;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Connections used to publish events, separate from the request pool so that publishing
+/// never waits for a connection held by the request itself.
+const PUBLISH_POOL_SIZE: u32 = 4;
+/// Notifications only wake the listener up, it also checks for new events this often in case
+/// one was missed.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+/// How long events are kept in the database for listeners catching up after a reconnect.
+const EVENT_RETENTION_MINUTES: i64 = 10;
+/// Ids are taken when rows are inserted, but concurrent instances may commit them out of
+/// order. This many ids before the last one are read again, skipping the delivered ones.
+const LOOKBACK_IDS: i64 = 100;
+
+/// Events delivered to the clients, serialized the same way whatever the transport.
+#[derive(Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum ChatEvent<'a> {
+    PostCreated { item: &'a PostWithFiles },
+    PostHidden { id: i32 },
+    NotificationCreated { item: &'a NotificationWithPost },
+    ReportCreated { item: &'a ReportWithPost },
+    SettingsChanged { item: &'a ChatSettings },
+    DirectMessageCreated { item: &'a DirectMessageWithFiles },
+}
+
+impl<'a> ChatEvent<'a> {
+    /// Posts held in quarantine are only visible to moderators and their author, direct
+    /// messages to their sender.
+    pub fn is_quarantined(&self) -> bool {
+        match self {
+            ChatEvent::PostCreated { item } => item.quarantined,
+            ChatEvent::DirectMessageCreated { item } => item.quarantined,
+            _ => false,
+        }
+    }
+}
+
+/// The clients an event is meant for.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Audience {
+    All,
+    Room(String),
+    Users(Vec<String>),
+    UsersInRoom(String, Vec<String>),
+}
+
+impl Audience {
+    pub fn get_users(&self) -> Option<&[String]> {
+        match self {
+            Audience::Users(uuids) | Audience::UsersInRoom(_, uuids) => Some(uuids),
+            _ => None,
+        }
+    }
+
+    pub fn includes(&self, user_uuid: Option<&str>, room: &str) -> bool {
+        let is_user = |uuids: &[String]| match user_uuid {
+            Some(user_uuid) => uuids.iter().any(|uuid| uuid == user_uuid),
+            None => false,
+        };
+
+        match self {
+            Audience::All => true,
+            Audience::Room(slug) => slug == room,
+            Audience::Users(uuids) => is_user(uuids),
+            Audience::UsersInRoom(slug, uuids) => slug == room && is_user(uuids),
+        }
+    }
+}
+
+/// An event as delivered to the subscribers, already serialized.
+#[derive(Clone, Debug)]
+pub struct BusEvent {
+    /// Increasing id of the event, the same on all the instances sharing the bus.
+    pub seq: u64,
+    pub audience: Audience,
+    pub payload: String,
+    /// Quarantined events are only delivered to their audience, never to outside services.
+    pub quarantined: bool,
+}
+
+/// A transport delivering the events to its clients.
+pub trait EventSubscriber: Send + Sync {
+    fn receive(&self, event: &BusEvent);
+}
+
+/// Delivers the events published by the routes to every subscribed transport.
+pub trait EventBus: Send + Sync {
+    fn publish(&self, audience: Audience, event: &ChatEvent);
+
+    /// Sequence id of the last event, after which subscribers joining now start.
+    fn last_seq(&self) -> u64;
+
+    fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>);
+}
+
+pub type SharedEventBus = Arc<dyn EventBus>;
+
+#[derive(Default)]
+struct Subscribers(Mutex<Vec<Arc<dyn EventSubscriber>>>);
+
+impl Subscribers {
+    fn add(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.0.lock().unwrap().push(subscriber);
+    }
+
+    fn deliver(&self, event: &BusEvent) {
+        for subscriber in self.0.lock().unwrap().iter() {
+            subscriber.receive(event);
+        }
+    }
+}
+
+/// Delivers the events within the process, for a single instance.
+pub struct MemoryEventBus {
+    last_seq: Mutex<u64>,
+    subscribers: Subscribers,
+}
+
+impl Default for MemoryEventBus {
+    /// Sequence ids start from the current time in milliseconds, so that they keep increasing
+    /// across restarts and ids from a previous run are detected as too old to resume from.
+    fn default() -> MemoryEventBus {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        MemoryEventBus {
+            last_seq: Mutex::new(now.as_millis() as u64),
+            subscribers: Subscribers::default(),
+        }
+    }
+}
+
+impl EventBus for MemoryEventBus {
+    fn publish(&self, audience: Audience, event: &ChatEvent) {
+        // Delivered with the lock held, so that subscribers get the events in order.
+        let mut last_seq = self.last_seq.lock().unwrap();
+        *last_seq += 1;
+        self.subscribers.deliver(&BusEvent {
+            seq: *last_seq,
+            audience,
+            payload: serde_json::to_string(event).unwrap(),
+            quarantined: event.is_quarantined(),
+        });
+    }
+
+    fn last_seq(&self) -> u64 {
+        *self.last_seq.lock().unwrap()
+    }
+
+    fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.subscribers.add(subscriber);
+    }
+}
+
+/// Shares the events between the instances through the database. Events are stored in a table
+/// and `NOTIFY` wakes up the listening instances, which read the new rows. The row ids are the
+/// sequence ids, so that clients can resume on any instance.
+pub struct PostgresEventBus {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    subscribers: Arc<Subscribers>,
+    last_id: Arc<AtomicU64>,
+}
+
+struct PostgresListener {
+    url: String,
+    pool: Pool<ConnectionManager<PgConnection>>,
+    subscribers: Arc<Subscribers>,
+    last_id: Arc<AtomicU64>,
+    delivered: HashSet<i64>,
+}
+
+impl PostgresEventBus {
+    pub fn new(url: &str) -> PostgresEventBus {
+        let pool = Pool::builder()
+            .max_size(PUBLISH_POOL_SIZE)
+            .build(ConnectionManager::new(url))
+            .unwrap();
+
+        // Only the events published from now on are delivered.
+        let conn = pool.get().unwrap();
+        let last_id = EventRepository::get_last_id(&conn);
+        let delivered = EventRepository::get_after(&conn, last_id - LOOKBACK_IDS)
+            .into_iter()
+            .map(|event| event.id)
+            .collect();
+
+        let subscribers = Arc::new(Subscribers::default());
+        let last_id = Arc::new(AtomicU64::new(last_id as u64));
+        let listener = PostgresListener {
+            url: url.to_string(),
+            pool: pool.clone(),
+            subscribers: Arc::clone(&subscribers),
+            last_id: Arc::clone(&last_id),
+            delivered,
+        };
+        thread::spawn(move || listener.run());
+
+        PostgresEventBus {
+            pool,
+            subscribers,
+            last_id,
+        }
+    }
+}
+
+impl EventBus for PostgresEventBus {
+    fn publish(&self, audience: Audience, event: &ChatEvent) {
+        let payload = serde_json::to_string(event).unwrap();
+        let audience = serde_json::to_value(&audience).unwrap();
+        let new_event = Event::new(audience, &payload, event.is_quarantined());
+        EventRepository::create(&self.pool.get().unwrap(), &new_event);
+    }
+
+    fn last_seq(&self) -> u64 {
+        self.last_id.load(Ordering::Relaxed)
+    }
+
+    fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.subscribers.add(subscriber);
+    }
+}
+
+impl PostgresListener {
+    fn run(mut self) {
+        loop {
+            if let Err(e) = self.listen() {
+                println!("Event bus error: {}", e);
+            }
+
+            thread::sleep(RECONNECT_DELAY);
+        }
+    }
+
+    fn listen(&mut self) -> Result<(), postgres::Error> {
+        let mut client = Client::connect(&self.url, NoTls)?;
+        client.batch_execute(&format!("LISTEN {}", EVENTS_CHANNEL))?;
+
+        // Catch up with the events published while reconnecting.
+        self.deliver_new();
+
+        let mut last_cleanup = Instant::now();
+        loop {
+            client.notifications().timeout_iter(LISTEN_TIMEOUT).next()?;
+            self.deliver_new();
+
+            if last_cleanup.elapsed() > CLEANUP_INTERVAL {
+                last_cleanup = Instant::now();
+                self.delete_old();
+            }
+        }
+    }
+
+    fn deliver_new(&mut self) {
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(e) => return println!("Event bus error: {}", e),
+        };
+
+        let last_id = self.last_id.load(Ordering::Relaxed) as i64;
+        for event in EventRepository::get_after(&conn, last_id - LOOKBACK_IDS) {
+            if !self.delivered.insert(event.id) {
+                continue;
+            }
+
+            self.last_id.fetch_max(event.id as u64, Ordering::Relaxed);
+            if let Ok(audience) = serde_json::from_value(event.audience) {
+                self.subscribers.deliver(&BusEvent {
+                    seq: event.id as u64,
+                    audience,
+                    payload: event.payload,
+                    quarantined: event.quarantined,
+                });
+            }
+        }
+
+        let oldest_id = self.last_id.load(Ordering::Relaxed) as i64 - LOOKBACK_IDS;
+        self.delivered.retain(|id| *id > oldest_id);
+    }
+
+    /// Only one instance needs to do it, but it's harmless if they all do.
+    fn delete_old(&self) {
+        if let Ok(conn) = self.pool.get() {
+            let before = Utc::now().naive_utc() - ChronoDuration::minutes(EVENT_RETENTION_MINUTES);
+            EventRepository::delete_before(&conn, before);
+        }
+    }
+}
+
+/// Returns the bus selected with `event_bus.backend`: `postgres` for instances sharing the
+/// database, anything else for a single one.
+pub fn event_bus_from_config(config: &Config) -> SharedEventBus {
+    let backend = config
+        .get_table("event_bus")
+        .ok()
+        .and_then(|table| table.get("backend"))
+        .and_then(|value| value.as_str());
+    match backend {
+        Some("postgres") => {
+            let url = database_config("pgsql_chat", config).unwrap().url;
+            Arc::new(PostgresEventBus::new(url))
+        }
+        _ => Arc::new(MemoryEventBus::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uuids(uuids: &[&str]) -> Vec<String> {
+        uuids.iter().copied().map(String::from).collect()
+    }
+
+    #[test]
+    fn includes_all() {
+        assert!(Audience::All.includes(None, "main"));
+        assert!(Audience::All.includes(Some("user"), "other"));
+    }
+
+    #[test]
+    fn includes_room() {
+        let audience = Audience::Room(String::from("main"));
+        assert!(audience.includes(None, "main"));
+        assert!(!audience.includes(Some("user"), "other"));
+    }
+
+    #[test]
+    fn includes_users() {
+        let audience = Audience::Users(uuids(&["user", "moderator"]));
+        assert!(audience.includes(Some("user"), "main"));
+        assert!(audience.includes(Some("moderator"), "other"));
+        assert!(!audience.includes(Some("other"), "main"));
+        assert!(!audience.includes(None, "main"));
+    }
+
+    #[test]
+    fn includes_users_in_room() {
+        let audience = Audience::UsersInRoom(String::from("main"), uuids(&["user"]));
+        assert!(audience.includes(Some("user"), "main"));
+        assert!(!audience.includes(Some("user"), "other"));
+        assert!(!audience.includes(Some("other"), "main"));
+        assert!(!audience.includes(None, "main"));
+    }
+
+    #[test]
+    fn get_users() {
+        assert_eq!(None, Audience::All.get_users());
+        assert_eq!(None, Audience::Room(String::from("main")).get_users());
+        assert_eq!(
+            Some(&uuids(&["user"])[..]),
+            Audience::UsersInRoom(String::from("main"), uuids(&["user"])).get_users()
+        );
+    }
+
+    #[test]
+    fn audience_survives_database_round_trip() {
+        let audience = Audience::UsersInRoom(String::from("main"), uuids(&["user"]));
+        let value = serde_json::to_value(&audience).unwrap();
+        let audience: Audience = serde_json::from_value(value).unwrap();
+        assert!(audience.includes(Some("user"), "main"));
+        assert!(!audience.includes(Some("user"), "other"));
+    }
+}
@@ -0,0 +1,560 @@
+use crate::sse::{EventStream, EVENTS_PATH};
+use crate::ws::Ws;
+use rocket::Config;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Path of the WebSocket endpoint on the public port.
+pub const WS_PATH: &str = "/api/v1/ws";
+
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+const PEEK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The peers whose `X-Real-IP` header is taken as the client address. Anyone else could set it
+/// to whatever they like, so for them the address of the connection is used.
+#[derive(Clone, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// Reads `trusted_proxies`, which defaults to the loopback addresses of a proxy on the same
+    /// host.
+    pub fn from_config(config: &Config) -> TrustedProxies {
+        match config.get_slice("trusted_proxies") {
+            Ok(items) => TrustedProxies(
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str())
+                    .filter_map(|item| item.parse().ok())
+                    .collect(),
+            ),
+            Err(_) => TrustedProxies::loopback(),
+        }
+    }
+
+    /// Trusts only proxies on the same host, like the front for Rocket on the internal port.
+    pub fn loopback() -> TrustedProxies {
+        TrustedProxies(vec![
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ])
+    }
+
+    pub fn get_client_ip(&self, peer: IpAddr, real_ip: Option<&str>) -> IpAddr {
+        if !self.0.contains(&peer) {
+            return peer;
+        }
+
+        real_ip
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer)
+    }
+}
+
+/// Listens on the public port in front of Rocket, which can't upgrade connections itself.
+/// WebSocket handshakes for `WS_PATH` are handed to the WebSocket server, requests for the
+/// event stream are served here as they never end, and everything else is forwarded to Rocket
+/// on its internal address. Without an internal address, as on the legacy port, handshakes for
+/// any path are accepted and other requests are answered with 404.
+pub struct Front {
+    upstream: Option<String>,
+    trusted_proxies: TrustedProxies,
+    ws: Ws,
+}
+
+impl Front {
+    /// Starts accepting connections, until the WebSocket server is shut down.
+    pub fn start(address: &str, upstream: Option<&str>, ws: Ws) {
+        let front = Front {
+            upstream: upstream.map(String::from),
+            trusted_proxies: ws.trusted_proxies().clone(),
+            ws: ws.clone(),
+        };
+
+        ws.listen_with(address, move |stream| front.handle_connection(stream));
+    }
+
+    fn handle_connection(&self, stream: TcpStream) {
+        let upstream = self.upstream.clone();
+        let trusted_proxies = self.trusted_proxies.clone();
+        let ws = self.ws.clone();
+        thread::spawn(move || {
+            let head = match Front::peek_head(&stream) {
+                Ok(Some(head)) => head,
+                Ok(None) => return,
+                Err(e) => {
+                    println!("Front connection error: {}", e);
+                    return;
+                }
+            };
+
+            let head_len = head.len();
+            let head = String::from_utf8_lossy(&head);
+            let result = if Front::is_websocket_request(&head, upstream.is_none()) {
+                ws.accept(stream, Vec::new());
+                Ok(())
+            } else if Front::is_event_stream_request(&head) {
+                Front::serve_events(stream, &head, head_len, &ws)
+            } else if let Some(upstream) = upstream {
+                Front::forward(stream, &upstream, &trusted_proxies, &ws)
+            } else {
+                Front::not_found(stream)
+            };
+
+            if let Err(e) = result {
+                println!("Front connection error: {}", e);
+            }
+        });
+    }
+
+    /// Waits for the request line and headers without consuming them, so that the WebSocket
+    /// handshake can still read them. Returns `None` if the client closed the connection.
+    fn peek_head(stream: &TcpStream) -> io::Result<Option<Vec<u8>>> {
+        // Some platforms pass the non-blocking mode of the listener on to the connections.
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(HEAD_TIMEOUT))?;
+
+        let deadline = Instant::now() + HEAD_TIMEOUT;
+        let mut buf = vec![0; MAX_HEAD_SIZE];
+        loop {
+            let len = stream.peek(&mut buf)?;
+            if len == 0 {
+                return Ok(None);
+            }
+
+            let data = &buf[..len];
+            if let Some(index) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+                return Ok(Some(data[..index + 4].to_vec()));
+            }
+
+            if len == MAX_HEAD_SIZE || Instant::now() > deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Request head is too large or incomplete",
+                ));
+            }
+
+            thread::sleep(PEEK_INTERVAL);
+        }
+    }
+
+    fn get_header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+        head.lines().skip(1).find_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            if key.eq_ignore_ascii_case(name) {
+                Some(value)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the method, the path and the query string of the request.
+    fn get_request_target(head: &str) -> (&str, &str, Option<&str>) {
+        let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("");
+        let mut target = request_line.next().unwrap_or("").splitn(2, '?');
+        let path = target.next().unwrap_or("");
+
+        (method, path, target.next())
+    }
+
+    fn is_websocket_request(head: &str, any_path: bool) -> bool {
+        let (method, path, _) = Front::get_request_target(head);
+        let upgrade = Front::get_header(head, "upgrade").unwrap_or("");
+
+        method == "GET"
+            && (any_path || path == WS_PATH)
+            && upgrade.eq_ignore_ascii_case("websocket")
+    }
+
+    fn is_event_stream_request(head: &str) -> bool {
+        let (method, path, _) = Front::get_request_target(head);
+
+        method == "GET" && path == EVENTS_PATH
+    }
+
+    /// Whether the request is served here instead of by Rocket, taking over the connection.
+    fn is_take_over_request(head: &str) -> bool {
+        Front::is_websocket_request(head, false) || Front::is_event_stream_request(head)
+    }
+
+    /// Consumes the head that has only been peeked so far.
+    fn consume_head(stream: &mut TcpStream, head_len: usize) -> io::Result<()> {
+        let mut consumed = vec![0; head_len];
+        stream.read_exact(&mut consumed)?;
+        stream.set_read_timeout(None)
+    }
+
+    fn serve_events(mut stream: TcpStream, head: &str, head_len: usize, ws: &Ws) -> io::Result<()> {
+        Front::consume_head(&mut stream, head_len)?;
+
+        let (_, _, query) = Front::get_request_target(head);
+        let last_event_id = Front::get_header(head, "last-event-id").and_then(|id| id.parse().ok());
+        EventStream::serve(ws, stream, query, last_event_id)
+    }
+
+    fn not_found(mut stream: TcpStream) -> io::Result<()> {
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+    }
+
+    fn invalid_data(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    /// Reads a line along with its line break. Returns an empty line at the end of the stream.
+    fn read_line(reader: &mut impl BufRead) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        reader
+            .take(MAX_HEAD_SIZE as u64)
+            .read_until(b'\n', &mut line)?;
+        if !line.is_empty() && !line.ends_with(b"\n") {
+            return Err(Front::invalid_data("Line is too long or incomplete"));
+        }
+
+        Ok(line)
+    }
+
+    /// Reads the next request head, or returns `None` if the client closed the connection.
+    fn read_head(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+        let mut head = Vec::new();
+        loop {
+            let line = Front::read_line(reader)?;
+            if line.is_empty() {
+                if head.is_empty() {
+                    return Ok(None);
+                }
+
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+
+            let is_blank = line == b"\r\n" || line == b"\n";
+            if is_blank && head.is_empty() {
+                // Clients may send line breaks between requests.
+                continue;
+            }
+
+            head.extend_from_slice(&line);
+            if is_blank {
+                return Ok(Some(String::from_utf8_lossy(&head).to_string()));
+            }
+
+            if head.len() > MAX_HEAD_SIZE {
+                return Err(Front::invalid_data("Request head is too large"));
+            }
+        }
+    }
+
+    /// Rewrites the request head so that Rocket sees the real client address, which it reads
+    /// from `X-Real-IP`. Anything else, like the `Connection` header, is passed on unchanged.
+    fn rewrite_head(head: &str, client: IpAddr) -> String {
+        let mut lines = head.lines().filter(|line| !line.is_empty());
+        let mut result = String::new();
+        if let Some(request_line) = lines.next() {
+            result.push_str(request_line);
+            result.push_str("\r\n");
+        }
+
+        for line in lines {
+            let key = line.split(':').next().unwrap_or("").trim();
+            if key.eq_ignore_ascii_case("x-real-ip") {
+                continue;
+            }
+
+            result.push_str(line);
+            result.push_str("\r\n");
+        }
+
+        result.push_str(&format!("X-Real-IP: {}\r\n\r\n", client));
+        result
+    }
+
+    fn copy_exact(reader: &mut impl BufRead, writer: &mut impl Write, len: u64) -> io::Result<()> {
+        if io::copy(&mut reader.take(len), writer)? < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+
+        Ok(())
+    }
+
+    /// Copies a chunked body up to and including the trailers.
+    fn forward_chunked(reader: &mut impl BufRead, upstream: &mut impl Write) -> io::Result<()> {
+        loop {
+            let line = Front::read_line(reader)?;
+            upstream.write_all(&line)?;
+
+            let size = String::from_utf8_lossy(&line);
+            let size = size.split(';').next().unwrap_or("").trim();
+            let size = u64::from_str_radix(size, 16)
+                .map_err(|_| Front::invalid_data("Invalid chunk size"))?;
+            if size == 0 {
+                break;
+            }
+
+            // The chunk is followed by a line break.
+            Front::copy_exact(reader, upstream, size + 2)?;
+        }
+
+        loop {
+            let line = Front::read_line(reader)?;
+            if line.is_empty() {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+
+            upstream.write_all(&line)?;
+            if line == b"\r\n" || line == b"\n" {
+                return Ok(());
+            }
+        }
+    }
+
+    fn forward_body(
+        reader: &mut impl BufRead,
+        upstream: &mut impl Write,
+        head: &str,
+    ) -> io::Result<()> {
+        let transfer_encoding = Front::get_header(head, "transfer-encoding").unwrap_or("");
+        if transfer_encoding.to_ascii_lowercase().contains("chunked") {
+            return Front::forward_chunked(reader, upstream);
+        }
+
+        let len = match Front::get_header(head, "content-length") {
+            Some(len) => len
+                .parse()
+                .map_err(|_| Front::invalid_data("Invalid content length"))?,
+            None => 0,
+        };
+        Front::copy_exact(reader, upstream, len)
+    }
+
+    /// Forwards the requests of the client one by one, so that each of them gets the client
+    /// address, until the client closes the connection. `peer` is the address of the connection,
+    /// which may be a trusted proxy forwarding the client address itself. Stops at a request
+    /// that takes the connection over, returning its head.
+    fn forward_requests(
+        reader: &mut impl BufRead,
+        upstream: &mut impl Write,
+        peer: IpAddr,
+        trusted_proxies: &TrustedProxies,
+    ) -> io::Result<Option<String>> {
+        while let Some(head) = Front::read_head(reader)? {
+            if Front::is_take_over_request(&head) {
+                return Ok(Some(head));
+            }
+
+            let real_ip = Front::get_header(&head, "x-real-ip");
+            let client = trusted_proxies.get_client_ip(peer, real_ip);
+            upstream.write_all(Front::rewrite_head(&head, client).as_bytes())?;
+            Front::forward_body(reader, upstream, &head)?;
+            upstream.flush()?;
+        }
+
+        Ok(None)
+    }
+
+    /// Passes the connection on to Rocket, keeping it alive for as long as both sides do.
+    /// Responses are copied back as they come, in the order of the requests. A WebSocket
+    /// handshake or event stream request takes the connection over once the requests before it
+    /// have been answered.
+    fn forward(
+        stream: TcpStream,
+        upstream: &str,
+        trusted_proxies: &TrustedProxies,
+        ws: &Ws,
+    ) -> io::Result<()> {
+        let peer = stream.peer_addr()?.ip();
+        stream.set_read_timeout(None)?;
+
+        let mut upstream = TcpStream::connect(upstream)?;
+        let mut upstream_reader = upstream.try_clone()?;
+        let mut client_writer = stream.try_clone()?;
+        let taking_over = Arc::new(AtomicBool::new(false));
+        let responses = {
+            let taking_over = Arc::clone(&taking_over);
+            thread::spawn(move || {
+                let _ = io::copy(&mut upstream_reader, &mut client_writer);
+                // Rocket closed the connection, which also ends reading the requests.
+                if !taking_over.load(Ordering::SeqCst) {
+                    let _ = client_writer.shutdown(Shutdown::Both);
+                }
+            })
+        };
+
+        let mut reader = BufReader::new(stream);
+        let result = Front::forward_requests(&mut reader, &mut upstream, peer, trusted_proxies);
+        if let Ok(Some(_)) = result {
+            taking_over.store(true, Ordering::SeqCst);
+        }
+
+        // Rocket answers the pending requests before closing its side.
+        let _ = upstream.shutdown(Shutdown::Write);
+        let _ = responses.join();
+        match result? {
+            Some(head) => Front::take_over(reader, &head, ws),
+            None => Ok(()),
+        }
+    }
+
+    /// Serves a request that came after others on a forwarded connection. Its head has already
+    /// been read, along with anything the client sent after it.
+    fn take_over(reader: BufReader<TcpStream>, head: &str, ws: &Ws) -> io::Result<()> {
+        let mut read = head.as_bytes().to_vec();
+        read.extend_from_slice(reader.buffer());
+        let stream = reader.into_inner();
+        if Front::is_websocket_request(head, false) {
+            ws.accept(stream, read);
+            Ok(())
+        } else {
+            Front::serve_events(stream, head, 0, ws)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn client() -> IpAddr {
+        "10.0.0.1".parse().unwrap()
+    }
+
+    fn forward_from(requests: &str, peer: IpAddr) -> io::Result<String> {
+        let mut reader = Cursor::new(requests.as_bytes().to_vec());
+        let mut upstream = Vec::new();
+        let trusted_proxies = TrustedProxies::loopback();
+        let take_over =
+            Front::forward_requests(&mut reader, &mut upstream, peer, &trusted_proxies)?;
+        assert_eq!(None, take_over);
+
+        Ok(String::from_utf8(upstream).unwrap())
+    }
+
+    fn forward(requests: &str) -> io::Result<String> {
+        forward_from(requests, client())
+    }
+
+    #[test]
+    fn rewrite_head_sets_real_ip() {
+        let head = "GET /api/v1/posts HTTP/1.1\r\nHost: chat\r\nX-Real-IP: 1.2.3.4\r\n\r\n";
+        assert_eq!(
+            "GET /api/v1/posts HTTP/1.1\r\nHost: chat\r\nX-Real-IP: 10.0.0.1\r\n\r\n",
+            Front::rewrite_head(head, client())
+        );
+    }
+
+    #[test]
+    fn rewrite_head_keeps_connection() {
+        let head = "GET / HTTP/1.1\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\n\r\n";
+        assert_eq!(
+            "GET / HTTP/1.1\r\nConnection: keep-alive\r\nKeep-Alive: timeout=5\r\n\
+            X-Real-IP: 10.0.0.1\r\n\r\n",
+            Front::rewrite_head(head, client())
+        );
+    }
+
+    #[test]
+    fn forward_keep_alive_requests() {
+        let requests = "POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+            GET /b HTTP/1.1\r\nX-Real-IP: 1.2.3.4\r\n\r\n";
+        assert_eq!(
+            "POST /a HTTP/1.1\r\nContent-Length: 5\r\nX-Real-IP: 10.0.0.1\r\n\r\nhello\
+            GET /b HTTP/1.1\r\nX-Real-IP: 10.0.0.1\r\n\r\n",
+            forward(requests).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_real_ip_from_trusted_proxy() {
+        let requests = "GET /a HTTP/1.1\r\nX-Real-IP: 1.2.3.4\r\n\r\nGET /b HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            "GET /a HTTP/1.1\r\nX-Real-IP: 1.2.3.4\r\n\r\n\
+            GET /b HTTP/1.1\r\nX-Real-IP: 127.0.0.1\r\n\r\n",
+            forward_from(requests, IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap()
+        );
+    }
+
+    #[test]
+    fn trusted_proxies_get_client_ip() {
+        let proxies = TrustedProxies::loopback();
+        let proxy = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert_eq!(client(), proxies.get_client_ip(proxy, Some(" 10.0.0.1 ")));
+        assert_eq!(proxy, proxies.get_client_ip(proxy, Some("unknown")));
+        assert_eq!(proxy, proxies.get_client_ip(proxy, None));
+        assert_eq!(client(), proxies.get_client_ip(client(), Some("1.2.3.4")));
+        assert_eq!(
+            client(),
+            TrustedProxies::default().get_client_ip(client(), Some("1.2.3.4"))
+        );
+    }
+
+    #[test]
+    fn forward_chunked_request() {
+        let requests = "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            5\r\nhello\r\n0\r\n\r\n\
+            GET /b HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nX-Real-IP: 10.0.0.1\r\n\r\n\
+            5\r\nhello\r\n0\r\n\r\n\
+            GET /b HTTP/1.1\r\nX-Real-IP: 10.0.0.1\r\n\r\n",
+            forward(requests).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_stops_at_take_over_request() {
+        let requests = "GET /a HTTP/1.1\r\n\r\n\
+            GET /api/v1/events HTTP/1.1\r\n\r\n\
+            GET /b HTTP/1.1\r\n\r\n";
+        let mut reader = Cursor::new(requests.as_bytes().to_vec());
+        let mut upstream = Vec::new();
+        let trusted_proxies = TrustedProxies::default();
+        let take_over =
+            Front::forward_requests(&mut reader, &mut upstream, client(), &trusted_proxies);
+        assert_eq!(
+            Some("GET /api/v1/events HTTP/1.1\r\n\r\n".to_string()),
+            take_over.unwrap()
+        );
+        assert_eq!(
+            "GET /a HTTP/1.1\r\nX-Real-IP: 10.0.0.1\r\n\r\n",
+            String::from_utf8(upstream).unwrap()
+        );
+    }
+
+    #[test]
+    fn forward_incomplete_request() {
+        assert!(forward("POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel").is_err());
+        assert!(forward("GET /a HTTP/1.1\r\nHost: chat").is_err());
+        assert_eq!("", forward("").unwrap());
+    }
+
+    #[test]
+    fn is_websocket_request() {
+        let head = "GET /api/v1/ws?room=main HTTP/1.1\r\nUpgrade: WebSocket\r\n\r\n";
+        assert!(Front::is_websocket_request(head, false));
+
+        let head = "GET / HTTP/1.1\r\nUpgrade: websocket\r\n\r\n";
+        assert!(!Front::is_websocket_request(head, false));
+        assert!(Front::is_websocket_request(head, true));
+
+        let head = "GET /api/v1/ws HTTP/1.1\r\n\r\n";
+        assert!(!Front::is_websocket_request(head, true));
+    }
+
+    #[test]
+    fn get_request_target() {
+        let head = "GET /api/v1/events?room=main HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            ("GET", "/api/v1/events", Some("room=main")),
+            Front::get_request_target(head)
+        );
+        assert!(Front::is_event_stream_request(head));
+    }
+}
@@ -9,24 +9,74 @@ extern crate rocket;
 #[macro_use]
 extern crate rocket_contrib;
 
+mod events;
+mod front;
+mod moderation;
+mod rate_limit;
 mod requests;
 mod routes;
+mod signals;
+mod sse;
+mod webhooks;
 mod ws;
 
+use chat::models::message_parser::MessageParserOptions;
+use chat::models::posts::PostLimits;
+use chat::models::spam::SpamOptions;
 use diesel::pg::PgConnection;
+use events::{event_bus_from_config, SharedEventBus};
+use front::{Front, TrustedProxies};
+use rate_limit::RateLimiter;
+use rocket::{Config, Rocket};
 use rocket_contrib::serve::StaticFiles;
 use rocket_sentry::RocketSentry;
-use routes::{files, notifications, posts, thumbnails};
+use routes::posts::PostContext;
+use routes::types::{
+    parser_options_from_config, post_limits_from_config, spam_options_from_config, Roles,
+};
+use routes::{
+    api_tokens, blocked_files, direct_messages, emoji, files, moderation as moderation_routes,
+    notifications, posts, reports, rooms, settings, thumbnails, webhooks as webhook_routes,
+    word_filters,
+};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use webhooks::Webhooks;
 use ws::Ws;
 
 #[database("pgsql_chat")]
 pub struct ChatDbConn(PgConnection);
 
-fn rocket() -> rocket::Rocket {
+fn rocket(config: Config, trusted_proxies: TrustedProxies) -> Rocket {
     let static_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www");
 
-    rocket::ignite()
-        .mount("/api/v1/files", routes![files::get_file_list,])
+    let rocket = rocket::custom(config);
+    let roles = Roles::from_config(rocket.config());
+    let parser_options = parser_options_from_config(rocket.config());
+    let post_limits = post_limits_from_config(rocket.config(), &parser_options);
+    let rate_limiter = RateLimiter::from_config(rocket.config());
+    let spam_options = spam_options_from_config(rocket.config());
+    let event_bus = event_bus_from_config(rocket.config());
+
+    let rocket = rocket
+        .mount(
+            "/api/v1/emoji",
+            routes![
+                emoji::get_emoji_list,
+                emoji::create_emoji,
+                emoji::delete_emoji,
+            ],
+        )
+        .mount(
+            "/api/v1/files",
+            routes![
+                files::get_file_list,
+                moderation_routes::delete_file,
+                moderation_routes::take_down_file,
+            ],
+        )
         .mount(
             "/api/v1/favorites",
             routes![
@@ -43,8 +93,37 @@ fn rocket() -> rocket::Rocket {
                 posts::create_post_multipart,
                 posts::get_post_list,
                 posts::get_post,
+                reports::create_report,
+                moderation_routes::delete_post,
+            ],
+        )
+        .mount(
+            "/api/v1/rooms",
+            routes![
+                rooms::get_room_list,
+                rooms::get_room,
+                rooms::create_room,
+                rooms::update_room,
+                posts::create_room_post_json,
+                posts::create_room_post_multipart,
+                posts::get_room_post_list,
             ],
         )
+        .mount(
+            "/api/v1/conversations",
+            routes![
+                direct_messages::get_conversation_list,
+                direct_messages::get_unread_count,
+                direct_messages::get_direct_message_list,
+                direct_messages::read_conversation,
+                direct_messages::create_direct_message_json,
+                direct_messages::create_direct_message_multipart,
+            ],
+        )
+        .mount(
+            "/api/v1/reports",
+            routes![reports::get_report_list, reports::update_report],
+        )
         .mount(
             "/api/v1/notifications",
             routes![
@@ -53,20 +132,146 @@ fn rocket() -> rocket::Rocket {
                 notifications::delete_notification,
             ],
         )
+        .mount(
+            "/api/v1/filters",
+            routes![
+                word_filters::get_word_filter_list,
+                word_filters::create_word_filter,
+                word_filters::update_word_filter,
+                word_filters::delete_word_filter,
+                word_filters::test_word_filters,
+            ],
+        )
+        .mount(
+            "/api/v1/flags",
+            routes![word_filters::get_flag_list, word_filters::delete_flag],
+        )
+        .mount(
+            "/api/v1/blocklist",
+            routes![
+                blocked_files::get_blocked_file_list,
+                blocked_files::create_blocked_file,
+                blocked_files::delete_blocked_file,
+            ],
+        )
+        .mount(
+            "/api/v1/settings",
+            routes![settings::get_settings, settings::update_settings],
+        )
+        .mount("/api/v1/admin", routes![moderation_routes::get_audit_log])
+        .mount(
+            "/api/v1/admin/restrictions",
+            routes![
+                moderation_routes::get_restriction_list,
+                moderation_routes::create_restriction,
+                moderation_routes::delete_restriction,
+            ],
+        )
+        .mount(
+            "/api/v1/admin/webhooks",
+            routes![
+                webhook_routes::get_webhook_list,
+                webhook_routes::create_webhook,
+                webhook_routes::update_webhook,
+                webhook_routes::delete_webhook,
+                webhook_routes::get_delivery_list,
+            ],
+        )
+        .mount(
+            "/api/v1/admin/tokens",
+            routes![
+                api_tokens::get_api_token_list,
+                api_tokens::create_api_token,
+                api_tokens::revoke_api_token,
+            ],
+        )
         .mount("/thumb", routes![thumbnails::get_thumbnail])
         .mount("/", StaticFiles::from(static_dir))
         .attach(RocketSentry::fairing())
         .attach(ChatDbConn::fairing())
+        .manage(roles)
+        .manage(trusted_proxies)
+        .manage(parser_options)
+        .manage(post_limits)
+        .manage(rate_limiter)
+        .manage(spam_options)
+        .manage(event_bus);
+
+    let context = post_context(&rocket);
+    rocket.manage(context)
+}
+
+/// Bundles the managed state needed to create posts, for the routes and for the WebSocket server,
+/// which creates posts outside of requests. The database pool, the rate limit buckets and the
+/// event bus are shared with the other routes.
+fn post_context(rocket: &Rocket) -> PostContext {
+    PostContext {
+        pool: rocket.state::<ChatDbConnPool>().unwrap().0.clone(),
+        options: rocket.state::<MessageParserOptions>().unwrap().clone(),
+        limits: rocket.state::<PostLimits>().unwrap().clone(),
+        spam_options: rocket.state::<SpamOptions>().unwrap().clone(),
+        roles: rocket.state::<Roles>().unwrap().clone(),
+        rate_limiter: rocket.state::<RateLimiter>().unwrap().clone(),
+        events: rocket.state::<SharedEventBus>().unwrap().clone(),
+    }
 }
 
 fn main() {
-    let rocket = rocket();
+    // Rocket only reads Rocket.toml when ignited, the instance itself is rebuilt below.
+    let config = rocket::ignite().config().clone();
+
+    // Unless disabled, the front takes over the configured address and serves WebSocket
+    // connections on the same port, forwarding HTTP requests to Rocket on an internal port.
+    let shared_port = config.get_bool("ws_shared_port").unwrap_or(true);
+    let internal_port = config.get_int("http_internal_port").unwrap_or(8002) as u16;
+    let trusted_proxies = TrustedProxies::from_config(&config);
+    let rocket = if shared_port {
+        let mut internal_config = config.clone();
+        internal_config.set_address("127.0.0.1").unwrap();
+        internal_config.set_port(internal_port);
+        // The front checks the proxies and always sets the client address itself.
+        rocket(internal_config, TrustedProxies::loopback())
+    } else {
+        rocket(config.clone(), trusted_proxies.clone())
+    };
+
+    let posts = rocket.state::<PostContext>().unwrap().clone();
+    let ws = Ws::new(posts, trusted_proxies);
+    let events = rocket.state::<SharedEventBus>().unwrap();
+    events.subscribe(Arc::new(ws.clone()));
+    let pool = rocket.state::<ChatDbConnPool>().unwrap().0.clone();
+    events.subscribe(Arc::new(Webhooks::start(pool)));
+
+    // The standalone port is kept for clients that haven't moved to WS_PATH, and serves the
+    // event stream as well when the outer proxy shares the port instead of the front.
+    if let Ok(ws_port) = config.get_int("ws_port") {
+        let ws_address = config.get_str("ws_address").unwrap_or("127.0.0.1");
+        Front::start(&format!("{}:{}", ws_address, ws_port), None, ws.clone());
+    }
+
+    if shared_port {
+        let address = format!("{}:{}", config.address, config.port);
+        let upstream = format!("127.0.0.1:{}", internal_port);
+        Front::start(&address, Some(&upstream), ws.clone());
+    }
+
+    // Rocket can't be stopped, so it runs on its own thread until the process exits. On SIGINT
+    // or SIGTERM the listeners are stopped and the open connections closed first.
+    signals::install();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // Launching only returns on failure.
+        let _ = sender.send(rocket.launch());
+    });
 
-    let ws_address = rocket.config().get_str("ws_address").unwrap_or("127.0.0.1");
-    let ws_port = rocket.config().get_int("ws_port").unwrap_or(8001);
-    let ws_address = format!("{}:{}", ws_address, ws_port);
-    let ws = Ws::new(&ws_address);
-    let rocket = rocket.manage(ws);
+    let error = loop {
+        match receiver.recv_timeout(Duration::from_millis(100)) {
+            Ok(error) => break Some(error),
+            Err(RecvTimeoutError::Timeout) if !signals::is_stop_requested() => {}
+            Err(_) => break None,
+        }
+    };
 
-    rocket.launch();
+    ws.shutdown();
+    drop(error);
 }
@@ -0,0 +1,388 @@
+use chat::models::api_tokens::{ApiToken, NewApiToken};
+use chat::models::audit_log::*;
+use chat::models::blocked_files::{BlockedFile, NewBlockedFile};
+use chat::models::emoji::{CustomEmoji, NewCustomEmoji};
+use chat::models::files::File;
+use chat::models::posts::Post;
+use chat::models::reports::Report;
+use chat::models::restrictions::{NewUserRestriction, UserRestriction};
+use chat::models::rooms::{NewRoom, Room};
+use chat::models::settings::ChatSettings;
+use chat::models::webhooks::{NewWebhook, Webhook};
+use chat::models::word_filters::{NewWordFilter, PostFlag, WordFilter};
+use chat::repositories::api_tokens::ApiTokenRepository;
+use chat::repositories::audit_log::AuditLogRepository;
+use chat::repositories::blocked_files::BlockedFileRepository;
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use chat::repositories::posts::PostRepository;
+use chat::repositories::reports::ReportRepository;
+use chat::repositories::restrictions::RestrictionRepository;
+use chat::repositories::rooms::RoomRepository;
+use chat::repositories::settings::SettingRepository;
+use chat::repositories::webhooks::WebhookRepository;
+use chat::repositories::word_filters::WordFilterRepository;
+use chrono::prelude::*;
+use diesel::pg::PgConnection;
+use diesel::{Connection, QueryResult};
+use serde::Serialize;
+
+/// Performs the moderation and admin actions on behalf of a user. Every change is written to the
+/// audit log in the same transaction, so routes must not call the repositories directly for these.
+pub struct Moderation<'a> {
+    conn: &'a PgConnection,
+    actor_uuid: String,
+}
+
+impl<'a> Moderation<'a> {
+    pub fn new(conn: &'a PgConnection, actor_uuid: &str) -> Moderation<'a> {
+        Moderation {
+            conn,
+            actor_uuid: actor_uuid.to_string(),
+        }
+    }
+
+    fn log<T: Serialize>(
+        &self,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> QueryResult<()> {
+        let entry = AuditLogEntry::new(
+            &self.actor_uuid,
+            action,
+            target_type,
+            target_id,
+            before,
+            after,
+        );
+        AuditLogRepository::create(self.conn, &entry)?;
+        Ok(())
+    }
+
+    fn transaction<T, F>(&self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce() -> QueryResult<T>,
+    {
+        self.conn.transaction(f)
+    }
+
+    pub fn hide_post(&self, post: &Post) -> QueryResult<Post> {
+        self.transaction(|| {
+            let hidden = PostRepository::set_hidden(self.conn, post, true)?;
+            let target_id = post.id.to_string();
+            self.log(
+                ACTION_POST_HIDE,
+                "post",
+                &target_id,
+                Some(post),
+                Some(&hidden),
+            )?;
+            Ok(hidden)
+        })
+    }
+
+    /// Refuses to remove files used by custom emoji, with the reason as the inner error.
+    pub fn remove_file(&self, file: &File) -> QueryResult<Result<File, String>> {
+        if FileRepository::is_used_by_emoji(self.conn, file) {
+            return Ok(Err(String::from("File is used by a custom emoji")));
+        }
+
+        self.transaction(|| {
+            let file = FileRepository::delete(self.conn, file)?;
+            let target_id = file.id.to_string();
+            self.log(ACTION_FILE_REMOVE, "file", &target_id, Some(&file), None)?;
+            Ok(Ok(file))
+        })
+    }
+
+    /// Marks all records of the file as removed and deletes its media from disk. Returns the
+    /// updated records, or an empty list if there was nothing to take down, along with the
+    /// errors of the media which couldn't be deleted.
+    pub fn take_down_file(&self, md5: &str) -> QueryResult<(Vec<File>, Vec<String>)> {
+        let files = self.transaction(|| {
+            let files = FileRepository::set_removed_by_md5(self.conn, md5)?;
+            if !files.is_empty() {
+                self.log(ACTION_FILE_TAKE_DOWN, "file", md5, None, Some(&files))?;
+            }
+            Ok(files)
+        })?;
+
+        let errors = match files.first().map(File::remove_media) {
+            Some(Err(errors)) => errors,
+            _ => Vec::new(),
+        };
+
+        Ok((files, errors))
+    }
+
+    pub fn restrict_user(&self, restriction: &NewUserRestriction) -> QueryResult<UserRestriction> {
+        self.transaction(|| {
+            let restriction = RestrictionRepository::create(self.conn, restriction)?;
+            let target_id = restriction.user_uuid.clone();
+            self.log(
+                ACTION_USER_RESTRICT,
+                "user",
+                &target_id,
+                None,
+                Some(&restriction),
+            )?;
+            Ok(restriction)
+        })
+    }
+
+    pub fn lift_restriction(&self, restriction: &UserRestriction) -> QueryResult<UserRestriction> {
+        self.transaction(|| {
+            let lifted = RestrictionRepository::lift(self.conn, restriction)?;
+            let target_id = restriction.user_uuid.clone();
+            self.log(
+                ACTION_USER_UNRESTRICT,
+                "user",
+                &target_id,
+                Some(restriction),
+                Some(&lifted),
+            )?;
+            Ok(lifted)
+        })
+    }
+
+    pub fn resolve_reports(&self, post_id: i32, status: &str) -> QueryResult<Vec<Report>> {
+        self.transaction(|| {
+            let reports =
+                ReportRepository::resolve_for_post(self.conn, post_id, status, &self.actor_uuid)?;
+            let target_id = post_id.to_string();
+            self.log(
+                ACTION_REPORT_RESOLVE,
+                "post",
+                &target_id,
+                None,
+                Some(&reports),
+            )?;
+            Ok(reports)
+        })
+    }
+
+    pub fn dismiss_flag(&self, flag: &PostFlag) -> QueryResult<PostFlag> {
+        self.transaction(|| {
+            let flag = WordFilterRepository::delete_flag(self.conn, flag)?;
+            let target_id = flag.id.to_string();
+            self.log(
+                ACTION_FLAG_DISMISS,
+                "post_flag",
+                &target_id,
+                Some(&flag),
+                None,
+            )?;
+            Ok(flag)
+        })
+    }
+
+    pub fn create_emoji(&self, emoji: &NewCustomEmoji) -> QueryResult<CustomEmoji> {
+        self.transaction(|| {
+            let emoji = EmojiRepository::create(self.conn, emoji)?;
+            let target_id = emoji.id.to_string();
+            self.log(ACTION_EMOJI_CREATE, "emoji", &target_id, None, Some(&emoji))?;
+            Ok(emoji)
+        })
+    }
+
+    pub fn delete_emoji(&self, emoji: &CustomEmoji) -> QueryResult<CustomEmoji> {
+        self.transaction(|| {
+            let emoji = EmojiRepository::delete(self.conn, emoji)?;
+            let target_id = emoji.id.to_string();
+            self.log(ACTION_EMOJI_DELETE, "emoji", &target_id, Some(&emoji), None)?;
+            Ok(emoji)
+        })
+    }
+
+    pub fn create_word_filter(&self, filter: &NewWordFilter) -> QueryResult<WordFilter> {
+        self.transaction(|| {
+            let filter = WordFilterRepository::create(self.conn, filter)?;
+            let target_id = filter.id.to_string();
+            self.log(
+                ACTION_WORD_FILTER_CREATE,
+                "word_filter",
+                &target_id,
+                None,
+                Some(&filter),
+            )?;
+            Ok(filter)
+        })
+    }
+
+    pub fn update_word_filter(
+        &self,
+        filter: &WordFilter,
+        data: &NewWordFilter,
+    ) -> QueryResult<WordFilter> {
+        self.transaction(|| {
+            let updated = WordFilterRepository::update(self.conn, filter, data)?;
+            let target_id = filter.id.to_string();
+            let action = ACTION_WORD_FILTER_UPDATE;
+            self.log(
+                action,
+                "word_filter",
+                &target_id,
+                Some(filter),
+                Some(&updated),
+            )?;
+            Ok(updated)
+        })
+    }
+
+    pub fn delete_word_filter(&self, filter: &WordFilter) -> QueryResult<WordFilter> {
+        self.transaction(|| {
+            let filter = WordFilterRepository::delete(self.conn, filter)?;
+            let target_id = filter.id.to_string();
+            self.log(
+                ACTION_WORD_FILTER_DELETE,
+                "word_filter",
+                &target_id,
+                Some(&filter),
+                None,
+            )?;
+            Ok(filter)
+        })
+    }
+
+    pub fn block_file(&self, blocked: &NewBlockedFile) -> QueryResult<BlockedFile> {
+        self.transaction(|| {
+            let blocked = BlockedFileRepository::create(self.conn, blocked)?;
+            let target_id = blocked.md5.clone();
+            self.log(ACTION_FILE_BLOCK, "file", &target_id, None, Some(&blocked))?;
+            Ok(blocked)
+        })
+    }
+
+    pub fn unblock_file(&self, blocked: &BlockedFile) -> QueryResult<BlockedFile> {
+        self.transaction(|| {
+            let blocked = BlockedFileRepository::delete(self.conn, blocked)?;
+            let target_id = blocked.md5.clone();
+            self.log(
+                ACTION_FILE_UNBLOCK,
+                "file",
+                &target_id,
+                Some(&blocked),
+                None,
+            )?;
+            Ok(blocked)
+        })
+    }
+
+    pub fn update_settings(&self, data: &ChatSettings) -> QueryResult<ChatSettings> {
+        self.transaction(|| {
+            let before = SettingRepository::get(self.conn)?;
+            let after = SettingRepository::update(self.conn, data, &self.actor_uuid)?;
+            self.log(
+                ACTION_SETTINGS_UPDATE,
+                "settings",
+                "chat",
+                Some(&before),
+                Some(&after),
+            )?;
+            Ok(after)
+        })
+    }
+
+    pub fn create_room(&self, room: &NewRoom) -> QueryResult<Room> {
+        self.transaction(|| {
+            let room = RoomRepository::create(self.conn, room)?;
+            let target_id = room.id.to_string();
+            self.log(ACTION_ROOM_CREATE, "room", &target_id, None, Some(&room))?;
+            Ok(room)
+        })
+    }
+
+    pub fn update_room(&self, room: &Room, data: &NewRoom) -> QueryResult<Room> {
+        self.transaction(|| {
+            let updated = RoomRepository::update(self.conn, room, data)?;
+            let target_id = room.id.to_string();
+            self.log(
+                ACTION_ROOM_UPDATE,
+                "room",
+                &target_id,
+                Some(room),
+                Some(&updated),
+            )?;
+            Ok(updated)
+        })
+    }
+
+    pub fn create_webhook(&self, webhook: &NewWebhook) -> QueryResult<Webhook> {
+        self.transaction(|| {
+            let webhook = WebhookRepository::create(self.conn, webhook)?;
+            let target_id = webhook.id.to_string();
+            self.log(
+                ACTION_WEBHOOK_CREATE,
+                "webhook",
+                &target_id,
+                None,
+                Some(&webhook),
+            )?;
+            Ok(webhook)
+        })
+    }
+
+    pub fn update_webhook(&self, webhook: &Webhook, data: &NewWebhook) -> QueryResult<Webhook> {
+        self.transaction(|| {
+            let updated = WebhookRepository::update(self.conn, webhook, data)?;
+            let target_id = webhook.id.to_string();
+            self.log(
+                ACTION_WEBHOOK_UPDATE,
+                "webhook",
+                &target_id,
+                Some(webhook),
+                Some(&updated),
+            )?;
+            Ok(updated)
+        })
+    }
+
+    pub fn delete_webhook(&self, webhook: &Webhook) -> QueryResult<Webhook> {
+        self.transaction(|| {
+            let deleted = WebhookRepository::delete(self.conn, webhook)?;
+            let target_id = webhook.id.to_string();
+            self.log(
+                ACTION_WEBHOOK_DELETE,
+                "webhook",
+                &target_id,
+                Some(webhook),
+                None,
+            )?;
+            Ok(deleted)
+        })
+    }
+
+    pub fn create_api_token(&self, token: &NewApiToken) -> QueryResult<ApiToken> {
+        self.transaction(|| {
+            let token = ApiTokenRepository::create(self.conn, token)?;
+            let target_id = token.id.to_string();
+            self.log(
+                ACTION_API_TOKEN_CREATE,
+                "api_token",
+                &target_id,
+                None,
+                Some(&token),
+            )?;
+            Ok(token)
+        })
+    }
+
+    pub fn revoke_api_token(&self, token: &ApiToken) -> QueryResult<ApiToken> {
+        self.transaction(|| {
+            let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+            let revoked = ApiTokenRepository::revoke(self.conn, token, now)?;
+            let target_id = token.id.to_string();
+            self.log(
+                ACTION_API_TOKEN_REVOKE,
+                "api_token",
+                &target_id,
+                Some(token),
+                Some(&revoked),
+            )?;
+            Ok(revoked)
+        })
+    }
+}
@@ -0,0 +1,486 @@
+use crate::front::TrustedProxies;
+use crate::routes::types::{Actor, Poster};
+use chat::models::api_tokens::{ApiToken, MAX_POST_BURST};
+use chat::models::rate_limits::{RateLimit, RateLimitBucket, RateLimitCharge};
+use chat::repositories::rate_limits::RateLimitRepository;
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::pg::PgConnection;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::{Config, Request, State};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// How many buckets are kept in memory before the full ones are forgotten.
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+/// How many buckets are left when there are too many that aren't full, after forgetting the
+/// least recently used ones.
+const MEMORY_BUCKETS_AFTER_EVICTION: usize = MAX_MEMORY_BUCKETS / 10 * 9;
+
+/// How many requests are handled between removing stale buckets from the database.
+const DATABASE_CLEANUP_INTERVAL: usize = 1000;
+
+pub enum RateLimitBackend {
+    Memory(Mutex<HashMap<String, RateLimitBucket>>),
+    Postgres(AtomicUsize),
+}
+
+/// Clones share the buckets, so that the WebSocket server and the routes have the same budgets.
+#[derive(Clone)]
+pub struct RateLimiter {
+    posts: RateLimit,
+    files: RateLimit,
+    backend: Arc<RateLimitBackend>,
+}
+
+impl RateLimiter {
+    pub fn new(posts: RateLimit, files: RateLimit, backend: RateLimitBackend) -> RateLimiter {
+        RateLimiter {
+            posts,
+            files,
+            backend: Arc::new(backend),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> RateLimiter {
+        let table = config.get_table("rate_limit").ok();
+        let get_f64 = |name: &str, default: f64| {
+            table
+                .and_then(|table| table.get(name))
+                .and_then(|value| value.as_float().or(value.as_integer().map(|v| v as f64)))
+                .unwrap_or(default)
+        };
+
+        let posts = RateLimit {
+            capacity: get_f64("post_burst", 5.0),
+            refill_per_second: get_f64("posts_per_minute", 10.0) / 60.0,
+        };
+
+        let files = RateLimit {
+            capacity: get_f64("file_burst", 10.0),
+            refill_per_second: get_f64("files_per_minute", 10.0) / 60.0,
+        };
+
+        let backend = table
+            .and_then(|table| table.get("backend"))
+            .and_then(|value| value.as_str());
+        let backend = match backend {
+            Some("postgres") => RateLimitBackend::Postgres(AtomicUsize::new(0)),
+            _ => RateLimitBackend::Memory(Mutex::new(HashMap::new())),
+        };
+
+        RateLimiter::new(posts, files, backend)
+    }
+
+    /// Takes tokens from the buckets kept in memory, forgetting the full ones when there are
+    /// too many, and then the least recently used ones if that isn't enough.
+    fn take_memory(
+        &self,
+        buckets: &Mutex<HashMap<String, RateLimitBucket>>,
+        charges: &[RateLimitCharge],
+        now: NaiveDateTime,
+    ) -> Result<(), u64> {
+        let mut buckets = buckets.lock().unwrap();
+        if buckets.len() >= MAX_MEMORY_BUCKETS {
+            let posts = self.posts;
+            let files = self.files;
+            buckets.retain(|key, bucket| {
+                // Bots have limits of their own, there are few enough to keep them.
+                if key.starts_with("posts:bot:") {
+                    return true;
+                }
+
+                let limit = if key.starts_with("files:") {
+                    &files
+                } else {
+                    &posts
+                };
+                !bucket.is_full(limit, now)
+            });
+        }
+
+        if buckets.len() >= MAX_MEMORY_BUCKETS {
+            let mut by_use: Vec<(NaiveDateTime, String)> = buckets
+                .iter()
+                .map(|(key, bucket)| (bucket.updated_at, key.clone()))
+                .collect();
+            by_use.sort_unstable();
+
+            let excess = buckets.len() - MEMORY_BUCKETS_AFTER_EVICTION;
+            for (_, key) in by_use.into_iter().take(excess) {
+                buckets.remove(&key);
+            }
+        }
+
+        let mut taken: Vec<RateLimitBucket> = charges
+            .iter()
+            .map(|charge| {
+                buckets
+                    .get(&charge.key)
+                    .cloned()
+                    .unwrap_or_else(|| RateLimitBucket::new(&charge.key, &charge.limit, now))
+            })
+            .collect();
+        let result = RateLimitBucket::take_all(&mut taken, charges, now);
+        for bucket in taken {
+            buckets.insert(bucket.key.clone(), bucket);
+        }
+
+        result
+    }
+
+    /// Takes tokens from every bucket if all of them have enough, otherwise takes none and
+    /// returns the longest wait.
+    pub fn take(&self, conn: &PgConnection, charges: &[RateLimitCharge]) -> Result<(), u64> {
+        match &*self.backend {
+            RateLimitBackend::Memory(buckets) => {
+                self.take_memory(buckets, charges, Utc::now().naive_utc())
+            }
+            RateLimitBackend::Postgres(counter) => {
+                if counter.fetch_add(1, Ordering::Relaxed) % DATABASE_CLEANUP_INTERVAL == 0 {
+                    // Buckets which had enough time to refill completely are indistinguishable
+                    // from new ones.
+                    let refill_seconds = [self.posts, self.files]
+                        .iter()
+                        .map(|limit| limit.capacity / limit.refill_per_second)
+                        .fold(0.0, f64::max)
+                        // Bots may refill as slowly as one post per minute.
+                        .max(f64::from(MAX_POST_BURST) * 60.0);
+                    let before = Utc::now().naive_utc()
+                        - Duration::seconds(refill_seconds.ceil().min(1e9) as i64);
+                    RateLimitRepository::delete_stale(conn, before);
+                }
+
+                RateLimitRepository::take(conn, charges)
+            }
+        }
+    }
+
+    fn get_charges(
+        prefix: &str,
+        keys: &[String],
+        limit: &RateLimit,
+        cost: f64,
+    ) -> Vec<RateLimitCharge> {
+        keys.iter()
+            .map(|key| RateLimitCharge::new(&format!("{}:{}", prefix, key), limit, cost))
+            .collect()
+    }
+
+    pub fn get_post_charges(&self, keys: &[String]) -> Vec<RateLimitCharge> {
+        RateLimiter::get_charges("posts", keys, &self.posts, 1.0)
+    }
+
+    /// Returns the post limit of the bot token, the configured one is used for the values it
+    /// doesn't set.
+    pub fn get_token_post_limit(&self, api_token: &ApiToken) -> RateLimit {
+        RateLimit {
+            capacity: api_token.post_burst.map_or(self.posts.capacity, f64::from),
+            refill_per_second: api_token
+                .posts_per_minute
+                .map_or(self.posts.refill_per_second, |value| {
+                    f64::from(value) / 60.0
+                }),
+        }
+    }
+
+    pub fn get_token_post_charge(&self, api_token: &ApiToken) -> RateLimitCharge {
+        let key = format!("posts:bot:{}", api_token.user_uuid);
+        RateLimitCharge::new(&key, &self.get_token_post_limit(api_token), 1.0)
+    }
+
+    pub fn get_file_charges(&self, keys: &[String], count: usize) -> Vec<RateLimitCharge> {
+        if count == 0 {
+            return Vec::new();
+        }
+
+        RateLimiter::get_charges("files", keys, &self.files, count as f64)
+    }
+}
+
+/// Request guard with the post budgets of the current user and client IP, or of the bot.
+/// Nothing is taken until the handler calls `take` once the data is parsed, so that a request
+/// trying several routes in turn, one per content type, is only charged once.
+pub struct PostRateLimit {
+    keys: Vec<String>,
+    /// Bots have the post limit of their token instead of the configured one.
+    api_token: Option<ApiToken>,
+}
+
+impl PostRateLimit {
+    /// Also used for requests that don't go through Rocket, like WebSocket commands.
+    pub fn new(user_uuid: Option<&str>, ip: Option<IpAddr>) -> PostRateLimit {
+        let mut keys = Vec::new();
+        if let Some(user_uuid) = user_uuid {
+            keys.push(format!("user:{}", user_uuid));
+        }
+
+        if let Some(ip) = ip {
+            keys.push(format!("ip:{}", ip));
+        }
+
+        PostRateLimit {
+            keys,
+            api_token: None,
+        }
+    }
+
+    /// The budgets of the bot replace the ones of the user and IP. They are shared by all the
+    /// tokens of the bot, with the limits of the token used. Files still count against the
+    /// configured limits.
+    pub fn for_token(api_token: ApiToken) -> PostRateLimit {
+        PostRateLimit {
+            keys: vec![format!("bot:{}", api_token.user_uuid)],
+            api_token: Some(api_token),
+        }
+    }
+
+    /// Takes a token from the post budgets and one per file from the file budgets, or nothing
+    /// if any of them is short.
+    pub fn take(
+        &self,
+        conn: &PgConnection,
+        limiter: &RateLimiter,
+        file_count: usize,
+    ) -> Result<(), TooManyRequests> {
+        let mut charges = match &self.api_token {
+            Some(api_token) => vec![limiter.get_token_post_charge(api_token)],
+            None => limiter.get_post_charges(&self.keys),
+        };
+        charges.extend(limiter.get_file_charges(&self.keys, file_count));
+
+        limiter.take(conn, &charges).map_err(TooManyRequests::new)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for PostRateLimit {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        let trusted_proxies = request.guard::<State<TrustedProxies>>()?;
+        let real_ip = request.headers().get_one("X-Real-IP");
+        let ip = request
+            .remote()
+            .map(|remote| trusted_proxies.get_client_ip(remote.ip(), real_ip));
+        let rate_limit = match request.guard::<Poster>() {
+            Outcome::Success(poster) => match poster.get() {
+                Actor::Bot(api_token) => PostRateLimit::for_token(api_token.clone()),
+                actor => PostRateLimit::new(Some(&actor.get_uuid()), ip),
+            },
+            _ => PostRateLimit::new(None, ip),
+        };
+
+        Outcome::Success(rate_limit)
+    }
+}
+
+#[derive(Serialize)]
+pub struct RateLimitErrorJson {
+    message: String,
+    retry_after: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct TooManyRequests {
+    retry_after: u64,
+}
+
+impl TooManyRequests {
+    pub fn new(retry_after: u64) -> TooManyRequests {
+        TooManyRequests { retry_after }
+    }
+
+    pub fn get_json(&self) -> RateLimitErrorJson {
+        RateLimitErrorJson {
+            message: String::from("Too many requests"),
+            retry_after: self.retry_after,
+        }
+    }
+}
+
+impl<'r> Responder<'r> for TooManyRequests {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let json = Json(self.get_json());
+
+        Response::build_from(json.respond_to(request)?)
+            .status(Status::TooManyRequests)
+            .raw_header("Retry-After", self.retry_after.to_string())
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        let limit = RateLimit {
+            capacity: 2.0,
+            refill_per_second: 1.0,
+        };
+        RateLimiter::new(
+            limit,
+            limit,
+            RateLimitBackend::Memory(Mutex::new(HashMap::new())),
+        )
+    }
+
+    fn get_buckets(limiter: &RateLimiter) -> &Mutex<HashMap<String, RateLimitBucket>> {
+        match &*limiter.backend {
+            RateLimitBackend::Memory(buckets) => buckets,
+            RateLimitBackend::Postgres(_) => unreachable!(),
+        }
+    }
+
+    fn take(limiter: &RateLimiter, keys: &[&str], now: NaiveDateTime) -> Result<(), u64> {
+        let charges: Vec<RateLimitCharge> = keys
+            .iter()
+            .map(|key| RateLimitCharge::new(key, &limiter.posts, 1.0))
+            .collect();
+        limiter.take_memory(get_buckets(limiter), &charges, now)
+    }
+
+    fn get_now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 10, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn take_memory_until_empty() {
+        let limiter = limiter();
+        let now = get_now();
+        assert_eq!(Ok(()), take(&limiter, &["posts:ip:1"], now));
+        assert_eq!(Ok(()), take(&limiter, &["posts:ip:1"], now));
+        assert_eq!(Err(1), take(&limiter, &["posts:ip:1"], now));
+        assert_eq!(
+            Ok(()),
+            take(&limiter, &["posts:ip:1"], now + Duration::seconds(1))
+        );
+    }
+
+    #[test]
+    fn take_memory_all_or_nothing() {
+        let limiter = limiter();
+        let now = get_now();
+        take(&limiter, &["posts:ip:1"], now).unwrap();
+        take(&limiter, &["posts:ip:1"], now).unwrap();
+
+        // The user bucket isn't charged when the IP bucket is empty.
+        assert_eq!(Err(1), take(&limiter, &["posts:user:a", "posts:ip:1"], now));
+        assert_eq!(Ok(()), take(&limiter, &["posts:user:a"], now));
+        assert_eq!(Ok(()), take(&limiter, &["posts:user:a"], now));
+        assert_eq!(Err(1), take(&limiter, &["posts:user:a"], now));
+    }
+
+    #[test]
+    fn take_memory_posts_and_files_all_or_nothing() {
+        let limiter = limiter();
+        let now = get_now();
+        let keys = vec![String::from("ip:1")];
+        let mut charges = limiter.get_post_charges(&keys);
+        charges.extend(limiter.get_file_charges(&keys, 2));
+        let buckets = get_buckets(&limiter);
+        assert_eq!(Ok(()), limiter.take_memory(buckets, &charges, now));
+
+        // The post budget isn't charged when the file budget is empty.
+        assert_eq!(Err(2), limiter.take_memory(buckets, &charges, now));
+        assert_eq!(Ok(()), take(&limiter, &["posts:ip:1"], now));
+    }
+
+    #[test]
+    fn take_memory_forgets_least_recently_used() {
+        let limit = RateLimit {
+            capacity: 2.0,
+            refill_per_second: 0.001,
+        };
+        let limiter = RateLimiter::new(
+            limit,
+            limit,
+            RateLimitBackend::Memory(Mutex::new(HashMap::new())),
+        );
+        let now = get_now();
+        for index in 0..MAX_MEMORY_BUCKETS {
+            let key = format!("posts:ip:{}", index);
+            let now = now + Duration::milliseconds(index as i64);
+            take(&limiter, &[&key], now).unwrap();
+        }
+
+        take(&limiter, &["posts:ip:new"], now + Duration::seconds(60)).unwrap();
+        let buckets = get_buckets(&limiter).lock().unwrap();
+        assert!(buckets.len() <= MEMORY_BUCKETS_AFTER_EVICTION + 1);
+        assert!(!buckets.contains_key("posts:ip:0"));
+        assert!(buckets.contains_key(&format!("posts:ip:{}", MAX_MEMORY_BUCKETS - 1)));
+        assert!(buckets.contains_key("posts:ip:new"));
+    }
+
+    fn api_token() -> ApiToken {
+        ApiToken {
+            id: 1,
+            user_uuid: String::from("bot"),
+            name: String::from("Bot"),
+            token_hash: String::new(),
+            scopes: Vec::new(),
+            post_burst: None,
+            posts_per_minute: None,
+            created_by: String::from("admin"),
+            created_at: get_now(),
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn get_token_post_limit() {
+        let limiter = limiter();
+        let mut api_token = api_token();
+        let limit = limiter.get_token_post_limit(&api_token);
+        assert_eq!(2.0, limit.capacity);
+        assert_eq!(1.0, limit.refill_per_second);
+
+        api_token.post_burst = Some(20);
+        api_token.posts_per_minute = Some(30);
+        let limit = limiter.get_token_post_limit(&api_token);
+        assert_eq!(20.0, limit.capacity);
+        assert_eq!(0.5, limit.refill_per_second);
+    }
+
+    #[test]
+    fn post_rate_limit_keys() {
+        let ip = "127.0.0.1".parse().ok();
+        assert_eq!(
+            vec!["user:a", "ip:127.0.0.1"],
+            PostRateLimit::new(Some("a"), ip).keys
+        );
+        assert_eq!(vec!["ip:127.0.0.1"], PostRateLimit::new(None, ip).keys);
+        assert!(PostRateLimit::new(None, None).keys.is_empty());
+        assert_eq!(vec!["bot:bot"], PostRateLimit::for_token(api_token()).keys);
+    }
+
+    #[test]
+    fn bot_tokens_share_post_budget() {
+        let limiter = limiter();
+        let now = get_now();
+        let first = api_token();
+        let second = ApiToken {
+            id: 2,
+            ..api_token()
+        };
+        let take = |api_token: &ApiToken| {
+            let charge = limiter.get_token_post_charge(api_token);
+            limiter.take_memory(get_buckets(&limiter), &[charge], now)
+        };
+
+        assert_eq!(Ok(()), take(&first));
+        assert_eq!(Ok(()), take(&second));
+        assert_eq!(Err(1), take(&first));
+    }
+}
@@ -1,5 +1,6 @@
+use chat::models::files::File;
 use rocket::data::{FromDataSimple, Outcome};
-use rocket::http::ContentType;
+use rocket::http::{ContentType, Status};
 use rocket::{Data, Request};
 use rocket_multipart_form_data::{
     MultipartFormData, MultipartFormDataField, MultipartFormDataOptions, Repetition,
@@ -10,6 +11,8 @@ pub struct UploadedFile {
     pub content_type: Option<mime::Mime>,
     pub file_name: Option<String>,
     pub path: PathBuf,
+    /// Computed once on upload, every later check and the stored file use it.
+    pub md5: String,
 }
 
 pub struct CreatePostMultipart {
@@ -29,13 +32,20 @@ impl FromDataSimple for CreatePostMultipart {
             let options = MultipartFormDataOptions::with_multipart_form_data_fields(vec![
                 MultipartFormDataField::text("name").size_limit(255),
                 MultipartFormDataField::text("tripcode").size_limit(255),
-                MultipartFormDataField::text("message").size_limit(8000),
+                // Up to this size, overlong messages get a validation error like in JSON requests.
+                MultipartFormDataField::text("message").size_limit(64 * 1024), // 64 KB.
                 MultipartFormDataField::file("file")
                     .repetition(Repetition::fixed(5))
                     .size_limit(100 * 1024 * 1024), // 100 MB.
             ]);
 
-            let mut form_data = MultipartFormData::parse(req_ct, data, options).unwrap();
+            let mut form_data = match MultipartFormData::parse(req_ct, data, options) {
+                Ok(form_data) => form_data,
+                Err(_) => {
+                    let message = String::from("Invalid multipart form data");
+                    return Outcome::Failure((Status::BadRequest, message));
+                }
+            };
 
             let name = match form_data.texts.remove("name") {
                 Some(mut name_fields) => {
@@ -64,13 +74,21 @@ impl FromDataSimple for CreatePostMultipart {
             let files = match form_data.files.remove("file") {
                 Some(file_fields) => file_fields
                     .into_iter()
-                    .map(|field| UploadedFile {
-                        content_type: field.content_type,
-                        file_name: field.file_name,
-                        path: field.path,
+                    .map(|field| {
+                        Ok(UploadedFile {
+                            md5: File::get_md5(&field.path)?,
+                            content_type: field.content_type,
+                            file_name: field.file_name,
+                            path: field.path,
+                        })
                     })
                     .collect(),
-                None => Vec::new(),
+                None => Ok(Vec::new()),
+            };
+
+            let files = match files {
+                Ok(files) => files,
+                Err(message) => return Outcome::Failure((Status::InternalServerError, message)),
             };
 
             Outcome::Success(CreatePostMultipart {
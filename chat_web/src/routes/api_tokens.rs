@@ -0,0 +1,131 @@
+use crate::moderation::Moderation;
+use crate::routes::types::Admin;
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::api_tokens::ApiToken;
+use chat::repositories::api_tokens::ApiTokenRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct ApiTokenRequest {
+    name: String,
+    scopes: Vec<String>,
+    post_burst: Option<i32>,
+    posts_per_minute: Option<i32>,
+    /// Creates another token for an existing bot account instead of a new account. It must be
+    /// the UUID of a bot, tokens can't be created for users.
+    user_uuid: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenResponse {
+    item: ApiToken,
+}
+
+/// The plain token is only ever returned here, when the token is created.
+#[derive(Serialize)]
+pub struct CreatedApiTokenResponse {
+    item: ApiToken,
+    token: String,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenListResponse {
+    items: Vec<ApiToken>,
+}
+
+#[derive(Responder)]
+pub enum UpdateApiTokenResponse {
+    Created(Created<Json<CreatedApiTokenResponse>>),
+    Updated(Json<ApiTokenResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateApiTokenResponse {
+    fn created(api_token: ApiToken, token: String) -> UpdateApiTokenResponse {
+        let location = format!("/api/v1/admin/tokens/{}", api_token.id);
+        let json = Json(CreatedApiTokenResponse {
+            item: api_token,
+            token,
+        });
+        UpdateApiTokenResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(api_token: ApiToken) -> UpdateApiTokenResponse {
+        let json = Json(ApiTokenResponse { item: api_token });
+        UpdateApiTokenResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateApiTokenResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateApiTokenResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateApiTokenResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateApiTokenResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_api_token_list(_admin: Admin, conn: ChatDbConn) -> Json<ApiTokenListResponse> {
+    let items = ApiTokenRepository::get_all(&conn);
+    Json(ApiTokenListResponse { items })
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_api_token(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<ApiTokenRequest>,
+) -> Result<UpdateApiTokenResponse, DatabaseErrorResponse> {
+    let bot = match &data.user_uuid {
+        Some(user_uuid) => match ApiTokenRepository::get_one_by_user_uuid(&conn, user_uuid) {
+            Some(bot) => Some(bot),
+            None => return Ok(UpdateApiTokenResponse::not_found("Bot not found")),
+        },
+        None => None,
+    };
+
+    let result = ApiToken::new(
+        &data.name,
+        data.scopes.clone(),
+        data.post_burst,
+        data.posts_per_minute,
+        bot.as_ref(),
+        &admin.get_uuid(),
+    );
+    match result {
+        Ok((new_token, token)) => {
+            let api_token =
+                Moderation::new(&conn, &admin.get_uuid()).create_api_token(&new_token)?;
+            Ok(UpdateApiTokenResponse::created(api_token, token))
+        }
+        Err(message) => Ok(UpdateApiTokenResponse::bad_request(&message)),
+    }
+}
+
+/// Revoked tokens are kept, so that the audit log and the posts of the bot still make sense.
+#[delete("/<id>")]
+pub fn revoke_api_token(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+) -> Result<UpdateApiTokenResponse, DatabaseErrorResponse> {
+    match ApiTokenRepository::get_one(&conn, id) {
+        Some(api_token) if api_token.is_revoked() => Ok(UpdateApiTokenResponse::bad_request(
+            "Token is already revoked",
+        )),
+        Some(api_token) => {
+            let api_token =
+                Moderation::new(&conn, &admin.get_uuid()).revoke_api_token(&api_token)?;
+            Ok(UpdateApiTokenResponse::updated(api_token))
+        }
+        None => Ok(UpdateApiTokenResponse::not_found("Not found")),
+    }
+}
@@ -0,0 +1,111 @@
+use crate::moderation::Moderation;
+use crate::routes::types::Admin;
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::blocked_files::BlockedFile;
+use chat::models::files::File;
+use chat::repositories::blocked_files::BlockedFileRepository;
+use chat::repositories::files::FileRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateBlockedFileRequest {
+    md5: String,
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Serialize)]
+pub struct BlockedFileResponse {
+    item: BlockedFile,
+}
+
+#[derive(Serialize)]
+pub struct BlockedFileListResponse {
+    items: Vec<BlockedFile>,
+}
+
+#[derive(Responder)]
+pub enum UpdateBlockedFileResponse {
+    Created(Created<Json<BlockedFileResponse>>),
+    Deleted(Json<BlockedFileResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateBlockedFileResponse {
+    fn created(blocked: BlockedFile) -> UpdateBlockedFileResponse {
+        let location = format!("/api/v1/blocklist/{}", blocked.id);
+        let json = Json(BlockedFileResponse { item: blocked });
+        UpdateBlockedFileResponse::Created(Created(location, Some(json)))
+    }
+
+    fn deleted(blocked: BlockedFile) -> UpdateBlockedFileResponse {
+        let json = Json(BlockedFileResponse { item: blocked });
+        UpdateBlockedFileResponse::Deleted(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateBlockedFileResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateBlockedFileResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateBlockedFileResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateBlockedFileResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_blocked_file_list(_admin: Admin, conn: ChatDbConn) -> Json<BlockedFileListResponse> {
+    let items = BlockedFileRepository::get_all(&conn);
+    Json(BlockedFileListResponse { items })
+}
+
+/// Blocks an already uploaded file by its md5. The perceptual hash is computed from the stored
+/// source, so re-encoded copies of an image are blocked too.
+#[post("/", format = "json", data = "<data>")]
+pub fn create_blocked_file(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<CreateBlockedFileRequest>,
+) -> Result<UpdateBlockedFileResponse, DatabaseErrorResponse> {
+    if BlockedFileRepository::get_one_by_md5(&conn, &data.md5).is_some() {
+        return Ok(UpdateBlockedFileResponse::bad_request(
+            "File is already blocked",
+        ));
+    }
+
+    let file = match FileRepository::get_one_by_md5(&conn, &data.md5) {
+        Some(file) => file,
+        None => return Ok(UpdateBlockedFileResponse::not_found("Not found")),
+    };
+
+    let phash = File::get_perceptual_hash_from_path(&file.get_source_path());
+    match BlockedFile::new(&file.md5, phash, &data.reason, &admin.get_uuid()) {
+        Ok(new_blocked) => {
+            let blocked = Moderation::new(&conn, &admin.get_uuid()).block_file(&new_blocked)?;
+            Ok(UpdateBlockedFileResponse::created(blocked))
+        }
+        Err(message) => Ok(UpdateBlockedFileResponse::bad_request(&message)),
+    }
+}
+
+#[delete("/<id>")]
+pub fn delete_blocked_file(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+) -> Result<UpdateBlockedFileResponse, DatabaseErrorResponse> {
+    match BlockedFileRepository::get_one(&conn, id) {
+        Some(blocked) => {
+            let blocked = Moderation::new(&conn, &admin.get_uuid()).unblock_file(&blocked)?;
+            Ok(UpdateBlockedFileResponse::deleted(blocked))
+        }
+        None => Ok(UpdateBlockedFileResponse::not_found("Not found")),
+    }
+}
@@ -0,0 +1,280 @@
+use crate::events::{Audience, ChatEvent, EventBus};
+use crate::rate_limit::{PostRateLimit, TooManyRequests};
+use crate::requests::{CreatePostMultipart, UploadedFile};
+use crate::routes::posts::{check_message, PostContext};
+use crate::routes::types::{Authenticated, ConversationWithMessage, DirectMessageWithFiles};
+use crate::ChatDbConn;
+use chat::models::direct_messages::DirectMessage;
+use chat::models::files::File;
+use chat::models::message_parser::MessageParserOptions;
+use chat::models::posts::FieldError;
+use chat::repositories::direct_messages::DirectMessageRepository;
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use diesel::pg::PgConnection;
+use diesel::Connection;
+use rocket::http::Status;
+use rocket::response::status::{Created, Custom};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateDirectMessageJson {
+    message: String,
+}
+
+#[derive(Serialize)]
+pub struct ConversationListResponse {
+    items: Vec<ConversationWithMessage>,
+    unread_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct DirectMessageListResponse {
+    items: Vec<DirectMessageWithFiles>,
+}
+
+#[derive(Serialize)]
+pub struct DirectMessageResponse {
+    item: DirectMessageWithFiles,
+}
+
+#[derive(Serialize)]
+pub struct UnreadCountResponse {
+    unread_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct ValidationErrorJson {
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+#[derive(Responder)]
+pub enum CreateDirectMessageResponse {
+    Created(Created<Json<DirectMessageResponse>>),
+    Invalid(Custom<Json<ValidationErrorJson>>),
+    TooManyRequests(TooManyRequests),
+}
+
+impl CreateDirectMessageResponse {
+    fn created(message: DirectMessageWithFiles) -> CreateDirectMessageResponse {
+        let other_uuid = &message.recipient_uuid;
+        let location = format!("/api/v1/conversations/{}/messages", other_uuid);
+        let json = Json(DirectMessageResponse { item: message });
+        CreateDirectMessageResponse::Created(Created(location, Some(json)))
+    }
+
+    fn invalid(errors: Vec<FieldError>) -> CreateDirectMessageResponse {
+        let message = String::from("Validation failed");
+        let json = Json(ValidationErrorJson { message, errors });
+        CreateDirectMessageResponse::Invalid(Custom(Status::UnprocessableEntity, json))
+    }
+}
+
+fn create_direct_message(
+    conn: &PgConnection,
+    context: &PostContext,
+    sender_uuid: &str,
+    recipient_uuid: &str,
+    message: &str,
+    files: Vec<UploadedFile>,
+) -> Result<DirectMessageWithFiles, Vec<FieldError>> {
+    // Flags point at posts, so direct messages are only rejected or rewritten by word filters.
+    let checked = check_message(conn, context, Some(sender_uuid), None, message, &files)?;
+    let mut new_message = DirectMessage::new(
+        sender_uuid,
+        recipient_uuid,
+        &checked.message,
+        files.len(),
+        &context.limits,
+    )?;
+    new_message.quarantined = checked.quarantined;
+
+    // A file which can't be stored rolls the message back along with the files before it.
+    let mut file_error = None;
+    let created = conn.transaction::<_, diesel::result::Error, _>(|| {
+        let message = DirectMessageRepository::create(conn, &new_message);
+        let mut created_files = Vec::new();
+        for file in files {
+            let new_file =
+                File::new_for_direct_message(file.file_name, file.path, file.md5, message.id);
+            match new_file {
+                Ok(new_file) => created_files.push(FileRepository::create(conn, &new_file)),
+                Err(message) => {
+                    file_error = Some(message);
+                    return Err(diesel::result::Error::RollbackTransaction);
+                }
+            }
+        }
+
+        Ok((message, created_files))
+    });
+
+    let (message, files) = match (created, file_error) {
+        (Ok(created), _) => created,
+        (Err(_), Some(message)) => return Err(vec![FieldError::new("files", "invalid", message)]),
+        (Err(e), None) => panic!("Can't create direct message: {}", e),
+    };
+
+    let emoji = EmojiRepository::get_map(conn);
+    Ok(DirectMessageWithFiles::new(
+        message,
+        files,
+        &emoji,
+        &context.options,
+    ))
+}
+
+/// Delivers the message only to the authenticated connections of the two participants, or of
+/// its sender alone if it is quarantined.
+fn send_direct_message_created_event(events: &dyn EventBus, data: &DirectMessageWithFiles) {
+    let mut uuids = vec![data.sender_uuid.clone()];
+    if !data.quarantined {
+        uuids.push(data.recipient_uuid.clone());
+    }
+
+    events.publish(
+        Audience::Users(uuids),
+        &ChatEvent::DirectMessageCreated { item: data },
+    );
+}
+
+#[get("/", format = "json")]
+pub fn get_conversation_list(
+    auth: Authenticated,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+) -> Json<ConversationListResponse> {
+    let uuid = auth.get_uuid();
+    let conversations = DirectMessageRepository::get_conversations_for_user(&conn, &uuid);
+    let messages: Vec<DirectMessage> = conversations
+        .iter()
+        .map(|conversation| conversation.last_message.clone())
+        .collect();
+
+    let files = FileRepository::get_belonging_to_direct_messages(&conn, &messages);
+    let emoji = EmojiRepository::get_map(&conn);
+    let unread_count = conversations
+        .iter()
+        .map(|conversation| conversation.unread_count)
+        .sum();
+
+    let items = conversations
+        .into_iter()
+        .zip(messages)
+        .zip(files)
+        .map(|((conversation, message), files)| {
+            let message = DirectMessageWithFiles::new(message, files, &emoji, &options);
+            ConversationWithMessage::new(conversation, message)
+        })
+        .collect();
+
+    Json(ConversationListResponse {
+        items,
+        unread_count,
+    })
+}
+
+#[get("/unread", format = "json")]
+pub fn get_unread_count(auth: Authenticated, conn: ChatDbConn) -> Json<UnreadCountResponse> {
+    let unread_count = DirectMessageRepository::count_unread_for_user(&conn, &auth.get_uuid());
+
+    Json(UnreadCountResponse { unread_count })
+}
+
+#[get("/<user_uuid>/messages?<before_id>", format = "json")]
+pub fn get_direct_message_list(
+    auth: Authenticated,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    user_uuid: String,
+    before_id: Option<i32>,
+) -> Json<DirectMessageListResponse> {
+    let uuid = auth.get_uuid();
+    let messages = DirectMessageRepository::get_between(&conn, &uuid, &user_uuid, before_id);
+    let files = FileRepository::get_belonging_to_direct_messages(&conn, &messages);
+    let emoji = EmojiRepository::get_map(&conn);
+    let items = messages
+        .into_iter()
+        .zip(files)
+        .map(|(message, files)| DirectMessageWithFiles::new(message, files, &emoji, &options))
+        .collect();
+
+    Json(DirectMessageListResponse { items })
+}
+
+/// Marks the messages received from the user as read.
+#[post("/<user_uuid>/read")]
+pub fn read_conversation(
+    auth: Authenticated,
+    conn: ChatDbConn,
+    user_uuid: String,
+) -> Json<UnreadCountResponse> {
+    let uuid = auth.get_uuid();
+    DirectMessageRepository::mark_read(&conn, &uuid, &user_uuid);
+    let unread_count = DirectMessageRepository::count_unread_for_user(&conn, &uuid);
+
+    Json(UnreadCountResponse { unread_count })
+}
+
+#[post("/<user_uuid>/messages", format = "json", data = "<data>")]
+pub fn create_direct_message_json(
+    auth: Authenticated,
+    rate_limit: PostRateLimit,
+    conn: ChatDbConn,
+    context: State<PostContext>,
+    user_uuid: String,
+    data: Json<CreateDirectMessageJson>,
+) -> CreateDirectMessageResponse {
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, 0) {
+        return CreateDirectMessageResponse::TooManyRequests(error);
+    }
+
+    let result = create_direct_message(
+        &conn,
+        &context,
+        &auth.get_uuid(),
+        &user_uuid,
+        &data.message,
+        Vec::new(),
+    );
+    match result {
+        Ok(message) => {
+            send_direct_message_created_event(&*context.events, &message);
+            CreateDirectMessageResponse::created(message)
+        }
+        Err(errors) => CreateDirectMessageResponse::invalid(errors),
+    }
+}
+
+#[post("/<user_uuid>/messages", data = "<data>", rank = 2)]
+pub fn create_direct_message_multipart(
+    auth: Authenticated,
+    rate_limit: PostRateLimit,
+    conn: ChatDbConn,
+    context: State<PostContext>,
+    user_uuid: String,
+    data: CreatePostMultipart,
+) -> CreateDirectMessageResponse {
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, data.files.len()) {
+        return CreateDirectMessageResponse::TooManyRequests(error);
+    }
+
+    let result = create_direct_message(
+        &conn,
+        &context,
+        &auth.get_uuid(),
+        &user_uuid,
+        &data.message,
+        data.files,
+    );
+    match result {
+        Ok(message) => {
+            send_direct_message_created_event(&*context.events, &message);
+            CreateDirectMessageResponse::created(message)
+        }
+        Err(errors) => CreateDirectMessageResponse::invalid(errors),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::moderation::Moderation;
+use crate::routes::types::{Admin, EmojiWithFile};
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::emoji::CustomEmoji;
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateEmojiRequest {
+    shortcode: String,
+    md5: String,
+}
+
+#[derive(Serialize)]
+pub struct EmojiResponse {
+    item: EmojiWithFile,
+}
+
+#[derive(Serialize)]
+pub struct EmojiListResponse {
+    items: Vec<EmojiWithFile>,
+}
+
+#[derive(Responder)]
+pub enum UpdateEmojiResponse {
+    Created(Created<Json<EmojiResponse>>),
+    Deleted(Json<EmojiResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateEmojiResponse {
+    fn created(emoji: EmojiWithFile) -> UpdateEmojiResponse {
+        let location = format!("/api/v1/emoji/{}", emoji.shortcode);
+        let json = Json(EmojiResponse { item: emoji });
+        UpdateEmojiResponse::Created(Created(location, Some(json)))
+    }
+
+    fn deleted(emoji: EmojiWithFile) -> UpdateEmojiResponse {
+        let json = Json(EmojiResponse { item: emoji });
+        UpdateEmojiResponse::Deleted(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateEmojiResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateEmojiResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateEmojiResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateEmojiResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_emoji_list(conn: ChatDbConn) -> Json<EmojiListResponse> {
+    let items = EmojiRepository::get_all(&conn)
+        .into_iter()
+        .map(|(emoji, file)| EmojiWithFile::new(emoji, file))
+        .collect();
+
+    Json(EmojiListResponse { items })
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_emoji(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<CreateEmojiRequest>,
+) -> Result<UpdateEmojiResponse, DatabaseErrorResponse> {
+    if EmojiRepository::get_one_by_shortcode(&conn, &data.shortcode).is_some() {
+        return Ok(UpdateEmojiResponse::bad_request(
+            "Shortcode is already taken",
+        ));
+    }
+
+    let file = match FileRepository::get_one_by_md5(&conn, &data.md5) {
+        Some(file) => file,
+        None => return Ok(UpdateEmojiResponse::not_found("Not found")),
+    };
+
+    match CustomEmoji::new(&data.shortcode, &file, &admin.get_uuid()) {
+        Ok(new_emoji) => {
+            let emoji = Moderation::new(&conn, &admin.get_uuid()).create_emoji(&new_emoji)?;
+            Ok(UpdateEmojiResponse::created(EmojiWithFile::new(
+                emoji, file,
+            )))
+        }
+        Err(message) => Ok(UpdateEmojiResponse::bad_request(&message)),
+    }
+}
+
+#[delete("/<shortcode>")]
+pub fn delete_emoji(
+    admin: Admin,
+    conn: ChatDbConn,
+    shortcode: String,
+) -> Result<UpdateEmojiResponse, DatabaseErrorResponse> {
+    match EmojiRepository::get_one_by_shortcode(&conn, &shortcode) {
+        Some((emoji, file)) => {
+            let emoji = Moderation::new(&conn, &admin.get_uuid()).delete_emoji(&emoji)?;
+            Ok(UpdateEmojiResponse::deleted(EmojiWithFile::new(
+                emoji, file,
+            )))
+        }
+        None => Ok(UpdateEmojiResponse::not_found("Not found")),
+    }
+}
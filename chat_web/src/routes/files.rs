@@ -1,4 +1,5 @@
 use crate::routes::types::Authenticated;
+use crate::routes::ErrorJson;
 use crate::ChatDbConn;
 use chat::models::files::{File, UserFavoriteFile};
 use chat::repositories::files::FileRepository;
@@ -11,11 +12,6 @@ pub struct CreateFavoriteRequest {
     md5: String,
 }
 
-#[derive(Serialize)]
-pub struct ErrorJson {
-    message: String,
-}
-
 #[derive(Serialize)]
 pub struct FileResponse {
     item: File,
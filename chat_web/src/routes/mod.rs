@@ -1,5 +1,55 @@
+use diesel::result::{DatabaseErrorKind, Error};
+use rocket::http::Status;
+use rocket::response::status::{BadRequest, Custom};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+pub mod api_tokens;
+pub mod blocked_files;
+pub mod direct_messages;
+pub mod emoji;
 pub mod files;
+pub mod moderation;
 pub mod notifications;
 pub mod posts;
+pub mod reports;
+pub mod rooms;
+pub mod settings;
 pub mod thumbnails;
 pub mod types;
+pub mod webhooks;
+pub mod word_filters;
+
+/// The body of error responses.
+#[derive(Debug, Serialize)]
+pub struct ErrorJson {
+    pub message: String,
+}
+
+/// Response to a database write that failed. Writes that conflict with existing rows come from
+/// the request, anything else is an error of the server.
+#[derive(Debug, Responder)]
+pub enum DatabaseErrorResponse {
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    InternalServerError(Custom<Json<ErrorJson>>),
+}
+
+impl From<Error> for DatabaseErrorResponse {
+    fn from(error: Error) -> DatabaseErrorResponse {
+        match error {
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+            | Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                let message = String::from("Conflicts with existing data");
+                let json = Json(ErrorJson { message });
+                DatabaseErrorResponse::BadRequest(BadRequest(Some(json)))
+            }
+            error => {
+                println!("Database error: {}", error);
+                let message = String::from("Internal server error");
+                let json = Json(ErrorJson { message });
+                let status = Status::InternalServerError;
+                DatabaseErrorResponse::InternalServerError(Custom(status, json))
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::events::{Audience, ChatEvent, EventBus, SharedEventBus};
+use crate::moderation::Moderation;
+use crate::routes::types::{Admin, Moderator, PostWithFiles};
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::audit_log::{AuditLogEntry, AuditLogFilter};
+use chat::models::files::File;
+use chat::models::message_parser::MessageParserOptions;
+use chat::models::restrictions::{UserRestriction, KIND_BAN};
+use chat::repositories::audit_log::AuditLogRepository;
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use chat::repositories::posts::PostRepository;
+use chat::repositories::restrictions::RestrictionRepository;
+use chrono::prelude::*;
+use chrono::Duration;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateRestrictionRequest {
+    user_uuid: String,
+    kind: Option<String>,
+    #[serde(default)]
+    reason: String,
+    expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct PostResponse {
+    item: PostWithFiles,
+}
+
+#[derive(Serialize)]
+pub struct FileResponse {
+    item: File,
+}
+
+/// The media errors are reported so that the moderator can check the files which are left.
+#[derive(Serialize)]
+pub struct FileTakeDownResponse {
+    items: Vec<File>,
+    errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct RestrictionResponse {
+    item: UserRestriction,
+}
+
+#[derive(Serialize)]
+pub struct RestrictionListResponse {
+    items: Vec<UserRestriction>,
+}
+
+#[derive(Serialize)]
+pub struct AuditLogListResponse {
+    items: Vec<AuditLogEntry>,
+}
+
+#[derive(Responder)]
+pub enum GetAuditLogResponse {
+    Found(Json<AuditLogListResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+}
+
+impl GetAuditLogResponse {
+    fn found(items: Vec<AuditLogEntry>) -> GetAuditLogResponse {
+        GetAuditLogResponse::Found(Json(AuditLogListResponse { items }))
+    }
+
+    fn bad_request(error: &str) -> GetAuditLogResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        GetAuditLogResponse::BadRequest(BadRequest(Some(json)))
+    }
+}
+
+#[derive(Responder)]
+pub enum DeleteFileResponse {
+    Deleted(Json<FileResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl DeleteFileResponse {
+    fn deleted(file: File) -> DeleteFileResponse {
+        let json = Json(FileResponse { item: file });
+        DeleteFileResponse::Deleted(json)
+    }
+
+    fn bad_request(error: &str) -> DeleteFileResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        DeleteFileResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> DeleteFileResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        DeleteFileResponse::NotFound(NotFound(json))
+    }
+}
+
+#[derive(Responder)]
+pub enum UpdateRestrictionResponse {
+    Created(Created<Json<RestrictionResponse>>),
+    Updated(Json<RestrictionResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateRestrictionResponse {
+    fn created(restriction: UserRestriction) -> UpdateRestrictionResponse {
+        let location = format!("/api/v1/admin/restrictions/{}", restriction.id);
+        let json = Json(RestrictionResponse { item: restriction });
+        UpdateRestrictionResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(restriction: UserRestriction) -> UpdateRestrictionResponse {
+        let json = Json(RestrictionResponse { item: restriction });
+        UpdateRestrictionResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateRestrictionResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateRestrictionResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateRestrictionResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateRestrictionResponse::NotFound(NotFound(json))
+    }
+}
+
+pub fn send_post_hidden_event(events: &dyn EventBus, post_id: i32) {
+    events.publish(Audience::All, &ChatEvent::PostHidden { id: post_id });
+}
+
+/// Parses either a full timestamp or a date. A date in the `to` bound includes the whole day.
+fn parse_date(value: &str, is_end: bool) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Some(datetime);
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let datetime = date.and_hms_opt(0, 0, 0)?;
+    if is_end {
+        Some(datetime + Duration::days(1))
+    } else {
+        Some(datetime)
+    }
+}
+
+/// Removes the post from the chat. The post is kept in the database as hidden.
+#[delete("/<post_id>")]
+pub fn delete_post(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    events: State<SharedEventBus>,
+    post_id: i32,
+) -> Result<Option<Json<PostResponse>>, DatabaseErrorResponse> {
+    let post = match PostRepository::get_one(&conn, &moderator.get_viewer(), post_id) {
+        Some(post) => post,
+        None => return Ok(None),
+    };
+
+    let post = Moderation::new(&conn, &moderator.get_uuid()).hide_post(&post)?;
+    send_post_hidden_event(&**events, post.id);
+
+    let files = FileRepository::get_belonging_to_post(&conn, &post);
+    let emoji = EmojiRepository::get_map(&conn);
+    let item = PostWithFiles::new(post, files, &emoji, &options);
+    Ok(Some(Json(PostResponse { item })))
+}
+
+#[delete("/<file_id>")]
+pub fn delete_file(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    file_id: i32,
+) -> Result<DeleteFileResponse, DatabaseErrorResponse> {
+    let file = match FileRepository::get_one(&conn, file_id) {
+        Some(file) => file,
+        None => return Ok(DeleteFileResponse::not_found("Not found")),
+    };
+
+    match Moderation::new(&conn, &moderator.get_uuid()).remove_file(&file)? {
+        Ok(file) => Ok(DeleteFileResponse::deleted(file)),
+        Err(message) => Ok(DeleteFileResponse::bad_request(&message)),
+    }
+}
+
+/// Takes down the media of a file from every post it is attached to.
+#[post("/<md5>/takedown")]
+pub fn take_down_file(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    md5: String,
+) -> Result<Option<Json<FileTakeDownResponse>>, DatabaseErrorResponse> {
+    let (items, errors) = Moderation::new(&conn, &moderator.get_uuid()).take_down_file(&md5)?;
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Json(FileTakeDownResponse { items, errors })))
+}
+
+#[get("/", format = "json")]
+pub fn get_restriction_list(
+    _moderator: Moderator,
+    conn: ChatDbConn,
+) -> Json<RestrictionListResponse> {
+    let items = RestrictionRepository::get_active(&conn);
+    Json(RestrictionListResponse { items })
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_restriction(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    data: Json<CreateRestrictionRequest>,
+) -> Result<UpdateRestrictionResponse, DatabaseErrorResponse> {
+    let kind = data.kind.as_deref().unwrap_or(KIND_BAN);
+    let uuid = moderator.get_uuid();
+    if RestrictionRepository::get_active_for_user(&conn, &data.user_uuid, kind).is_some() {
+        return Ok(UpdateRestrictionResponse::bad_request(
+            "User is already restricted",
+        ));
+    }
+
+    match UserRestriction::new(&data.user_uuid, kind, &data.reason, data.expires_at, &uuid) {
+        Ok(new_restriction) => {
+            let restriction = Moderation::new(&conn, &uuid).restrict_user(&new_restriction)?;
+            Ok(UpdateRestrictionResponse::created(restriction))
+        }
+        Err(message) => Ok(UpdateRestrictionResponse::bad_request(&message)),
+    }
+}
+
+#[delete("/<id>")]
+pub fn delete_restriction(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    id: i32,
+) -> Result<UpdateRestrictionResponse, DatabaseErrorResponse> {
+    let restriction = match RestrictionRepository::get_one(&conn, id) {
+        Some(restriction) => restriction,
+        None => return Ok(UpdateRestrictionResponse::not_found("Not found")),
+    };
+
+    if restriction.lifted_at.is_some() {
+        return Ok(UpdateRestrictionResponse::bad_request(
+            "Restriction is already lifted",
+        ));
+    }
+
+    let moderation = Moderation::new(&conn, &moderator.get_uuid());
+    let restriction = moderation.lift_restriction(&restriction)?;
+    Ok(UpdateRestrictionResponse::updated(restriction))
+}
+
+/// Lists the audit log from the newest entry, 100 at a time. `from` and `to` accept either
+/// `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`.
+#[get("/audit?<actor>&<action>&<from>&<to>&<before_id>", format = "json")]
+pub fn get_audit_log(
+    _admin: Admin,
+    conn: ChatDbConn,
+    actor: Option<String>,
+    action: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    before_id: Option<i32>,
+) -> GetAuditLogResponse {
+    let from = match from.map(|value| parse_date(&value, false)) {
+        Some(None) => return GetAuditLogResponse::bad_request("Invalid from date"),
+        Some(value) => value,
+        None => None,
+    };
+
+    let to = match to.map(|value| parse_date(&value, true)) {
+        Some(None) => return GetAuditLogResponse::bad_request("Invalid to date"),
+        Some(value) => value,
+        None => None,
+    };
+
+    let filter = AuditLogFilter {
+        actor_uuid: actor,
+        action,
+        from,
+        to,
+        before_id,
+    };
+
+    let items = AuditLogRepository::get_filtered(&conn, &filter);
+    GetAuditLogResponse::found(items)
+}
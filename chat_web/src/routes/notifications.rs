@@ -1,16 +1,15 @@
-use crate::routes::types::{Authenticated, NotificationWithPost, PostWithFiles};
+use crate::routes::types::{NotificationWithPost, PostWithFiles, Reader};
+use crate::routes::ErrorJson;
 use crate::ChatDbConn;
+use chat::models::message_parser::MessageParserOptions;
+use chat::repositories::emoji::EmojiRepository;
 use chat::repositories::files::FileRepository;
 use chat::repositories::notifications::NotificationRepository;
 use rocket::response::status::NotFound;
+use rocket::State;
 use rocket_contrib::json::Json;
 use serde::Serialize;
 
-#[derive(Serialize)]
-pub struct ErrorJson {
-    message: String,
-}
-
 #[derive(Serialize)]
 pub struct NotificationJson {
     item: NotificationWithPost,
@@ -41,8 +40,12 @@ pub struct NotificationListResponse {
 }
 
 #[get("/", format = "json")]
-pub fn get_notifications(auth: Authenticated, conn: ChatDbConn) -> Json<NotificationListResponse> {
-    let uuid = auth.get_uuid();
+pub fn get_notifications(
+    reader: Reader,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+) -> Json<NotificationListResponse> {
+    let uuid = reader.get().get_uuid();
     let notifications = NotificationRepository::get_all_for_user(&*conn, &uuid);
 
     let (notifications, posts) = notifications.into_iter().fold(
@@ -55,13 +58,17 @@ pub fn get_notifications(auth: Authenticated, conn: ChatDbConn) -> Json<Notifica
     );
 
     let files = FileRepository::get_belonging_to_posts(&*conn, &posts);
+    let emoji = EmojiRepository::get_map(&conn);
 
     let data = notifications
         .into_iter()
         .zip(posts)
         .zip(files)
         .map(|((notification, post), files)| {
-            NotificationWithPost::new(notification, PostWithFiles::new(post, files))
+            NotificationWithPost::new(
+                notification,
+                PostWithFiles::new(post, files, &emoji, &options),
+            )
         })
         .collect();
 
@@ -70,16 +77,18 @@ pub fn get_notifications(auth: Authenticated, conn: ChatDbConn) -> Json<Notifica
 
 #[post("/<id>/read")]
 pub fn read_notification(
-    auth: Authenticated,
+    reader: Reader,
     conn: ChatDbConn,
+    options: State<MessageParserOptions>,
     id: i32,
 ) -> UpdateNotificationResponse {
-    let uuid = auth.get_uuid();
+    let uuid = reader.get().get_uuid();
     match NotificationRepository::get_one_for_user(&conn, id, &uuid) {
         Some((notification, post)) => {
             let notification = NotificationRepository::update_read(&conn, &notification, true);
             let files = FileRepository::get_belonging_to_post(&conn, &post);
-            let post = PostWithFiles::new(post, files);
+            let emoji = EmojiRepository::get_map(&conn);
+            let post = PostWithFiles::new(post, files, &emoji, &options);
             let notification = NotificationWithPost::new(notification, post);
             UpdateNotificationResponse::ok(notification)
         }
@@ -89,16 +98,18 @@ pub fn read_notification(
 
 #[delete("/<id>")]
 pub fn delete_notification(
-    auth: Authenticated,
+    reader: Reader,
     conn: ChatDbConn,
+    options: State<MessageParserOptions>,
     id: i32,
 ) -> UpdateNotificationResponse {
-    let uuid = auth.get_uuid();
+    let uuid = reader.get().get_uuid();
     match NotificationRepository::get_one_for_user(&conn, id, &uuid) {
         Some((notification, post)) => {
             let notification = NotificationRepository::delete(&conn, &notification);
             let files = FileRepository::get_belonging_to_post(&conn, &post);
-            let post = PostWithFiles::new(post, files);
+            let emoji = EmojiRepository::get_map(&conn);
+            let post = PostWithFiles::new(post, files, &emoji, &options);
             let notification = NotificationWithPost::new(notification, post);
             UpdateNotificationResponse::ok(notification)
         }
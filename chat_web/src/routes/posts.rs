@@ -1,19 +1,74 @@
+use crate::events::{Audience, ChatEvent, EventBus, SharedEventBus};
+use crate::rate_limit::{PostRateLimit, RateLimiter, TooManyRequests};
 use crate::requests::{CreatePostMultipart, UploadedFile};
-use crate::routes::types::{Authenticated, NotificationWithPost, PostWithFiles};
-use crate::ws::Ws;
+use crate::routes::types::{NotificationWithPost, PostWithFiles, Poster, Roles, Viewer};
 use crate::ChatDbConn;
+use chat::models::blocked_files::BlockedFile;
 use chat::models::files::File;
+use chat::models::message_parser::MessageParserOptions;
 use chat::models::notifications::Notification;
-use chat::models::posts::Post;
+use chat::models::posts::{FieldError, Post, PostLimits};
+use chat::models::restrictions::{KIND_BAN, KIND_QUARANTINE};
+use chat::models::rooms::Room;
+use chat::models::spam::{SpamFilter, SpamOptions};
+use chat::models::word_filters::{PostFlag, WordFilter};
+use chat::repositories::blocked_files::BlockedFileRepository;
+use chat::repositories::emoji::EmojiRepository;
 use chat::repositories::files::FileRepository;
 use chat::repositories::notifications::NotificationRepository;
 use chat::repositories::posts::PostRepository;
+use chat::repositories::restrictions::RestrictionRepository;
+use chat::repositories::rooms::RoomRepository;
+use chat::repositories::settings::SettingRepository;
+use chat::repositories::word_filters::WordFilterRepository;
+use chrono::prelude::*;
+use chrono::Duration;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use rocket::http::Status;
 use rocket::request::Form;
-use rocket::response::status::Created;
+use rocket::response::status::{Created, Custom};
 use rocket::response::Redirect;
 use rocket::State;
-use rocket_contrib::json::Json;
+use rocket_contrib::json::{Json, JsonValue};
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// State needed to create posts outside of requests, shared with the routes.
+#[derive(Clone)]
+pub struct PostContext {
+    pub pool: Pool<ConnectionManager<PgConnection>>,
+    pub options: MessageParserOptions,
+    pub limits: PostLimits,
+    pub spam_options: SpamOptions,
+    pub roles: Roles,
+    pub rate_limiter: RateLimiter,
+    pub events: SharedEventBus,
+}
+
+/// A post as submitted, before any of the checks.
+pub struct PostData {
+    pub name: String,
+    pub tripcode: String,
+    pub message: String,
+    pub files: Vec<UploadedFile>,
+}
+
+impl PostData {
+    pub fn new(
+        name: String,
+        tripcode: Option<String>,
+        message: String,
+        files: Vec<UploadedFile>,
+    ) -> PostData {
+        PostData {
+            name,
+            tripcode: tripcode.unwrap_or_default(),
+            message,
+            files,
+        }
+    }
+}
 
 #[derive(Deserialize)]
 pub struct CreatePostJson {
@@ -39,25 +94,239 @@ pub struct PostResponse {
     item: PostWithFiles,
 }
 
-fn create_post(
-    conn: ChatDbConn,
-    name: &str,
-    tripcode: &str,
+#[derive(Serialize)]
+pub struct ValidationErrorJson {
+    message: String,
+    errors: Vec<FieldError>,
+}
+
+#[derive(Responder)]
+pub enum CreatePostResponse {
+    Created(Created<Json<PostResponse>>),
+    Redirect(Redirect),
+    Invalid(Custom<Json<ValidationErrorJson>>),
+    TooManyRequests(TooManyRequests),
+}
+
+impl CreatePostResponse {
+    fn created(post: PostWithFiles) -> CreatePostResponse {
+        let location = format!("/api/v1/posts/{}", post.id);
+        let json = Json(PostResponse { item: post });
+        CreatePostResponse::Created(Created(location, Some(json)))
+    }
+
+    fn redirect() -> CreatePostResponse {
+        CreatePostResponse::Redirect(Redirect::found("/"))
+    }
+
+    fn invalid(errors: Vec<FieldError>) -> CreatePostResponse {
+        let message = String::from("Validation failed");
+        let json = Json(ValidationErrorJson { message, errors });
+        CreatePostResponse::Invalid(Custom(Status::UnprocessableEntity, json))
+    }
+}
+
+fn check_spam(
+    conn: &PgConnection,
+    options: &SpamOptions,
+    user_uuid: &str,
     message: &str,
-    files: Vec<UploadedFile>,
+    files: &[UploadedFile],
+) -> Result<(), Vec<FieldError>> {
+    let since = Utc::now().naive_utc() - Duration::seconds(options.window_seconds);
+    let mut errors = Vec::new();
+
+    let recent_messages = PostRepository::get_recent_messages_for_user(conn, user_uuid, since);
+    if let Err(error) = SpamFilter::check_message(message, &recent_messages, options) {
+        errors.push(error);
+    }
+
+    let recent_file_counts: Vec<usize> = files
+        .iter()
+        .map(|file| FileRepository::count_recent_for_user_by_md5(conn, user_uuid, &file.md5, since))
+        .collect();
+    if let Err(error) = SpamFilter::check_files(&recent_file_counts, options) {
+        errors.push(error);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Rejects files matching the blocklist by md5 or, for images, by perceptual hash. Images are
+/// only decoded if any blocked file has a perceptual hash.
+fn check_blocked_files(conn: &PgConnection, files: &[UploadedFile]) -> Result<(), FieldError> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let blocked_error = || FieldError::new("files", "blocked", String::from("File is not allowed"));
+    if files
+        .iter()
+        .any(|file| BlockedFileRepository::get_one_by_md5(conn, &file.md5).is_some())
+    {
+        return Err(blocked_error());
+    }
+
+    let blocked_phashes = BlockedFileRepository::get_phashes(conn);
+    if blocked_phashes.is_empty() {
+        return Ok(());
+    }
+
+    for file in files {
+        if let Some(phash) = File::get_perceptual_hash_from_path(&file.path) {
+            if BlockedFile::is_phash_blocked(&blocked_phashes, phash) {
+                return Err(blocked_error());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects files whose type can't be detected, as they couldn't be stored, and files whose type
+/// isn't allowed in the room, if any.
+fn check_file_types(room: Option<&Room>, files: &[UploadedFile]) -> Result<(), FieldError> {
+    for file in files {
+        let (_, mimetype) = File::get_file_type(&file.path)
+            .map_err(|message| FieldError::new("files", "invalid", message))?;
+        if matches!(room, Some(room) if !room.allows_file_type(&mimetype)) {
+            return Err(FieldError::new(
+                "files",
+                "file_type_not_allowed",
+                format!("Files of type {} are not allowed in this room", mimetype),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// A message that passed the checks on its author, ready to be stored.
+pub struct CheckedMessage {
+    /// The message with the replacements of the word filters.
+    pub message: String,
+    /// The word filters that flagged the message for review.
+    pub flagged_by: Vec<i32>,
+    /// Whether the author is quarantined, so that the message is hidden from the other users.
+    pub quarantined: bool,
+}
+
+/// Runs the checks shared by posts and direct messages on the author and what they wrote: bans,
+/// read-only and slow mode, word filters, file types, blocked files, spam and quarantine.
+/// Anonymous posts skip the checks tied to a user. Slow mode and the file types allowed in a
+/// room only apply to posts in that room.
+pub fn check_message(
+    conn: &PgConnection,
+    context: &PostContext,
+    user_uuid: Option<&str>,
+    room: Option<&Room>,
+    message: &str,
+    files: &[UploadedFile],
+) -> Result<CheckedMessage, Vec<FieldError>> {
+    if let Some(user_uuid) = user_uuid {
+        if RestrictionRepository::get_active_for_user(conn, user_uuid, KIND_BAN).is_some() {
+            return Err(vec![FieldError::new(
+                "user",
+                "banned",
+                String::from("You are banned from posting"),
+            )]);
+        }
+    }
+
+    let is_moderator = matches!(user_uuid, Some(uuid) if context.roles.is_moderator(uuid));
+    let settings = SettingRepository::get(conn).unwrap();
+    let last_post_at = match room {
+        Some(_) => {
+            user_uuid.and_then(|uuid| PostRepository::get_last_created_at_for_user(conn, uuid))
+        }
+        None => None,
+    };
+    let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+    settings
+        .check_post(is_moderator, last_post_at, now)
+        .map_err(|error| vec![error])?;
+
+    let word_filters = WordFilterRepository::get_all(conn);
+    let emoji = EmojiRepository::get_map(conn);
+    let filtered = WordFilter::apply_all(message, &word_filters, &emoji, &context.options);
+    if filtered.rejected {
+        return Err(vec![FieldError::new(
+            "message",
+            "filtered",
+            String::from("Message contains forbidden words"),
+        )]);
+    }
+
+    check_file_types(room, files).map_err(|error| vec![error])?;
+    check_blocked_files(conn, files).map_err(|error| vec![error])?;
+    let message = filtered.message;
+    let mut quarantined = false;
+    if let Some(user_uuid) = user_uuid {
+        check_spam(conn, &context.spam_options, user_uuid, &message, files)?;
+        quarantined =
+            RestrictionRepository::get_active_for_user(conn, user_uuid, KIND_QUARANTINE).is_some();
+    }
+
+    Ok(CheckedMessage {
+        message,
+        flagged_by: filtered.flagged_by,
+        quarantined,
+    })
+}
+
+fn create_post(
+    conn: &PgConnection,
+    context: &PostContext,
+    room: &Room,
+    data: PostData,
     user_uuid: Option<&str>,
-) -> (PostWithFiles, Vec<NotificationWithPost>) {
-    let new_post = Post::new(name, tripcode, message, user_uuid).unwrap();
-    let post = PostRepository::create(&*conn, &new_post);
+    is_bot: bool,
+) -> Result<(PostWithFiles, Vec<NotificationWithPost>), Vec<FieldError>> {
+    let checked = check_message(
+        conn,
+        context,
+        user_uuid,
+        Some(room),
+        &data.message,
+        &data.files,
+    )?;
+    let files = data.files;
+    let mut new_post = Post::new(
+        &data.name,
+        &data.tripcode,
+        &checked.message,
+        user_uuid,
+        room.id,
+        files.len(),
+        &context.limits,
+    )?;
+    new_post.quarantined = checked.quarantined;
+    new_post.bot = is_bot;
+    let post = PostRepository::create(conn, &new_post);
+    for word_filter_id in checked.flagged_by {
+        let new_flag = PostFlag::new(post.id, word_filter_id);
+        WordFilterRepository::create_flag(conn, &new_flag);
+    }
+
     let files = files
         .into_iter()
         .map(|file| {
-            let new_file = File::new(file.file_name, file.path, post.id).unwrap();
-            FileRepository::create(&*conn, &new_file)
+            let new_file = File::new(file.file_name, file.path, file.md5, post.id).unwrap();
+            FileRepository::create(conn, &new_file)
         })
         .collect();
 
-    let post = PostWithFiles::new(post, files);
+    let emoji = EmojiRepository::get_map(conn);
+    let post = PostWithFiles::new(post, files, &emoji, &context.options);
+
+    // Quarantined posts must not reveal themselves through notifications.
+    if post.quarantined {
+        return Ok((post, Vec::new()));
+    }
 
     // Create notifications for referenced posts.
     let mut ref_links: Vec<i32> = post
@@ -78,7 +347,7 @@ fn create_post(
     ref_links.sort_unstable();
     ref_links.dedup();
 
-    let mut uuids: Vec<String> = PostRepository::get_many_by_id(&conn, ref_links)
+    let mut uuids: Vec<String> = PostRepository::get_many_by_id(conn, ref_links)
         .into_iter()
         .filter_map(|ref_post| ref_post.user_uuid)
         .collect();
@@ -90,129 +359,352 @@ fn create_post(
         .into_iter()
         .map(|uuid| {
             let new_notification = Notification::new(post.id, &uuid, false);
-            let notification = NotificationRepository::create(&conn, &new_notification);
+            let notification = NotificationRepository::create(conn, &new_notification);
             NotificationWithPost::new(notification, post.clone())
         })
         .collect();
 
-    (post, notifications)
+    Ok((post, notifications))
 }
 
-fn send_post_created_event(ws: &Ws, data: &PostWithFiles) {
-    let json = json!({
-        "event": "post_created",
-        "data": { "item": data },
-    })
-    .to_string();
-    ws.send_to_all(&json);
+fn send_post_created_event(
+    events: &dyn EventBus,
+    roles: &Roles,
+    room: &Room,
+    data: &PostWithFiles,
+) {
+    let audience = if data.quarantined {
+        let mut uuids = roles.get_moderator_uuids();
+        uuids.extend(data.user_uuid.clone());
+        Audience::UsersInRoom(room.slug.clone(), uuids)
+    } else {
+        Audience::Room(room.slug.clone())
+    };
+
+    events.publish(audience, &ChatEvent::PostCreated { item: data });
 }
 
-fn send_notification_created_event(ws: &Ws, data: &NotificationWithPost) {
-    let json = json!({
-        "event": "notification_created",
-        "data": { "item": data },
-    })
-    .to_string();
-    ws.send_to_all(&json);
+/// Delivers the notification only to the authenticated connections of its owner.
+fn send_notification_created_event(events: &dyn EventBus, data: &NotificationWithPost) {
+    let audience = Audience::Users(vec![data.user_uuid.clone()]);
+    events.publish(audience, &ChatEvent::NotificationCreated { item: data });
+}
+
+fn send_created_events(
+    events: &dyn EventBus,
+    roles: &Roles,
+    room: &Room,
+    post: &PostWithFiles,
+    notifications: &[NotificationWithPost],
+) {
+    send_post_created_event(events, roles, room, post);
+    for notification in notifications {
+        send_notification_created_event(events, notification);
+    }
+}
+
+/// Creates a text post for a WebSocket client, with the same checks and events as the routes.
+/// Returns the post id, or an error in the same shape as the HTTP error responses.
+pub fn create_ws_post(
+    context: &PostContext,
+    user_uuid: &str,
+    ip: Option<IpAddr>,
+    room_slug: &str,
+    data: PostData,
+) -> Result<i32, JsonValue> {
+    let conn = match context.pool.get() {
+        Ok(conn) => conn,
+        Err(_) => return Err(json!({ "message": "Database is unavailable" })),
+    };
+
+    PostRateLimit::new(Some(user_uuid), ip)
+        .take(&conn, &context.rate_limiter, 0)
+        .map_err(|error| json!(error.get_json()))?;
+
+    let room = match RoomRepository::get_one_by_slug(&conn, room_slug) {
+        Some(room) => room,
+        None => return Err(json!({ "message": "Room not found" })),
+    };
+
+    let result = create_post(&conn, context, &room, data, Some(user_uuid), false);
+    match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            Ok(post.id)
+        }
+        Err(errors) => {
+            let message = String::from("Validation failed");
+            Err(json!(ValidationErrorJson { message, errors }))
+        }
+    }
+}
+
+fn get_posts(
+    conn: &PgConnection,
+    viewer: &Viewer,
+    options: &MessageParserOptions,
+    room: &Room,
+    before_id: Option<i32>,
+) -> Vec<PostWithFiles> {
+    let posts = match before_id {
+        Some(before_id) => PostRepository::get_before(conn, viewer.get(), room.id, before_id),
+        None => PostRepository::get_latest(conn, viewer.get(), room.id),
+    };
+
+    let files = FileRepository::get_belonging_to_posts(conn, &posts);
+    let emoji = EmojiRepository::get_map(conn);
+    posts
+        .into_iter()
+        .zip(files)
+        .map(|(post, files)| PostWithFiles::new(post, files, &emoji, options))
+        .collect()
 }
 
 #[post("/", format = "json", data = "<data>")]
 pub fn create_post_json(
-    auth: Authenticated,
+    poster: Poster,
+    rate_limit: PostRateLimit,
     data: Json<CreatePostJson>,
     conn: ChatDbConn,
-    ws: State<Ws>,
-) -> Created<Json<PostResponse>> {
-    let user_uuid = auth.get_uuid();
-    let (post, notifications) = create_post(
-        conn,
-        &data.name,
-        &data.tripcode.clone().unwrap_or("".to_string()),
-        &data.message,
-        Vec::new(),
+    context: State<PostContext>,
+) -> CreatePostResponse {
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, 0) {
+        return CreatePostResponse::TooManyRequests(error);
+    }
+
+    let user_uuid = poster.get().get_uuid();
+    let room = RoomRepository::get_default(&conn);
+    let data = data.into_inner();
+    let data = PostData::new(data.name, data.tripcode, data.message, Vec::new());
+    let result = create_post(
+        &conn,
+        &context,
+        &room,
+        data,
         Some(&user_uuid),
+        poster.get().is_bot(),
     );
-    send_post_created_event(&ws, &post);
-    for notification in notifications {
-        send_notification_created_event(&ws, &notification);
+    match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            CreatePostResponse::created(post)
+        }
+        Err(errors) => CreatePostResponse::invalid(errors),
     }
-
-    let location = format!("/api/v1/posts/{}", post.id);
-    Created(location, Some(Json(PostResponse { item: post })))
 }
 
 #[post("/", data = "<data>", rank = 1)]
 pub fn create_post_form(
-    auth: Authenticated,
+    poster: Poster,
+    rate_limit: PostRateLimit,
     data: Form<CreatePostForm>,
     conn: ChatDbConn,
-    ws: State<Ws>,
-) -> Redirect {
-    let user_uuid = auth.get_uuid();
-    let (post, notifications) = create_post(
-        conn,
-        &data.name,
-        &data.tripcode.clone().unwrap_or("".to_string()),
-        &data.message,
-        Vec::new(),
+    context: State<PostContext>,
+) -> CreatePostResponse {
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, 0) {
+        return CreatePostResponse::TooManyRequests(error);
+    }
+
+    let user_uuid = poster.get().get_uuid();
+    let room = RoomRepository::get_default(&conn);
+    let data = data.into_inner();
+    let data = PostData::new(data.name, data.tripcode, data.message, Vec::new());
+    let result = create_post(
+        &conn,
+        &context,
+        &room,
+        data,
         Some(&user_uuid),
+        poster.get().is_bot(),
     );
-    send_post_created_event(&ws, &post);
-    for notification in notifications {
-        send_notification_created_event(&ws, &notification);
+    match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            CreatePostResponse::redirect()
+        }
+        Err(errors) => CreatePostResponse::invalid(errors),
     }
-
-    Redirect::found("/")
 }
 
 #[post("/", data = "<data>", rank = 2)]
 pub fn create_post_multipart(
-    auth: Authenticated,
+    poster: Poster,
+    rate_limit: PostRateLimit,
     data: CreatePostMultipart,
     conn: ChatDbConn,
-    ws: State<Ws>,
-) -> Redirect {
-    let user_uuid = auth.get_uuid();
-    let (post, notifications) = create_post(
-        conn,
-        &data.name,
-        &data.tripcode.unwrap_or("".to_string()),
-        &data.message,
-        data.files,
+    context: State<PostContext>,
+) -> CreatePostResponse {
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, data.files.len()) {
+        return CreatePostResponse::TooManyRequests(error);
+    }
+
+    let user_uuid = poster.get().get_uuid();
+    let room = RoomRepository::get_default(&conn);
+    let data = PostData::new(data.name, data.tripcode, data.message, data.files);
+    let result = create_post(
+        &conn,
+        &context,
+        &room,
+        data,
         Some(&user_uuid),
+        poster.get().is_bot(),
     );
-    send_post_created_event(&ws, &post);
-    for notification in notifications {
-        send_notification_created_event(&ws, &notification);
+    match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            CreatePostResponse::redirect()
+        }
+        Err(errors) => CreatePostResponse::invalid(errors),
     }
-
-    Redirect::found("/")
 }
 
 #[get("/?<before_id>", format = "json")]
-pub fn get_post_list(conn: ChatDbConn, before_id: Option<i32>) -> Json<PostListResponse> {
-    let posts = match before_id {
-        Some(before_id) => PostRepository::get_before(&*conn, before_id),
-        None => PostRepository::get_latest(&*conn),
-    };
-
-    let files = FileRepository::get_belonging_to_posts(&*conn, &posts);
-    let data = posts
-        .into_iter()
-        .zip(files)
-        .map(|(post, files)| PostWithFiles::new(post, files))
-        .collect();
+pub fn get_post_list(
+    viewer: Viewer,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    before_id: Option<i32>,
+) -> Json<PostListResponse> {
+    let room = RoomRepository::get_default(&conn);
+    let items = get_posts(&conn, &viewer, &options, &room, before_id);
 
-    Json(PostListResponse { items: data })
+    Json(PostListResponse { items })
 }
 
 #[get("/<post_id>", format = "json")]
-pub fn get_post(conn: ChatDbConn, post_id: i32) -> Option<Json<PostResponse>> {
-    let post = PostRepository::get_one(&*conn, post_id);
+pub fn get_post(
+    viewer: Viewer,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    post_id: i32,
+) -> Option<Json<PostResponse>> {
+    let post = PostRepository::get_one(&*conn, viewer.get(), post_id);
     post.map(|post| {
         let files = Vec::new();
-        let data = PostWithFiles::new(post, files);
+        let emoji = EmojiRepository::get_map(&*conn);
+        let data = PostWithFiles::new(post, files, &emoji, &options);
 
         Json(PostResponse { item: data })
     })
 }
+
+#[post("/<slug>/posts", format = "json", data = "<data>")]
+pub fn create_room_post_json(
+    poster: Poster,
+    rate_limit: PostRateLimit,
+    slug: String,
+    data: Json<CreatePostJson>,
+    conn: ChatDbConn,
+    context: State<PostContext>,
+) -> Option<CreatePostResponse> {
+    let room = RoomRepository::get_one_by_slug(&conn, &slug)?;
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, 0) {
+        return Some(CreatePostResponse::TooManyRequests(error));
+    }
+
+    let user_uuid = poster.get().get_uuid();
+    let data = data.into_inner();
+    let data = PostData::new(data.name, data.tripcode, data.message, Vec::new());
+    let result = create_post(
+        &conn,
+        &context,
+        &room,
+        data,
+        Some(&user_uuid),
+        poster.get().is_bot(),
+    );
+    let response = match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            CreatePostResponse::created(post)
+        }
+        Err(errors) => CreatePostResponse::invalid(errors),
+    };
+
+    Some(response)
+}
+
+#[post("/<slug>/posts", data = "<data>", rank = 2)]
+pub fn create_room_post_multipart(
+    poster: Poster,
+    rate_limit: PostRateLimit,
+    slug: String,
+    data: CreatePostMultipart,
+    conn: ChatDbConn,
+    context: State<PostContext>,
+) -> Option<CreatePostResponse> {
+    let room = RoomRepository::get_one_by_slug(&conn, &slug)?;
+    if let Err(error) = rate_limit.take(&conn, &context.rate_limiter, data.files.len()) {
+        return Some(CreatePostResponse::TooManyRequests(error));
+    }
+
+    let user_uuid = poster.get().get_uuid();
+    let data = PostData::new(data.name, data.tripcode, data.message, data.files);
+    let result = create_post(
+        &conn,
+        &context,
+        &room,
+        data,
+        Some(&user_uuid),
+        poster.get().is_bot(),
+    );
+    let response = match result {
+        Ok((post, notifications)) => {
+            send_created_events(
+                &*context.events,
+                &context.roles,
+                &room,
+                &post,
+                &notifications,
+            );
+            CreatePostResponse::created(post)
+        }
+        Err(errors) => CreatePostResponse::invalid(errors),
+    };
+
+    Some(response)
+}
+
+#[get("/<slug>/posts?<before_id>", format = "json")]
+pub fn get_room_post_list(
+    viewer: Viewer,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    slug: String,
+    before_id: Option<i32>,
+) -> Option<Json<PostListResponse>> {
+    let room = RoomRepository::get_one_by_slug(&conn, &slug)?;
+    let items = get_posts(&conn, &viewer, &options, &room, before_id);
+
+    Some(Json(PostListResponse { items }))
+}
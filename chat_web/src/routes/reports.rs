@@ -0,0 +1,178 @@
+use crate::events::{Audience, ChatEvent, EventBus, SharedEventBus};
+use crate::moderation::Moderation;
+use crate::routes::moderation::send_post_hidden_event;
+use crate::routes::types::{Authenticated, Moderator, PostWithFiles, ReportWithPost, Roles};
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::message_parser::MessageParserOptions;
+use chat::models::posts::{Post, PostViewer};
+use chat::models::reports::Report;
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use chat::repositories::posts::PostRepository;
+use chat::repositories::reports::ReportRepository;
+use diesel::pg::PgConnection;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateReportRequest {
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateReportRequest {
+    status: String,
+    #[serde(default)]
+    hide_post: bool,
+}
+
+#[derive(Serialize)]
+pub struct ReportResponse {
+    item: ReportWithPost,
+}
+
+#[derive(Serialize)]
+pub struct ReportListResponse {
+    items: Vec<ReportWithPost>,
+}
+
+#[derive(Responder)]
+pub enum UpdateReportResponse {
+    Created(Created<Json<ReportResponse>>),
+    Updated(Json<ReportResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateReportResponse {
+    fn created(report: ReportWithPost) -> UpdateReportResponse {
+        let location = format!("/api/v1/reports/{}", report.id);
+        let json = Json(ReportResponse { item: report });
+        UpdateReportResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(report: ReportWithPost) -> UpdateReportResponse {
+        let json = Json(ReportResponse { item: report });
+        UpdateReportResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateReportResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateReportResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateReportResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateReportResponse::NotFound(NotFound(json))
+    }
+}
+
+fn report_with_post(
+    conn: &PgConnection,
+    options: &MessageParserOptions,
+    report: Report,
+    post: Post,
+) -> ReportWithPost {
+    let files = FileRepository::get_belonging_to_post(conn, &post);
+    let emoji = EmojiRepository::get_map(conn);
+    let post = PostWithFiles::new(post, files, &emoji, options);
+    ReportWithPost::new(report, post)
+}
+
+fn send_report_created_event(events: &dyn EventBus, roles: &Roles, data: &ReportWithPost) {
+    let audience = Audience::Users(roles.get_moderator_uuids());
+    events.publish(audience, &ChatEvent::ReportCreated { item: data });
+}
+
+#[post("/<post_id>/report", format = "json", data = "<data>")]
+pub fn create_report(
+    auth: Authenticated,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    roles: State<Roles>,
+    events: State<SharedEventBus>,
+    post_id: i32,
+    data: Json<CreateReportRequest>,
+) -> UpdateReportResponse {
+    let user_uuid = auth.get_uuid();
+    let viewer = PostViewer {
+        user_uuid: Some(user_uuid.clone()),
+        is_moderator: roles.is_moderator(&user_uuid),
+    };
+    let post = match PostRepository::get_one(&conn, &viewer, post_id) {
+        Some(post) => post,
+        None => return UpdateReportResponse::not_found("Not found"),
+    };
+
+    if ReportRepository::get_open_by_user(&conn, post.id, &user_uuid).is_some() {
+        return UpdateReportResponse::bad_request("You have already reported this post");
+    }
+
+    match Report::new(post.id, &data.reason, &user_uuid) {
+        Ok(new_report) => {
+            let report = ReportRepository::create(&conn, &new_report);
+            let report = report_with_post(&conn, &options, report, post);
+            send_report_created_event(&**events, &roles, &report);
+            UpdateReportResponse::created(report)
+        }
+        Err(message) => UpdateReportResponse::bad_request(&message),
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_report_list(
+    _moderator: Moderator,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+) -> Json<ReportListResponse> {
+    let items = ReportRepository::get_open(&conn)
+        .into_iter()
+        .map(|(report, post)| report_with_post(&conn, &options, report, post))
+        .collect();
+
+    Json(ReportListResponse { items })
+}
+
+/// Resolves or dismisses the report along with the other open reports of the same post,
+/// optionally hiding the post.
+#[put("/<id>", format = "json", data = "<data>")]
+pub fn update_report(
+    moderator: Moderator,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    events: State<SharedEventBus>,
+    id: i32,
+    data: Json<UpdateReportRequest>,
+) -> Result<UpdateReportResponse, DatabaseErrorResponse> {
+    if !Report::is_valid_resolution(&data.status) {
+        return Ok(UpdateReportResponse::bad_request(
+            "Status must be either resolved or dismissed",
+        ));
+    }
+
+    let (report, mut post) = match ReportRepository::get_one(&conn, id) {
+        Some(item) => item,
+        None => return Ok(UpdateReportResponse::not_found("Not found")),
+    };
+
+    let moderation = Moderation::new(&conn, &moderator.get_uuid());
+    let reports = moderation.resolve_reports(post.id, &data.status)?;
+    let report = reports
+        .into_iter()
+        .find(|item| item.id == report.id)
+        .unwrap_or(report);
+
+    if data.hide_post && !post.hidden {
+        post = moderation.hide_post(&post)?;
+        send_post_hidden_event(&**events, post.id);
+    }
+
+    Ok(UpdateReportResponse::updated(report_with_post(
+        &conn, &options, report, post,
+    )))
+}
@@ -0,0 +1,138 @@
+use crate::moderation::Moderation;
+use crate::routes::types::Admin;
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::rooms::Room;
+use chat::repositories::rooms::RoomRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateRoomRequest {
+    slug: String,
+    title: String,
+    description: Option<String>,
+    nsfw: Option<bool>,
+    allowed_file_types: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRoomRequest {
+    title: Option<String>,
+    description: Option<String>,
+    nsfw: Option<bool>,
+    allowed_file_types: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct RoomResponse {
+    item: Room,
+}
+
+#[derive(Serialize)]
+pub struct RoomListResponse {
+    items: Vec<Room>,
+}
+
+#[derive(Responder)]
+pub enum UpdateRoomResponse {
+    Created(Created<Json<RoomResponse>>),
+    Updated(Json<RoomResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateRoomResponse {
+    fn created(room: Room) -> UpdateRoomResponse {
+        let location = format!("/api/v1/rooms/{}", room.slug);
+        let json = Json(RoomResponse { item: room });
+        UpdateRoomResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(room: Room) -> UpdateRoomResponse {
+        let json = Json(RoomResponse { item: room });
+        UpdateRoomResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateRoomResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateRoomResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateRoomResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateRoomResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_room_list(conn: ChatDbConn) -> Json<RoomListResponse> {
+    Json(RoomListResponse {
+        items: RoomRepository::get_all(&conn),
+    })
+}
+
+#[get("/<slug>", format = "json")]
+pub fn get_room(conn: ChatDbConn, slug: String) -> Option<Json<RoomResponse>> {
+    RoomRepository::get_one_by_slug(&conn, &slug).map(|room| Json(RoomResponse { item: room }))
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_room(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<CreateRoomRequest>,
+) -> Result<UpdateRoomResponse, DatabaseErrorResponse> {
+    if RoomRepository::get_one_by_slug(&conn, &data.slug).is_some() {
+        return Ok(UpdateRoomResponse::bad_request("Room already exists"));
+    }
+
+    let new_room = Room::new(
+        &data.slug,
+        &data.title,
+        data.description.as_deref().unwrap_or(""),
+        data.nsfw.unwrap_or(false),
+        data.allowed_file_types.clone().unwrap_or_default(),
+    );
+    match new_room {
+        Ok(new_room) => {
+            let room = Moderation::new(&conn, &admin.get_uuid()).create_room(&new_room)?;
+            Ok(UpdateRoomResponse::created(room))
+        }
+        Err(message) => Ok(UpdateRoomResponse::bad_request(&message)),
+    }
+}
+
+#[put("/<slug>", format = "json", data = "<data>")]
+pub fn update_room(
+    admin: Admin,
+    conn: ChatDbConn,
+    slug: String,
+    data: Json<UpdateRoomRequest>,
+) -> Result<UpdateRoomResponse, DatabaseErrorResponse> {
+    let room = match RoomRepository::get_one_by_slug(&conn, &slug) {
+        Some(room) => room,
+        None => return Ok(UpdateRoomResponse::not_found("Room not found")),
+    };
+
+    let new_room = Room::new(
+        &room.slug,
+        data.title.as_ref().unwrap_or(&room.title),
+        data.description.as_ref().unwrap_or(&room.description),
+        data.nsfw.unwrap_or(room.nsfw),
+        data.allowed_file_types
+            .clone()
+            .unwrap_or_else(|| room.allowed_file_types.clone()),
+    );
+    match new_room {
+        Ok(new_room) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let room = moderation.update_room(&room, &new_room)?;
+            Ok(UpdateRoomResponse::updated(room))
+        }
+        Err(message) => Ok(UpdateRoomResponse::bad_request(&message)),
+    }
+}
@@ -0,0 +1,86 @@
+use crate::events::{Audience, ChatEvent, EventBus, SharedEventBus};
+use crate::moderation::Moderation;
+use crate::routes::types::{Admin, Viewer};
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::settings::ChatSettings;
+use chat::repositories::settings::SettingRepository;
+use rocket::response::status::BadRequest;
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct UpdateSettingsRequest {
+    slow_mode_seconds: Option<i64>,
+    read_only: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct SettingsResponse {
+    item: ChatSettings,
+    is_moderator: bool,
+}
+
+#[derive(Responder)]
+pub enum UpdateSettingsResponse {
+    Updated(Json<SettingsResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+}
+
+impl UpdateSettingsResponse {
+    fn updated(settings: ChatSettings) -> UpdateSettingsResponse {
+        let json = Json(SettingsResponse {
+            item: settings,
+            is_moderator: true,
+        });
+        UpdateSettingsResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateSettingsResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateSettingsResponse::BadRequest(BadRequest(Some(json)))
+    }
+}
+
+fn send_settings_changed_event(events: &dyn EventBus, data: &ChatSettings) {
+    events.publish(Audience::All, &ChatEvent::SettingsChanged { item: data });
+}
+
+/// Returns the current settings, along with whether the viewer is exempt from them.
+#[get("/", format = "json")]
+pub fn get_settings(
+    viewer: Viewer,
+    conn: ChatDbConn,
+) -> Result<Json<SettingsResponse>, DatabaseErrorResponse> {
+    Ok(Json(SettingsResponse {
+        item: SettingRepository::get(&conn)?,
+        is_moderator: viewer.get().is_moderator,
+    }))
+}
+
+#[put("/", format = "json", data = "<data>")]
+pub fn update_settings(
+    admin: Admin,
+    conn: ChatDbConn,
+    events: State<SharedEventBus>,
+    data: Json<UpdateSettingsRequest>,
+) -> Result<UpdateSettingsResponse, DatabaseErrorResponse> {
+    let mut settings = SettingRepository::get(&conn)?;
+    if let Some(slow_mode_seconds) = data.slow_mode_seconds {
+        settings.slow_mode_seconds = slow_mode_seconds;
+    }
+
+    if let Some(read_only) = data.read_only {
+        settings.read_only = read_only;
+    }
+
+    if let Err(message) = settings.validate() {
+        return Ok(UpdateSettingsResponse::bad_request(&message));
+    }
+
+    let settings = Moderation::new(&conn, &admin.get_uuid()).update_settings(&settings)?;
+    send_settings_changed_event(&**events, &settings);
+    Ok(UpdateSettingsResponse::updated(settings))
+}
@@ -4,6 +4,7 @@ use image::imageops::FilterType;
 use image::io::Reader;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use rocket::http::Status;
 use rocket::response::{self, NamedFile, Responder};
 use rocket::{Request, Response};
 use std::env;
@@ -113,41 +114,52 @@ fn create_video_thumbnail(src: &Path, dst: &Path, max_size: u32) -> Result<(), B
     Ok(())
 }
 
+/// Responds with 410 Gone for files that have been taken down.
 #[get("/<hash>?<max_width>")]
-pub fn get_thumbnail(conn: ChatDbConn, hash: String, max_width: Option<i32>) -> Option<CachedFile> {
+pub fn get_thumbnail(
+    conn: ChatDbConn,
+    hash: String,
+    max_width: Option<i32>,
+) -> Result<Option<CachedFile>, Status> {
     let max_width = max_width.unwrap_or(360);
     // TODO: Check list of allowed thumbnail sizes.
     if max_width != 360 {
-        return None;
+        return Ok(None);
     }
 
-    let file = FileRepository::get_one_by_md5(&*conn, &hash);
-    file.map(|file| {
-        let thumb_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/thumb");
-        let thumb_extension = get_thumb_extension(&file.extension);
-        let thumb_filename = format!("{}_{}.{}", hash, max_width, thumb_extension);
-        let thumb_path = Path::new(thumb_dir).join(thumb_filename);
-        if !thumb_path.exists() {
-            let src_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/src");
-            let src_filename = format!("{}.{}", hash, file.extension);
-            let src_path = Path::new(src_dir).join(src_filename);
-
-            match file.mimetype.clone() {
-                mimetype if mimetype.starts_with("image/webp") => {
-                    create_image_webp_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
-                }
-                mimetype if mimetype.starts_with("image/") => {
-                    create_image_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
-                }
-                mimetype if mimetype.starts_with("video/") => {
-                    create_video_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
-                }
-                _ => {}
-            };
-        }
-
-        NamedFile::open(thumb_path)
-            .map(|file| CachedFile::new(file, 31536000))
-            .unwrap()
-    })
+    let file = match FileRepository::get_one_by_md5(&*conn, &hash) {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+
+    if file.removed {
+        return Err(Status::Gone);
+    }
+
+    let thumb_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../www/thumb");
+    let thumb_extension = get_thumb_extension(&file.extension);
+    let thumb_filename = format!("{}_{}.{}", hash, max_width, thumb_extension);
+    let thumb_path = Path::new(thumb_dir).join(thumb_filename);
+    if !thumb_path.exists() {
+        let src_path = file.get_source_path();
+
+        match file.mimetype.clone() {
+            mimetype if mimetype.starts_with("image/webp") => {
+                create_image_webp_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
+            }
+            mimetype if mimetype.starts_with("image/") => {
+                create_image_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
+            }
+            mimetype if mimetype.starts_with("video/") => {
+                create_video_thumbnail(&src_path, &thumb_path, max_width as u32).unwrap();
+            }
+            _ => {}
+        };
+    }
+
+    let file = NamedFile::open(thumb_path)
+        .map(|file| CachedFile::new(file, 31536000))
+        .unwrap();
+
+    Ok(Some(file))
 }
@@ -1,12 +1,21 @@
-use chrono::prelude::*;
+use crate::ChatDbConn;
+use chat::models::api_tokens::{ApiToken, SCOPE_POST, SCOPE_READ};
+use chat::models::colors::{ColorContrast, ColorContrastMode, Rgba};
+use chat::models::direct_messages::{Conversation, DirectMessage};
+use chat::models::emoji::CustomEmoji;
 use chat::models::files::File;
-use chat::models::message_parser::{MessageParser, Markup};
+use chat::models::message_parser::{EmojiMap, Markup, MessageParser, MessageParserOptions};
 use chat::models::notifications::Notification;
-use chat::models::posts::Post;
+use chat::models::posts::{Post, PostLimits, PostViewer};
+use chat::models::reports::Report;
+use chat::models::spam::SpamOptions;
+use chat::models::word_filters::PostFlag;
+use chat::repositories::api_tokens::ApiTokenRepository;
+use chrono::prelude::*;
 use jsonwebtoken::{Algorithm, DecodingKey, Validation};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
-use rocket::Request;
+use rocket::{Config, Request, State};
 use serde::{Deserialize, Serialize};
 use std::fs;
 
@@ -59,29 +68,413 @@ impl Authenticated {
     }
 }
 
+#[derive(Clone)]
+pub struct Roles {
+    admins: Vec<String>,
+    moderators: Vec<String>,
+}
+
+impl Roles {
+    fn get_uuid_list(config: &Config, name: &str) -> Vec<String> {
+        match config.get_slice(name) {
+            Ok(items) => items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(String::from)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Roles {
+        Roles {
+            admins: Roles::get_uuid_list(config, "admin_uuids"),
+            moderators: Roles::get_uuid_list(config, "moderator_uuids"),
+        }
+    }
+
+    pub fn is_admin(&self, uuid: &str) -> bool {
+        self.admins.iter().any(|admin| admin == uuid)
+    }
+
+    /// Admins have all moderator rights too.
+    pub fn is_moderator(&self, uuid: &str) -> bool {
+        self.is_admin(uuid) || self.moderators.iter().any(|moderator| moderator == uuid)
+    }
+
+    pub fn get_moderator_uuids(&self) -> Vec<String> {
+        let mut uuids: Vec<String> = self
+            .admins
+            .iter()
+            .chain(self.moderators.iter())
+            .cloned()
+            .collect();
+        uuids.sort_unstable();
+        uuids.dedup();
+        uuids
+    }
+}
+
+pub fn parser_options_from_config(config: &Config) -> MessageParserOptions {
+    let mut options = MessageParserOptions::default();
+    let table = match config.get_table("message_parser") {
+        Ok(table) => table,
+        Err(_) => return options,
+    };
+
+    let get_list = |name: &str| {
+        table.get(name).and_then(|value| value.as_array()).map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(String::from)
+                .collect()
+        })
+    };
+
+    if let Some(enabled_tags) = get_list("enabled_tags") {
+        options.enabled_tags = enabled_tags;
+    }
+
+    if let Some(link_schemes) = get_list("link_schemes") {
+        options.link_schemes = link_schemes;
+    }
+
+    if let Some(depth) = table.get("max_nesting_depth").and_then(|v| v.as_integer()) {
+        options.max_nesting_depth = depth as usize;
+    }
+
+    if let Some(max_dice) = table.get("max_dice").and_then(|v| v.as_integer()) {
+        options.max_dice = max_dice as u32;
+    }
+
+    if let Some(allow_colors) = table.get("allow_colors").and_then(|v| v.as_bool()) {
+        options.allow_colors = allow_colors;
+    }
+
+    if let Some(allow_embeds) = table.get("allow_embeds").and_then(|v| v.as_bool()) {
+        options.allow_embeds = allow_embeds;
+    }
+
+    let background = table
+        .get("color_contrast_background")
+        .and_then(|v| v.as_str())
+        .and_then(Rgba::parse);
+    options.color_contrast = background.map(|background| ColorContrast {
+        background,
+        min_ratio: table
+            .get("color_contrast_min_ratio")
+            .and_then(|v| v.as_float())
+            .unwrap_or(3.0),
+        mode: match table.get("color_contrast_mode").and_then(|v| v.as_str()) {
+            Some("reject") => ColorContrastMode::Reject,
+            _ => ColorContrastMode::Clamp,
+        },
+    });
+
+    options
+}
+
+/// The nesting depth is the one of the message parser, so that posts are never accepted with
+/// tags the parser would show as text.
+pub fn post_limits_from_config(config: &Config, options: &MessageParserOptions) -> PostLimits {
+    let mut limits = PostLimits {
+        max_nesting_depth: options.max_nesting_depth,
+        ..PostLimits::default()
+    };
+    let table = match config.get_table("post_limits") {
+        Ok(table) => table,
+        Err(_) => return limits,
+    };
+
+    let get_usize = |name: &str| {
+        table
+            .get(name)
+            .and_then(|value| value.as_integer())
+            .map(|value| value as usize)
+    };
+
+    if let Some(max_message_bytes) = get_usize("max_message_bytes") {
+        limits.max_message_bytes = max_message_bytes;
+    }
+
+    if let Some(max_lines) = get_usize("max_lines") {
+        limits.max_lines = max_lines;
+    }
+
+    if let Some(max_ref_links) = get_usize("max_ref_links") {
+        limits.max_ref_links = max_ref_links;
+    }
+
+    if let Some(max_links) = get_usize("max_links") {
+        limits.max_links = max_links;
+    }
+
+    limits
+}
+
+pub fn spam_options_from_config(config: &Config) -> SpamOptions {
+    let mut options = SpamOptions::default();
+    let table = match config.get_table("spam") {
+        Ok(table) => table,
+        Err(_) => return options,
+    };
+
+    let get_usize = |name: &str| {
+        table
+            .get(name)
+            .and_then(|value| value.as_integer())
+            .map(|value| value as usize)
+    };
+
+    if let Some(window_seconds) = table.get("window_seconds").and_then(|v| v.as_integer()) {
+        options.window_seconds = window_seconds;
+    }
+
+    if let Some(min_message_length) = get_usize("min_message_length") {
+        options.min_message_length = min_message_length;
+    }
+
+    if let Some(shingle_size) = get_usize("shingle_size") {
+        options.shingle_size = shingle_size;
+    }
+
+    if let Some(threshold) = table.get("similarity_threshold").and_then(|v| v.as_float()) {
+        options.similarity_threshold = threshold;
+    }
+
+    if let Some(max_repeated_files) = get_usize("max_repeated_files") {
+        options.max_repeated_files = max_repeated_files;
+    }
+
+    options
+}
+
+pub fn decode_token(token: &str) -> Option<Claims> {
+    let validation = Validation {
+        algorithms: vec![Algorithm::RS256],
+        validate_nbf: true,
+        validate_exp: true,
+        leeway: 60,
+        aud: None,
+        iss: None,
+        sub: None,
+    };
+
+    match jsonwebtoken::decode::<Claims>(token, &*DECODING_KEY, &validation) {
+        Ok(token_data) => Some(token_data.claims),
+        Err(_) => None,
+    }
+}
+
 impl<'a, 'r> FromRequest<'a, 'r> for Authenticated {
     type Error = ();
 
     fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
         let BearerToken(token) = request.guard::<BearerToken>()?;
 
-        let validation = Validation {
-            algorithms: vec![Algorithm::RS256],
-            validate_nbf: true,
-            validate_exp: true,
-            leeway: 60,
-            aud: None,
-            iss: None,
-            sub: None,
-        };
+        match decode_token(token) {
+            Some(claims) => Outcome::Success(Authenticated(claims)),
+            None => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// A bot account authenticated with an API token instead of a JWT.
+pub struct Bot(ApiToken);
+
+/// The token is looked up once per request, even if several guards need it.
+struct CachedApiToken(Option<ApiToken>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for Bot {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        let BearerToken(token) = request.guard::<BearerToken>()?;
+        if !ApiToken::is_api_token(token) {
+            return Outcome::Forward(());
+        }
+
+        let CachedApiToken(api_token) = request.local_cache(|| {
+            let conn = request.guard::<ChatDbConn>().succeeded();
+            CachedApiToken(conn.and_then(|conn| {
+                let api_token =
+                    ApiTokenRepository::get_active_by_hash(&conn, &ApiToken::hash(token))?;
+                let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+                if api_token.needs_last_used_update(now) {
+                    ApiTokenRepository::set_last_used(&conn, &api_token, now);
+                }
+                Some(api_token)
+            }))
+        });
 
-        match jsonwebtoken::decode::<Claims>(token, &*DECODING_KEY, &validation) {
-            Ok(token_data) => Outcome::Success(Authenticated(token_data.claims)),
-            Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+        match api_token {
+            Some(api_token) => Outcome::Success(Bot(api_token.clone())),
+            None => Outcome::Failure((Status::Unauthorized, ())),
         }
     }
 }
 
+/// Who makes the request: a user signed in with a JWT, or a bot with an API token.
+pub enum Actor {
+    User(Claims),
+    Bot(ApiToken),
+}
+
+impl Actor {
+    pub fn get_uuid(&self) -> String {
+        match self {
+            Actor::User(claims) => claims.user_uuid.clone(),
+            Actor::Bot(api_token) => api_token.user_uuid.clone(),
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        matches!(self, Actor::Bot(_))
+    }
+
+    /// Bots are only let in if their token has the scope.
+    fn from_request_with_scope(request: &Request, scope: &str) -> Outcome<Actor, ()> {
+        let BearerToken(token) = request.guard::<BearerToken>()?;
+        if ApiToken::is_api_token(token) {
+            let Bot(api_token) = request.guard::<Bot>()?;
+            if api_token.has_scope(scope) {
+                Outcome::Success(Actor::Bot(api_token))
+            } else {
+                Outcome::Failure((Status::Forbidden, ()))
+            }
+        } else {
+            let Authenticated(claims) = request.guard::<Authenticated>()?;
+            Outcome::Success(Actor::User(claims))
+        }
+    }
+}
+
+/// The author of the posts created by the request.
+pub struct Poster(Actor);
+
+impl Poster {
+    pub fn get(&self) -> &Actor {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Poster {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        Actor::from_request_with_scope(request, SCOPE_POST).map(Poster)
+    }
+}
+
+/// The owner of the notifications read by the request.
+pub struct Reader(Actor);
+
+impl Reader {
+    pub fn get(&self) -> &Actor {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Reader {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        Actor::from_request_with_scope(request, SCOPE_READ).map(Reader)
+    }
+}
+
+pub struct Admin(Claims);
+
+impl Admin {
+    pub fn get_uuid(&self) -> String {
+        self.0.user_uuid.clone()
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Admin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        let Authenticated(claims) = request.guard::<Authenticated>()?;
+        let roles = request.guard::<State<Roles>>()?;
+        if roles.is_admin(&claims.user_uuid) {
+            Outcome::Success(Admin(claims))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+pub struct Moderator(Claims);
+
+impl Moderator {
+    pub fn get_uuid(&self) -> String {
+        self.0.user_uuid.clone()
+    }
+
+    pub fn get_viewer(&self) -> PostViewer {
+        PostViewer {
+            user_uuid: Some(self.get_uuid()),
+            is_moderator: true,
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Moderator {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        let Authenticated(claims) = request.guard::<Authenticated>()?;
+        let roles = request.guard::<State<Roles>>()?;
+        if roles.is_moderator(&claims.user_uuid) {
+            Outcome::Success(Moderator(claims))
+        } else {
+            Outcome::Failure((Status::Forbidden, ()))
+        }
+    }
+}
+
+/// The reader of the posts in the request, anonymous if there is no valid token.
+pub struct Viewer(PostViewer);
+
+impl Viewer {
+    pub fn get(&self) -> &PostViewer {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Viewer {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, ()> {
+        let roles = request.guard::<State<Roles>>()?;
+        let viewer = match request.guard::<Reader>() {
+            Outcome::Success(reader) => PostViewer {
+                is_moderator: roles.is_moderator(&reader.get().get_uuid()),
+                user_uuid: Some(reader.get().get_uuid()),
+            },
+            _ => PostViewer::default(),
+        };
+
+        Outcome::Success(Viewer(viewer))
+    }
+}
+
+/// Replaces the files that have been taken down with placeholders.
+fn replace_removed_files(files: Vec<File>) -> Vec<File> {
+    files
+        .into_iter()
+        .map(|file| {
+            if file.removed {
+                file.to_placeholder()
+            } else {
+                file
+            }
+        })
+        .collect()
+}
+
 #[derive(Serialize, Clone)]
 pub struct PostWithFiles {
     pub id: i32,
@@ -92,19 +485,99 @@ pub struct PostWithFiles {
     pub created_at: NaiveDateTime,
     pub files: Vec<File>,
     pub user_uuid: Option<String>,
+    pub room_id: i32,
+    /// Whether the post was created by a bot account.
+    pub bot: bool,
+    #[serde(skip)]
+    pub quarantined: bool,
 }
 
 impl PostWithFiles {
-    pub fn new(post: Post, files: Vec<File>) -> PostWithFiles {
+    pub fn new(
+        post: Post,
+        files: Vec<File>,
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> PostWithFiles {
         PostWithFiles {
             id: post.id,
             name: post.name,
             tripcode: post.tripcode,
             message_raw: post.message.clone(),
-            message: MessageParser::str_to_markup(&post.message),
+            message: MessageParser::str_to_markup_with_emoji(&post.message, emoji, options),
             created_at: post.created_at,
-            files,
+            files: replace_removed_files(files),
             user_uuid: post.user_uuid,
+            room_id: post.room_id,
+            bot: post.bot,
+            quarantined: post.quarantined,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EmojiWithFile {
+    pub id: i32,
+    pub shortcode: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub file: File,
+}
+
+impl EmojiWithFile {
+    pub fn new(emoji: CustomEmoji, file: File) -> EmojiWithFile {
+        EmojiWithFile {
+            id: emoji.id,
+            shortcode: emoji.shortcode,
+            created_by: emoji.created_by,
+            created_at: emoji.created_at,
+            file,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PostFlagWithPost {
+    pub id: i32,
+    pub word_filter_id: i32,
+    pub created_at: NaiveDateTime,
+    pub post: PostWithFiles,
+}
+
+impl PostFlagWithPost {
+    pub fn new(flag: PostFlag, post: PostWithFiles) -> PostFlagWithPost {
+        PostFlagWithPost {
+            id: flag.id,
+            word_filter_id: flag.word_filter_id,
+            created_at: flag.created_at,
+            post,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReportWithPost {
+    pub id: i32,
+    pub reason: String,
+    pub reported_by: String,
+    pub created_at: NaiveDateTime,
+    pub status: String,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub post: PostWithFiles,
+}
+
+impl ReportWithPost {
+    pub fn new(report: Report, post: PostWithFiles) -> ReportWithPost {
+        ReportWithPost {
+            id: report.id,
+            reason: report.reason,
+            reported_by: report.reported_by,
+            created_at: report.created_at,
+            status: report.status,
+            resolved_by: report.resolved_by,
+            resolved_at: report.resolved_at,
+            post,
         }
     }
 }
@@ -127,3 +600,58 @@ impl NotificationWithPost {
         }
     }
 }
+
+#[derive(Serialize, Clone)]
+pub struct DirectMessageWithFiles {
+    pub id: i32,
+    pub sender_uuid: String,
+    pub recipient_uuid: String,
+    pub message_raw: String,
+    pub message: Vec<Markup>,
+    pub created_at: NaiveDateTime,
+    pub read_at: Option<NaiveDateTime>,
+    pub files: Vec<File>,
+    #[serde(skip)]
+    pub quarantined: bool,
+}
+
+impl DirectMessageWithFiles {
+    pub fn new(
+        message: DirectMessage,
+        files: Vec<File>,
+        emoji: &EmojiMap,
+        options: &MessageParserOptions,
+    ) -> DirectMessageWithFiles {
+        DirectMessageWithFiles {
+            id: message.id,
+            sender_uuid: message.sender_uuid,
+            recipient_uuid: message.recipient_uuid,
+            message_raw: message.message.clone(),
+            message: MessageParser::str_to_markup_with_emoji(&message.message, emoji, options),
+            created_at: message.created_at,
+            read_at: message.read_at,
+            files: replace_removed_files(files),
+            quarantined: message.quarantined,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ConversationWithMessage {
+    pub user_uuid: String,
+    pub unread_count: i64,
+    pub last_message: DirectMessageWithFiles,
+}
+
+impl ConversationWithMessage {
+    pub fn new(
+        conversation: Conversation,
+        last_message: DirectMessageWithFiles,
+    ) -> ConversationWithMessage {
+        ConversationWithMessage {
+            user_uuid: conversation.user_uuid,
+            unread_count: conversation.unread_count,
+            last_message,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::moderation::Moderation;
+use crate::routes::types::Admin;
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::webhooks::{Webhook, WebhookDelivery};
+use chat::repositories::webhooks::WebhookRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    url: String,
+    /// Required when creating a webhook. The current one is kept if omitted when updating.
+    secret: Option<String>,
+    event_types: Vec<String>,
+    message_filter: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    item: Webhook,
+}
+
+#[derive(Serialize)]
+pub struct WebhookListResponse {
+    items: Vec<Webhook>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookDeliveryListResponse {
+    items: Vec<WebhookDelivery>,
+}
+
+#[derive(Responder)]
+pub enum UpdateWebhookResponse {
+    Created(Created<Json<WebhookResponse>>),
+    Updated(Json<WebhookResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateWebhookResponse {
+    fn created(webhook: Webhook) -> UpdateWebhookResponse {
+        let location = format!("/api/v1/admin/webhooks/{}", webhook.id);
+        let json = Json(WebhookResponse { item: webhook });
+        UpdateWebhookResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(webhook: Webhook) -> UpdateWebhookResponse {
+        let json = Json(WebhookResponse { item: webhook });
+        UpdateWebhookResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateWebhookResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateWebhookResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateWebhookResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateWebhookResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_webhook_list(_admin: Admin, conn: ChatDbConn) -> Json<WebhookListResponse> {
+    let items = WebhookRepository::get_all(&conn);
+    Json(WebhookListResponse { items })
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_webhook(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<WebhookRequest>,
+) -> Result<UpdateWebhookResponse, DatabaseErrorResponse> {
+    let secret = data.secret.clone().unwrap_or_default();
+    let result = Webhook::new(
+        &data.url,
+        &secret,
+        data.event_types.clone(),
+        data.message_filter.as_deref(),
+        &admin.get_uuid(),
+    );
+    match result {
+        Ok(new_webhook) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let webhook = moderation.create_webhook(&new_webhook)?;
+            Ok(UpdateWebhookResponse::created(webhook))
+        }
+        Err(message) => Ok(UpdateWebhookResponse::bad_request(&message)),
+    }
+}
+
+#[put("/<id>", format = "json", data = "<data>")]
+pub fn update_webhook(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+    data: Json<WebhookRequest>,
+) -> Result<UpdateWebhookResponse, DatabaseErrorResponse> {
+    let webhook = match WebhookRepository::get_one(&conn, id) {
+        Some(webhook) => webhook,
+        None => return Ok(UpdateWebhookResponse::not_found("Not found")),
+    };
+
+    let secret = data
+        .secret
+        .clone()
+        .unwrap_or_else(|| webhook.secret.clone());
+    let result = Webhook::new(
+        &data.url,
+        &secret,
+        data.event_types.clone(),
+        data.message_filter.as_deref(),
+        &admin.get_uuid(),
+    );
+    match result {
+        Ok(new_webhook) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let webhook = moderation.update_webhook(&webhook, &new_webhook)?;
+            Ok(UpdateWebhookResponse::updated(webhook))
+        }
+        Err(message) => Ok(UpdateWebhookResponse::bad_request(&message)),
+    }
+}
+
+#[delete("/<id>")]
+pub fn delete_webhook(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+) -> Result<UpdateWebhookResponse, DatabaseErrorResponse> {
+    match WebhookRepository::get_one(&conn, id) {
+        Some(webhook) => {
+            let webhook = Moderation::new(&conn, &admin.get_uuid()).delete_webhook(&webhook)?;
+            Ok(UpdateWebhookResponse::updated(webhook))
+        }
+        None => Ok(UpdateWebhookResponse::not_found("Not found")),
+    }
+}
+
+/// Returns the latest deliveries to the webhook with their status and last response, newest
+/// first.
+#[get("/<id>/deliveries", format = "json")]
+pub fn get_delivery_list(
+    _admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+) -> Option<Json<WebhookDeliveryListResponse>> {
+    WebhookRepository::get_one(&conn, id).map(|webhook| {
+        let items = WebhookRepository::get_deliveries(&conn, &webhook);
+        Json(WebhookDeliveryListResponse { items })
+    })
+}
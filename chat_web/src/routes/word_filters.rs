@@ -0,0 +1,215 @@
+use crate::moderation::Moderation;
+use crate::routes::types::{Admin, PostFlagWithPost, PostWithFiles};
+use crate::routes::{DatabaseErrorResponse, ErrorJson};
+use crate::ChatDbConn;
+use chat::models::message_parser::MessageParserOptions;
+use chat::models::word_filters::{WordFilter, WordFilterResult};
+use chat::repositories::emoji::EmojiRepository;
+use chat::repositories::files::FileRepository;
+use chat::repositories::word_filters::WordFilterRepository;
+use rocket::response::status::{BadRequest, Created, NotFound};
+use rocket::State;
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct WordFilterRequest {
+    pattern: String,
+    replacement: Option<String>,
+    action: String,
+}
+
+#[derive(Serialize)]
+pub struct WordFilterResponse {
+    item: WordFilter,
+}
+
+#[derive(Serialize)]
+pub struct WordFilterListResponse {
+    items: Vec<WordFilter>,
+}
+
+#[derive(Serialize)]
+pub struct PostFlagResponse {
+    item: PostFlagWithPost,
+}
+
+#[derive(Serialize)]
+pub struct PostFlagListResponse {
+    items: Vec<PostFlagWithPost>,
+}
+
+#[derive(Responder)]
+pub enum UpdateWordFilterResponse {
+    Created(Created<Json<WordFilterResponse>>),
+    Updated(Json<WordFilterResponse>),
+    BadRequest(BadRequest<Json<ErrorJson>>),
+    NotFound(NotFound<Json<ErrorJson>>),
+}
+
+impl UpdateWordFilterResponse {
+    fn created(filter: WordFilter) -> UpdateWordFilterResponse {
+        let location = format!("/api/v1/filters/{}", filter.id);
+        let json = Json(WordFilterResponse { item: filter });
+        UpdateWordFilterResponse::Created(Created(location, Some(json)))
+    }
+
+    fn updated(filter: WordFilter) -> UpdateWordFilterResponse {
+        let json = Json(WordFilterResponse { item: filter });
+        UpdateWordFilterResponse::Updated(json)
+    }
+
+    fn bad_request(error: &str) -> UpdateWordFilterResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateWordFilterResponse::BadRequest(BadRequest(Some(json)))
+    }
+
+    fn not_found(error: &str) -> UpdateWordFilterResponse {
+        let message = String::from(error);
+        let json = Json(ErrorJson { message });
+        UpdateWordFilterResponse::NotFound(NotFound(json))
+    }
+}
+
+#[get("/", format = "json")]
+pub fn get_word_filter_list(_admin: Admin, conn: ChatDbConn) -> Json<WordFilterListResponse> {
+    let items = WordFilterRepository::get_all(&conn);
+    Json(WordFilterListResponse { items })
+}
+
+#[post("/", format = "json", data = "<data>")]
+pub fn create_word_filter(
+    admin: Admin,
+    conn: ChatDbConn,
+    data: Json<WordFilterRequest>,
+) -> Result<UpdateWordFilterResponse, DatabaseErrorResponse> {
+    let replacement = data.replacement.clone().unwrap_or_default();
+    match WordFilter::new(&data.pattern, &replacement, &data.action, &admin.get_uuid()) {
+        Ok(new_filter) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let filter = moderation.create_word_filter(&new_filter)?;
+            Ok(UpdateWordFilterResponse::created(filter))
+        }
+        Err(message) => Ok(UpdateWordFilterResponse::bad_request(&message)),
+    }
+}
+
+#[put("/<id>", format = "json", data = "<data>")]
+pub fn update_word_filter(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+    data: Json<WordFilterRequest>,
+) -> Result<UpdateWordFilterResponse, DatabaseErrorResponse> {
+    let filter = match WordFilterRepository::get_one(&conn, id) {
+        Some(filter) => filter,
+        None => return Ok(UpdateWordFilterResponse::not_found("Not found")),
+    };
+
+    let replacement = data.replacement.clone().unwrap_or_default();
+    match WordFilter::new(&data.pattern, &replacement, &data.action, &admin.get_uuid()) {
+        Ok(new_filter) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let filter = moderation.update_word_filter(&filter, &new_filter)?;
+            Ok(UpdateWordFilterResponse::updated(filter))
+        }
+        Err(message) => Ok(UpdateWordFilterResponse::bad_request(&message)),
+    }
+}
+
+#[delete("/<id>")]
+pub fn delete_word_filter(
+    admin: Admin,
+    conn: ChatDbConn,
+    id: i32,
+) -> Result<UpdateWordFilterResponse, DatabaseErrorResponse> {
+    match WordFilterRepository::get_one(&conn, id) {
+        Some(filter) => {
+            let moderation = Moderation::new(&conn, &admin.get_uuid());
+            let filter = moderation.delete_word_filter(&filter)?;
+            Ok(UpdateWordFilterResponse::updated(filter))
+        }
+        None => Ok(UpdateWordFilterResponse::not_found("Not found")),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TestWordFilterRequest {
+    message: String,
+}
+
+#[derive(Serialize)]
+pub struct TestWordFilterResponse {
+    message: String,
+    rejected: bool,
+    flagged_by: Vec<i32>,
+}
+
+/// Shows what the current filters would do with a message without posting it.
+#[post("/test", format = "json", data = "<data>")]
+pub fn test_word_filters(
+    _admin: Admin,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    data: Json<TestWordFilterRequest>,
+) -> Json<TestWordFilterResponse> {
+    let filters = WordFilterRepository::get_all(&conn);
+    let emoji = EmojiRepository::get_map(&conn);
+    let WordFilterResult {
+        message,
+        rejected,
+        flagged_by,
+    } = WordFilter::apply_all(&data.message, &filters, &emoji, &options);
+
+    Json(TestWordFilterResponse {
+        message,
+        rejected,
+        flagged_by,
+    })
+}
+
+#[get("/", format = "json")]
+pub fn get_flag_list(
+    _admin: Admin,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+) -> Json<PostFlagListResponse> {
+    let (flags, posts): (Vec<_>, Vec<_>) =
+        WordFilterRepository::get_flags(&conn).into_iter().unzip();
+
+    let files = FileRepository::get_belonging_to_posts(&conn, &posts);
+    let emoji = EmojiRepository::get_map(&conn);
+    let items = flags
+        .into_iter()
+        .zip(posts.into_iter().zip(files))
+        .map(|(flag, (post, files))| {
+            let post = PostWithFiles::new(post, files, &emoji, &options);
+            PostFlagWithPost::new(flag, post)
+        })
+        .collect();
+
+    Json(PostFlagListResponse { items })
+}
+
+#[delete("/<id>")]
+pub fn delete_flag(
+    admin: Admin,
+    conn: ChatDbConn,
+    options: State<MessageParserOptions>,
+    id: i32,
+) -> Result<Option<Json<PostFlagResponse>>, DatabaseErrorResponse> {
+    let (flag, post) = match WordFilterRepository::get_one_flag(&conn, id) {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+
+    let flag = Moderation::new(&conn, &admin.get_uuid()).dismiss_flag(&flag)?;
+    let files = FileRepository::get_belonging_to_post(&conn, &post);
+    let emoji = EmojiRepository::get_map(&conn);
+    let post = PostWithFiles::new(post, files, &emoji, &options);
+
+    Ok(Some(Json(PostFlagResponse {
+        item: PostFlagWithPost::new(flag, post),
+    })))
+}
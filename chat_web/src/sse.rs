@@ -0,0 +1,151 @@
+use crate::ws::{Subscription, Ws};
+use serde_json::Value;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+use tungstenite::protocol::Message;
+
+/// Path of the event stream on the public port, for clients behind proxies that break
+/// WebSocket connections.
+pub const EVENTS_PATH: &str = "/api/v1/events";
+
+/// How long to wait for events before checking for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// A client that can't take an event for this long is considered dead.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Comments are sent when there are no events, so that proxies don't close the connection and
+/// disconnected clients are noticed.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Serves the same events as the WebSocket server as `text/event-stream`. Each event is sent
+/// as the same JSON as over WebSocket, with its sequence id as the event id, so that browsers
+/// resume from it with `Last-Event-ID` after reconnecting.
+pub struct EventStream<'a> {
+    ws: &'a Ws,
+    stream: TcpStream,
+    last_write: Instant,
+}
+
+impl<'a> EventStream<'a> {
+    /// Takes over a connection whose request head has already been read.
+    pub fn serve(
+        ws: &Ws,
+        stream: TcpStream,
+        query: Option<&str>,
+        last_event_id: Option<u64>,
+    ) -> io::Result<()> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+        let mut events = EventStream {
+            ws,
+            stream,
+            last_write: Instant::now(),
+        };
+        events.stream.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+            Content-Type: text/event-stream\r\n\
+            Cache-Control: no-cache\r\n\
+            X-Accel-Buffering: no\r\n\
+            Connection: close\r\n\r\n",
+        )?;
+
+        // Clients that can't set the header pass the id in the query instead.
+        let resume_from = last_event_id
+            .or_else(|| Ws::get_query_param(query, "last_event_id").and_then(|id| id.parse().ok()));
+        let subscription = ws.subscribe(Ws::get_claims(query), Ws::get_room(query), resume_from);
+        let result = events.run(&subscription);
+        ws.unsubscribe(subscription.id);
+
+        result
+    }
+
+    fn write(&mut self, data: &str) -> io::Result<()> {
+        self.last_write = Instant::now();
+        self.stream.write_all(data.as_bytes())?;
+        self.stream.flush()
+    }
+
+    /// Formats the WebSocket message as an event of the stream, if it is a text message.
+    fn format_event(message: &Message) -> Option<String> {
+        let text = message.to_text().ok()?;
+
+        // Only the numbered events can be resumed from, the others are sent without an id.
+        let seq = serde_json::from_str::<Value>(text)
+            .ok()
+            .and_then(|event| event.get("seq").and_then(Value::as_u64));
+        match seq {
+            Some(seq) => Some(format!("id: {}\ndata: {}\n\n", seq, text)),
+            None => Some(format!("data: {}\n\n", text)),
+        }
+    }
+
+    fn write_event(&mut self, message: &Message) -> io::Result<()> {
+        match EventStream::format_event(message) {
+            Some(event) => self.write(&event),
+            None => Ok(()),
+        }
+    }
+
+    fn run(&mut self, subscription: &Subscription) -> io::Result<()> {
+        match &subscription.missed {
+            Some(events) => {
+                for message in events {
+                    self.write_event(message)?;
+                }
+            }
+            None => {
+                let event = json!({ "event": "resync_required", "data": {} });
+                self.write(&format!("data: {}\n\n", *event))?;
+            }
+        }
+
+        while !self.ws.is_shutdown() {
+            match subscription.receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(message) => self.write_event(&message)?,
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.last_write.elapsed() > KEEPALIVE_INTERVAL {
+                        self.write(": keepalive\n\n")?;
+                    }
+                }
+                // The client has been dropped from the list as a slow consumer.
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_event_with_seq() {
+        let message = Message::text(r#"{"event":"post_hidden","data":{"id":1},"seq":5}"#);
+        assert_eq!(
+            Some(String::from(
+                "id: 5\ndata: {\"event\":\"post_hidden\",\"data\":{\"id\":1},\"seq\":5}\n\n"
+            )),
+            EventStream::format_event(&message)
+        );
+    }
+
+    #[test]
+    fn format_event_without_seq() {
+        let message = Message::text(r#"{"event":"presence","data":{"clients":1}}"#);
+        assert_eq!(
+            Some(String::from(
+                "data: {\"event\":\"presence\",\"data\":{\"clients\":1}}\n\n"
+            )),
+            EventStream::format_event(&message)
+        );
+    }
+
+    #[test]
+    fn format_event_skips_binary_messages() {
+        let message = Message::binary(vec![0xff, 0xfe]);
+        assert_eq!(None, EventStream::format_event(&message));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::events::{Audience, BusEvent, EventSubscriber};
+use chat::models::webhooks::{WebhookDelivery, EVENT_TYPES};
+use chat::repositories::webhooks::WebhookRepository;
+use chrono::prelude::*;
+use chrono::Duration as ChronoDuration;
+use diesel::pg::PgConnection;
+use diesel::r2d2::{ConnectionManager, Pool};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often due retries are checked when no events come in.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Number of deliveries taken at once by a worker.
+const BATCH_SIZE: i64 = 20;
+/// Deliveries taken by a worker that stopped before finishing them are retried after this long.
+const LEASE_MINUTES: i64 = 10;
+/// How long finished deliveries are kept in the log.
+const DELIVERY_RETENTION_DAYS: i64 = 7;
+
+/// Delivers the events to the webhooks. Events are received from the bus, turned into
+/// deliveries for the matching webhooks and sent by a background worker, which also retries
+/// the failed ones. With several instances each one does it, the database makes sure that every
+/// delivery is only created and sent once.
+pub struct Webhooks {
+    sender: Mutex<Sender<BusEvent>>,
+}
+
+struct WebhookWorker {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    receiver: Receiver<BusEvent>,
+    last_cleanup: Instant,
+}
+
+impl Webhooks {
+    pub fn start(pool: Pool<ConnectionManager<PgConnection>>) -> Webhooks {
+        let (sender, receiver) = mpsc::channel();
+        let worker = WebhookWorker {
+            pool,
+            receiver,
+            last_cleanup: Instant::now(),
+        };
+        thread::spawn(move || worker.run());
+
+        Webhooks {
+            sender: Mutex::new(sender),
+        }
+    }
+}
+
+impl EventSubscriber for Webhooks {
+    /// Only queues the event, as subscribers are called while publishing.
+    fn receive(&self, event: &BusEvent) {
+        let _ = self.sender.lock().unwrap().send(event.clone());
+    }
+}
+
+impl WebhookWorker {
+    fn now() -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0)
+    }
+
+    /// Returns the text of the post the event is about, if any.
+    fn get_message(event: &Value) -> Option<&str> {
+        let item = &event["data"]["item"];
+        item["message_raw"]
+            .as_str()
+            .or_else(|| item["post"]["message_raw"].as_str())
+    }
+
+    fn run(mut self) {
+        loop {
+            match self.receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => self.create_deliveries(&event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            while let Ok(event) = self.receiver.try_recv() {
+                self.create_deliveries(&event);
+            }
+
+            self.send_due();
+
+            if self.last_cleanup.elapsed() > CLEANUP_INTERVAL {
+                self.last_cleanup = Instant::now();
+                self.delete_old();
+            }
+        }
+    }
+
+    fn create_deliveries(&self, event: &BusEvent) {
+        // Events meant for some users only are never sent out, whatever their type.
+        let is_public = match event.audience {
+            Audience::All | Audience::Room(_) => true,
+            Audience::Users(_) | Audience::UsersInRoom(_, _) => false,
+        };
+        if !is_public || event.quarantined {
+            return;
+        }
+
+        let value: Value = match serde_json::from_str(&event.payload) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        let event_type = match value["event"].as_str() {
+            Some(event_type) if EVENT_TYPES.contains(&event_type) => event_type,
+            _ => return,
+        };
+
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(e) => return println!("Webhook error: {}", e),
+        };
+
+        let message = WebhookWorker::get_message(&value);
+        for webhook in WebhookRepository::get_all(&conn) {
+            if webhook.matches(event_type, message) {
+                let delivery =
+                    WebhookDelivery::new(webhook.id, event.seq as i64, event_type, &event.payload);
+                WebhookRepository::create_delivery(&conn, &delivery);
+            }
+        }
+    }
+
+    /// Sends the due deliveries with a thread per webhook, so that a slow endpoint doesn't hold
+    /// up the others. The deliveries of each webhook are still sent in order.
+    fn send_due(&self) {
+        let conn = match self.pool.get() {
+            Ok(conn) => conn,
+            Err(e) => return println!("Webhook error: {}", e),
+        };
+
+        let now = WebhookWorker::now();
+        let lease_until = now + ChronoDuration::minutes(LEASE_MINUTES);
+        let deliveries =
+            WebhookRepository::claim_due_deliveries(&conn, now, lease_until, BATCH_SIZE);
+        drop(conn);
+
+        let mut by_webhook: BTreeMap<i32, Vec<WebhookDelivery>> = BTreeMap::new();
+        for delivery in deliveries {
+            by_webhook
+                .entry(delivery.webhook_id)
+                .or_default()
+                .push(delivery);
+        }
+
+        let handles: Vec<_> = by_webhook
+            .into_iter()
+            .map(|(webhook_id, deliveries)| {
+                let pool = self.pool.clone();
+                thread::spawn(move || WebhookWorker::send_all(&pool, webhook_id, &deliveries))
+            })
+            .collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+
+    fn send_all(
+        pool: &Pool<ConnectionManager<PgConnection>>,
+        webhook_id: i32,
+        deliveries: &[WebhookDelivery],
+    ) {
+        let conn = match pool.get() {
+            Ok(conn) => conn,
+            Err(e) => return println!("Webhook error: {}", e),
+        };
+
+        if let Some(webhook) = WebhookRepository::get_one(&conn, webhook_id) {
+            for delivery in deliveries {
+                let result = delivery.send(&webhook);
+                let attempt = delivery.attempted(result, WebhookWorker::now());
+                WebhookRepository::update_delivery(&conn, delivery, &attempt);
+            }
+        }
+    }
+
+    fn delete_old(&self) {
+        if let Ok(conn) = self.pool.get() {
+            let before = WebhookWorker::now() - ChronoDuration::days(DELIVERY_RETENTION_DAYS);
+            WebhookRepository::delete_deliveries_before(&conn, before);
+        }
+    }
+}
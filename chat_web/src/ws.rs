@@ -1,60 +1,894 @@
-use std::collections::HashMap;
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use crate::events::{Audience, BusEvent, EventSubscriber};
+use crate::front::TrustedProxies;
+use crate::routes::posts::{create_ws_post, PostContext, PostData};
+use crate::routes::types::{decode_token, Claims};
+use chat::models::rooms::DEFAULT_ROOM_SLUG;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
 use tungstenite::protocol::{Message, WebSocket};
+use tungstenite::Error;
 
-type WsClient = Arc<Mutex<WebSocket<TcpStream>>>;
-type WsClients = Arc<Mutex<HashMap<SocketAddr, WsClient>>>;
+/// How long reads and accepts block before checking for outgoing messages and shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// A client that can't take a frame for this long is considered dead.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Connections that send nothing, not even a pong, for this long are closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(75);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of messages queued for a client before it is dropped as a slow consumer.
+const SEND_QUEUE_SIZE: usize = 256;
+/// Number of recent events kept for clients resuming after a reconnect.
+const EVENT_LOG_SIZE: usize = 1000;
+/// How often typing and presence changes are sent, at most.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Clients keep sending `typing_started` while typing. They are considered to have stopped
+/// typing if they don't for this long.
+const TYPING_TIMEOUT: Duration = Duration::from_secs(6);
 
+#[derive(Deserialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+enum ClientEvent {
+    /// Authenticates the connection with the same token as the HTTP API.
+    Auth {
+        token: String,
+    },
+    TypingStarted,
+    TypingStopped,
+    /// Creates a text post in the room of the connection. Answered with an `ack` carrying the
+    /// nonce and either the post id or an error.
+    CreatePost {
+        nonce: String,
+        name: String,
+        tripcode: Option<String>,
+        message: String,
+    },
+}
+
+/// Messages sent by clients. Anything else, like the keepalive text, is ignored.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClientMessage {
+    Event(ClientEvent),
+    /// Replays the events sent after `resume_from` that the client missed while reconnecting.
+    Resume {
+        resume_from: u64,
+    },
+}
+
+/// A client receiving events over another transport, like the event stream.
+pub struct Subscription {
+    pub id: u64,
+    pub receiver: Receiver<Message>,
+    /// Events missed since the requested sequence id, or `None` if they are no longer available.
+    pub missed: Option<Vec<Message>>,
+}
+
+struct WsClient {
+    sender: SyncSender<Message>,
+    user_uuid: Option<String>,
+    user_name: Option<String>,
+    room: String,
+    /// Sequence id of the last event sent before the client connected. Later events are
+    /// delivered live.
+    joined_seq: u64,
+}
+
+impl WsClient {
+    fn is_in(&self, audience: &Audience) -> bool {
+        audience.includes(self.user_uuid.as_deref(), &self.room)
+    }
+}
+
+struct LoggedEvent {
+    seq: u64,
+    audience: Audience,
+    message: Message,
+}
+
+/// The open connections, indexed by user for targeted delivery, and the recent events.
+#[derive(Default)]
+struct WsRegistry {
+    clients: HashMap<u64, WsClient>,
+    users: HashMap<String, HashSet<u64>>,
+    events: VecDeque<LoggedEvent>,
+    last_seq: u64,
+    /// When the typing clients last said they were typing.
+    typing: HashMap<u64, Instant>,
+    /// Rooms whose typing users changed since the last tick.
+    typing_rooms: HashSet<String>,
+    /// Numbers of clients and users last sent in the `presence` event.
+    presence: (usize, usize),
+}
+
+impl WsRegistry {
+    /// Adds the sequence id to an event, which is always a JSON object.
+    fn with_seq(message: &str, seq: u64) -> String {
+        match serde_json::from_str(message) {
+            Ok(Value::Object(mut event)) => {
+                event.insert(String::from("seq"), Value::from(seq));
+                Value::Object(event).to_string()
+            }
+            _ => message.to_string(),
+        }
+    }
+
+    fn insert(&mut self, id: u64, mut client: WsClient) {
+        client.joined_seq = self.last_seq;
+        if let Some(uuid) = &client.user_uuid {
+            self.users.entry(uuid.clone()).or_default().insert(id);
+        }
+
+        self.clients.insert(id, client);
+    }
+
+    fn unindex(&mut self, uuid: &str, id: u64) {
+        if let Some(ids) = self.users.get_mut(uuid) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.users.remove(uuid);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.stop_typing(id);
+        if let Some(client) = self.clients.remove(&id) {
+            if let Some(uuid) = client.user_uuid {
+                self.unindex(&uuid, id);
+            }
+        }
+    }
+
+    fn set_user(&mut self, id: u64, uuid: &str, name: &str) {
+        let previous = match self.clients.get_mut(&id) {
+            Some(client) => {
+                client.user_name = Some(name.to_string());
+                client.user_uuid.replace(uuid.to_string())
+            }
+            None => return,
+        };
+
+        if let Some(previous) = previous {
+            self.unindex(&previous, id);
+        }
+
+        self.users.entry(uuid.to_string()).or_default().insert(id);
+    }
+
+    fn get_user_ids(&self, uuids: &[String]) -> HashSet<u64> {
+        uuids
+            .iter()
+            .filter_map(|uuid| self.users.get(uuid))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    fn get_audience_ids(&self, audience: &Audience) -> Vec<u64> {
+        let ids: Vec<u64> = match audience.get_users() {
+            Some(uuids) => self.get_user_ids(uuids).into_iter().collect(),
+            None => self.clients.keys().cloned().collect(),
+        };
+
+        ids.into_iter()
+            .filter(|id| self.clients[id].is_in(audience))
+            .collect()
+    }
+
+    /// Sends the event to its audience and keeps it for clients resuming later. Events of other
+    /// instances may arrive slightly out of order, so the last id only ever increases.
+    fn broadcast(&mut self, event: &BusEvent) {
+        self.last_seq = self.last_seq.max(event.seq);
+        let message = Message::text(WsRegistry::with_seq(&event.payload, event.seq));
+        let ids = self.get_audience_ids(&event.audience);
+        self.send(ids, &message);
+
+        self.events.push_back(LoggedEvent {
+            seq: event.seq,
+            audience: event.audience.clone(),
+            message,
+        });
+        if self.events.len() > EVENT_LOG_SIZE {
+            self.events.pop_front();
+        }
+    }
+
+    /// Returns the events for the client sent after `resume_from` and before it connected, or
+    /// `None` if some of them are no longer in the log.
+    fn get_missed_events(&self, id: u64, resume_from: u64) -> Option<Vec<Message>> {
+        let client = self.clients.get(&id)?;
+        let oldest_seq = match self.events.front() {
+            Some(event) => event.seq,
+            None => self.last_seq + 1,
+        };
+        if resume_from > self.last_seq || resume_from + 1 < oldest_seq {
+            return None;
+        }
+
+        let events = self
+            .events
+            .iter()
+            .filter(|event| event.seq > resume_from && event.seq <= client.joined_seq)
+            .filter(|event| client.is_in(&event.audience))
+            .map(|event| event.message.clone())
+            .collect();
+
+        Some(events)
+    }
+
+    /// Only authenticated clients can type, as the others have no name to show.
+    fn start_typing(&mut self, id: u64) {
+        let client = match self.clients.get(&id) {
+            Some(client) if client.user_name.is_some() => client,
+            _ => return,
+        };
+
+        if self.typing.insert(id, Instant::now()).is_none() {
+            self.typing_rooms.insert(client.room.clone());
+        }
+    }
+
+    fn stop_typing(&mut self, id: u64) {
+        if self.typing.remove(&id).is_some() {
+            if let Some(client) = self.clients.get(&id) {
+                self.typing_rooms.insert(client.room.clone());
+            }
+        }
+    }
+
+    fn get_typing_names(&self, room: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .typing
+            .keys()
+            .filter_map(|id| self.clients.get(id))
+            .filter(|client| client.room == room)
+            .filter_map(|client| client.user_name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Sends the typing users of the rooms where they changed, and the number of connected
+    /// clients and users if it changed. These events are only useful live, so they aren't
+    /// numbered nor kept for resuming.
+    fn tick(&mut self) {
+        let expired: Vec<u64> = self
+            .typing
+            .iter()
+            .filter(|(_, since)| since.elapsed() > TYPING_TIMEOUT)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.stop_typing(id);
+        }
+
+        let rooms: Vec<String> = self.typing_rooms.drain().collect();
+        for room in rooms {
+            let message = json!({
+                "event": "typing",
+                "data": { "room": room, "user_names": self.get_typing_names(&room) },
+            });
+            let ids = self.get_audience_ids(&Audience::Room(room));
+            self.send(ids, &Message::text(message.to_string()));
+        }
+
+        let presence = (self.clients.len(), self.users.len());
+        if presence != self.presence {
+            self.presence = presence;
+            let message = json!({
+                "event": "presence",
+                "data": { "clients": presence.0, "users": presence.1 },
+            });
+            let ids: Vec<u64> = self.clients.keys().cloned().collect();
+            self.send(ids, &Message::text(message.to_string()));
+        }
+    }
+
+    /// Queues the message for the clients. Clients whose queue is full are dropped, which makes
+    /// their connection close instead of delaying everyone else.
+    fn send<I>(&mut self, ids: I, message: &Message)
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let dropped: Vec<u64> = ids
+            .into_iter()
+            .filter(|id| match self.clients.get(id) {
+                Some(client) => client.sender.try_send(message.clone()).is_err(),
+                None => false,
+            })
+            .collect();
+
+        for id in dropped {
+            self.remove(id);
+        }
+    }
+}
+
+type WsClients = Arc<Mutex<WsRegistry>>;
+
+/// Handle to the WebSocket server, shared by the routes and the listeners feeding connections.
+#[derive(Clone, Default)]
 pub struct Ws {
     clients: WsClients,
+    posts: Option<Arc<PostContext>>,
+    trusted_proxies: TrustedProxies,
+    shutdown: Arc<AtomicBool>,
+    next_id: Arc<AtomicU64>,
+    /// Listener and ticker threads, stopped on shutdown.
+    threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+/// A connection along with the part of it that has already been read, like the handshake
+/// request of a connection the front forwarded other requests on before. That part is read
+/// again first.
+struct WsStream {
+    read: Cursor<Vec<u8>>,
+    stream: TcpStream,
+}
+
+impl Read for WsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if (self.read.position() as usize) < self.read.get_ref().len() {
+            return self.read.read(buf);
+        }
+
+        self.stream.read(buf)
+    }
+}
+
+impl Write for WsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// The state of a single connection, owned by its thread.
+struct WsConnection {
+    id: u64,
+    ws: Ws,
+    socket: WebSocket<WsStream>,
+    /// The address of the client, taken from `X-Real-IP` behind a trusted proxy like in HTTP
+    /// requests.
+    ip: Option<IpAddr>,
+    receiver: Receiver<Message>,
+    last_seen: Instant,
+    last_ping: Instant,
+}
+
+impl WsConnection {
+    fn is_timeout(error: &Error) -> bool {
+        match error {
+            Error::Io(e) => e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+
+    /// Writes the queued messages. Fails when the client is gone or too slow to take them.
+    fn write_queued(&mut self) -> Result<(), Error> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(message) => self.socket.write_message(message)?,
+                Err(TryRecvError::Empty) => return Ok(()),
+                // The client has been dropped from the list, most likely as a slow consumer.
+                Err(TryRecvError::Disconnected) => return Err(Error::AlreadyClosed),
+            }
+        }
+    }
+
+    fn authenticate(&mut self, token: &str) -> Result<(), Error> {
+        let reply = match decode_token(token) {
+            Some(claims) => {
+                let mut clients = self.ws.clients.lock().unwrap();
+                clients.set_user(self.id, &claims.user_uuid, &claims.user_name);
+                json!({
+                    "event": "authenticated",
+                    "data": { "user_uuid": claims.user_uuid },
+                })
+            }
+            None => json!({
+                "event": "auth_failed",
+                "data": { "message": "Invalid token" },
+            }),
+        };
+
+        self.socket.write_message(Message::text(reply.to_string()))
+    }
+
+    fn resume(&mut self, resume_from: u64) -> Result<(), Error> {
+        let events = self
+            .ws
+            .clients
+            .lock()
+            .unwrap()
+            .get_missed_events(self.id, resume_from);
+        match events {
+            Some(events) => {
+                for message in events {
+                    self.socket.write_message(message)?;
+                }
+
+                Ok(())
+            }
+            None => {
+                let reply = json!({
+                    "event": "resync_required",
+                    "data": {},
+                });
+                self.socket.write_message(Message::text(reply.to_string()))
+            }
+        }
+    }
+
+    fn create_post(&mut self, nonce: String, data: PostData) -> Result<(), Error> {
+        let (user_uuid, room) = match self.ws.clients.lock().unwrap().clients.get(&self.id) {
+            Some(client) => (client.user_uuid.clone(), client.room.clone()),
+            None => return Err(Error::AlreadyClosed),
+        };
+
+        let result = match (&self.ws.posts, user_uuid) {
+            (Some(posts), Some(user_uuid)) => {
+                create_ws_post(posts, &user_uuid, self.ip, &room, data)
+            }
+            (None, _) => Err(json!({ "message": "Posting is not available" })),
+            (_, None) => Err(json!({ "message": "Unauthorized" })),
+        };
+        let reply = match result {
+            Ok(id) => json!({
+                "event": "ack",
+                "data": { "nonce": nonce, "id": id },
+            }),
+            Err(error) => json!({
+                "event": "ack",
+                "data": { "nonce": nonce, "error": error },
+            }),
+        };
+
+        // With the in-process bus, queued after the post_created event. With a shared bus the
+        // event goes through the database and may come after the ack.
+        let message = Message::text(reply.to_string());
+        self.ws
+            .clients
+            .lock()
+            .unwrap()
+            .send(vec![self.id], &message);
+        Ok(())
+    }
+
+    /// Reads an incoming frame, if any. Pings and close frames are answered by tungstenite.
+    fn read(&mut self) -> Result<(), Error> {
+        match self.socket.read_message() {
+            Ok(message) => {
+                self.last_seen = Instant::now();
+                if let Message::Text(text) = message {
+                    match serde_json::from_str(&text) {
+                        Ok(ClientMessage::Event(ClientEvent::Auth { token })) => {
+                            self.authenticate(&token)?
+                        }
+                        Ok(ClientMessage::Event(ClientEvent::TypingStarted)) => {
+                            self.ws.clients.lock().unwrap().start_typing(self.id)
+                        }
+                        Ok(ClientMessage::Event(ClientEvent::TypingStopped)) => {
+                            self.ws.clients.lock().unwrap().stop_typing(self.id)
+                        }
+                        Ok(ClientMessage::Event(ClientEvent::CreatePost {
+                            nonce,
+                            name,
+                            tripcode,
+                            message,
+                        })) => {
+                            let data = PostData::new(name, tripcode, message, Vec::new());
+                            self.create_post(nonce, data)?
+                        }
+                        Ok(ClientMessage::Resume { resume_from }) => self.resume(resume_from)?,
+                        Err(_) => {}
+                    }
+                }
+
+                Ok(())
+            }
+            Err(ref e) if WsConnection::is_timeout(e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn keep_alive(&mut self) -> Result<(), Error> {
+        if self.last_seen.elapsed() > IDLE_TIMEOUT {
+            return Err(Error::ConnectionClosed);
+        }
+
+        if self.last_ping.elapsed() > PING_INTERVAL {
+            self.last_ping = Instant::now();
+            self.socket.write_message(Message::Ping(Vec::new()))?;
+        }
+
+        Ok(())
+    }
+
+    fn run(&mut self, shutdown: &AtomicBool) -> Result<(), Error> {
+        while !shutdown.load(Ordering::Relaxed) {
+            self.write_queued()?;
+            self.read()?;
+            self.keep_alive()?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the close handshake and gives the client a moment to complete it.
+    fn close(&mut self) {
+        if self.socket.close(None).is_err() {
+            return;
+        }
+
+        let deadline = Instant::now() + POLL_INTERVAL * 4;
+        while Instant::now() < deadline {
+            match self.socket.read_message() {
+                Err(ref e) if WsConnection::is_timeout(e) => {}
+                Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+    }
 }
 
 impl Ws {
-    fn handle_connection(clients: &WsClients, addr: SocketAddr, stream: TcpStream) {
-        let websocket = tungstenite::accept(stream);
-        match websocket {
-            Ok(websocket) => {
-                let client = Arc::new(Mutex::new(websocket));
-
-                Arc::clone(&clients)
-                    .lock()
-                    .unwrap()
-                    .insert(addr, Arc::clone(&client));
-            },
+    pub fn get_query_param<'a>(query: Option<&'a str>, name: &str) -> Option<&'a str> {
+        let prefix = format!("{}=", name);
+        query?
+            .split('&')
+            .find_map(|param| param.strip_prefix(prefix.as_str()))
+    }
+
+    /// Returns the user of the connection, if it was opened with a valid `?token=` parameter.
+    /// WebSocket clients may also authenticate later with an `auth` message.
+    pub fn get_claims(query: Option<&str>) -> Option<Claims> {
+        let token = Ws::get_query_param(query, "token")?;
+
+        decode_token(token)
+    }
+
+    /// Returns the address set by the proxy in front of the server, if any.
+    fn get_real_ip(request: &Request) -> Option<&str> {
+        request.headers().get("X-Real-IP")?.to_str().ok()
+    }
+
+    /// Returns the room the connection subscribed to with `?room=`, or the default one.
+    pub fn get_room(query: Option<&str>) -> String {
+        Ws::get_query_param(query, "room")
+            .filter(|room| !room.is_empty())
+            .unwrap_or(DEFAULT_ROOM_SLUG)
+            .to_string()
+    }
+
+    /// Adds a client to the list, returning the queue of its events.
+    fn register(&self, id: u64, claims: Option<Claims>, room: String) -> Receiver<Message> {
+        let (sender, receiver) = mpsc::sync_channel(SEND_QUEUE_SIZE);
+        let client = WsClient {
+            sender,
+            user_uuid: claims.as_ref().map(|claims| claims.user_uuid.clone()),
+            user_name: claims.map(|claims| claims.user_name),
+            room,
+            joined_seq: 0,
+        };
+        self.clients.lock().unwrap().insert(id, client);
+
+        receiver
+    }
+
+    /// Runs a connection from the handshake until it is closed, on its own thread.
+    fn handle_connection(&self, id: u64, stream: TcpStream, read: Vec<u8>) {
+        let configured = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)))
+            .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)));
+        if let Err(e) = configured {
+            println!("WebSocket connection error: {}", e);
+            return;
+        }
+
+        let mut ip = stream.peer_addr().ok().map(|addr| addr.ip());
+        let mut claims = None;
+        let mut room = String::from(DEFAULT_ROOM_SLUG);
+        let stream = WsStream {
+            read: Cursor::new(read),
+            stream,
+        };
+        // The error type is set by tungstenite, the callback never fails.
+        #[allow(clippy::result_large_err)]
+        let websocket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+            ip = ip.map(|peer| {
+                let real_ip = Ws::get_real_ip(request);
+                self.trusted_proxies.get_client_ip(peer, real_ip)
+            });
+            claims = Ws::get_claims(request.uri().query());
+            room = Ws::get_room(request.uri().query());
+            Ok::<Response, ErrorResponse>(response)
+        });
+        let socket = match websocket {
+            Ok(socket) => socket,
             Err(e) => {
                 println!("WebSocket connection error: {}", e);
-            },
+                return;
+            }
+        };
+
+        let stream = &socket.get_ref().stream;
+        if let Err(e) = stream.set_read_timeout(Some(POLL_INTERVAL)) {
+            println!("WebSocket connection error: {}", e);
+            return;
         }
+
+        let receiver = self.register(id, claims, room);
+        let mut connection = WsConnection {
+            id,
+            ws: self.clone(),
+            socket,
+            ip,
+            receiver,
+            last_seen: Instant::now(),
+            last_ping: Instant::now(),
+        };
+        match connection.run(&self.shutdown) {
+            Ok(_) => connection.close(),
+            Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => connection.close(),
+            Err(e) => println!("WebSocket connection error: {}", e),
+        }
+
+        self.clients.lock().unwrap().remove(id);
     }
 
-    pub fn new(address: &str) -> Ws {
-        let clients: WsClients = Arc::new(Mutex::new(HashMap::new()));
+    /// Creates the server and starts the thread sending typing and presence changes. It must
+    /// then be subscribed to the event bus of the posts.
+    pub fn new(posts: PostContext, trusted_proxies: TrustedProxies) -> Ws {
+        let ws = Ws {
+            posts: Some(Arc::new(posts)),
+            trusted_proxies,
+            ..Ws::default()
+        };
+        if let Some(posts) = &ws.posts {
+            ws.clients.lock().unwrap().last_seq = posts.events.last_seq();
+        }
 
-        let thread_address = String::from(address);
-        let thread_clients = Arc::clone(&clients);
-        thread::spawn(move || loop {
-            let listener = TcpListener::bind(&thread_address).unwrap();
-            let (stream, addr) = listener.accept().unwrap();
-            Ws::handle_connection(&thread_clients, addr, stream);
+        let ticker = ws.clone();
+        let handle = thread::spawn(move || {
+            let mut last_tick = Instant::now();
+            while !ticker.shutdown.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                if last_tick.elapsed() >= TICK_INTERVAL {
+                    last_tick = Instant::now();
+                    ticker.clients.lock().unwrap().tick();
+                }
+            }
         });
 
-        Ws { clients }
+        ws.threads.lock().unwrap().push(handle);
+        ws
     }
 
-    pub fn send_to_all(&self, message: &str) {
-        let message = Message::text(message);
-        let clients = Arc::clone(&self.clients);
-        thread::spawn(move || {
-            let mut clients = clients.lock().unwrap();
-            for (key, value) in clients.clone().iter() {
-                let mut client = value.lock().unwrap();
-                match client.write_message(message.clone()) {
-                    Err(_) => clients.remove(key),
-                    _ => None,
-                };
+    pub fn trusted_proxies(&self) -> &TrustedProxies {
+        &self.trusted_proxies
+    }
+
+    /// Takes over a connection, of which `read` has already been read. That's usually nothing,
+    /// or else the start of the handshake request.
+    pub fn accept(&self, stream: TcpStream, read: Vec<u8>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let ws = self.clone();
+        thread::spawn(move || ws.handle_connection(id, stream, read));
+    }
+
+    /// Adds a client receiving events over another transport, with the events it missed since
+    /// `resume_from`. It must be removed with `unsubscribe` once disconnected.
+    pub fn subscribe(
+        &self,
+        claims: Option<Claims>,
+        room: String,
+        resume_from: Option<u64>,
+    ) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let receiver = self.register(id, claims, room);
+        let missed = match resume_from {
+            Some(resume_from) => self
+                .clients
+                .lock()
+                .unwrap()
+                .get_missed_events(id, resume_from),
+            None => Some(Vec::new()),
+        };
+
+        Subscription {
+            id,
+            receiver,
+            missed,
+        }
+    }
+
+    pub fn unsubscribe(&self, id: u64) {
+        self.clients.lock().unwrap().remove(id);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// Passes the connections to the address to `accept` on a thread of its own, until the
+    /// shutdown.
+    pub fn listen_with<F>(&self, address: &str, accept: F)
+    where
+        F: Fn(TcpStream) + Send + 'static,
+    {
+        // Accept without blocking, so that the listener notices the shutdown.
+        let listener = TcpListener::bind(address).unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        let shutdown = Arc::clone(&self.shutdown);
+        let handle = thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => accept(stream),
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(e) => println!("Accept error: {}", e),
+                }
             }
         });
+
+        self.threads.lock().unwrap().push(handle);
+    }
+
+    /// Stops accepting connections and closes the open ones, waiting a bit for them to finish.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        for handle in self.threads.lock().unwrap().drain(..) {
+            let _ = handle.join();
+        }
+
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while !self.clients.lock().unwrap().clients.is_empty() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl EventSubscriber for Ws {
+    fn receive(&self, event: &BusEvent) {
+        self.clients.lock().unwrap().broadcast(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(user_uuid: Option<&str>, room: &str) -> (WsClient, Receiver<Message>) {
+        let (sender, receiver) = mpsc::sync_channel(SEND_QUEUE_SIZE);
+        let client = WsClient {
+            sender,
+            user_uuid: user_uuid.map(String::from),
+            user_name: None,
+            room: room.to_string(),
+            joined_seq: 0,
+        };
+
+        (client, receiver)
+    }
+
+    fn event(seq: u64, audience: Audience) -> BusEvent {
+        BusEvent {
+            seq,
+            audience,
+            payload: format!(r#"{{"event":"post_hidden","data":{{"id":{}}}}}"#, seq),
+            quarantined: false,
+        }
+    }
+
+    fn seqs(messages: &[Message]) -> Vec<u64> {
+        messages
+            .iter()
+            .map(|message| {
+                let event: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+                event["seq"].as_u64().unwrap()
+            })
+            .collect()
+    }
+
+    fn registry_with_events(first_seq: u64, last_seq: u64) -> WsRegistry {
+        let mut registry = WsRegistry {
+            last_seq: first_seq - 1,
+            ..WsRegistry::default()
+        };
+        for seq in first_seq..=last_seq {
+            registry.broadcast(&event(seq, Audience::All));
+        }
+
+        registry
+    }
+
+    #[test]
+    fn with_seq() {
+        assert_eq!(
+            r#"{"data":{"id":1},"event":"post_hidden","seq":5}"#,
+            WsRegistry::with_seq(r#"{"event":"post_hidden","data":{"id":1}}"#, 5)
+        );
+        assert_eq!("not json", WsRegistry::with_seq("not json", 5));
+    }
+
+    #[test]
+    fn get_missed_events_for_audience() {
+        let mut registry = registry_with_events(11, 11);
+        registry.broadcast(&event(12, Audience::Room(String::from("other"))));
+        registry.broadcast(&event(13, Audience::Users(vec![String::from("user")])));
+        registry.broadcast(&event(14, Audience::Users(vec![String::from("other")])));
+        registry.broadcast(&event(15, Audience::Room(String::from("main"))));
+
+        let (client, _receiver) = client(Some("user"), "main");
+        registry.insert(1, client);
+        // Delivered live, not replayed.
+        registry.broadcast(&event(16, Audience::All));
+
+        assert_eq!(
+            vec![11, 13, 15],
+            seqs(&registry.get_missed_events(1, 10).unwrap())
+        );
+        assert_eq!(vec![15], seqs(&registry.get_missed_events(1, 13).unwrap()));
+        assert!(registry.get_missed_events(1, 16).unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_missed_events_no_longer_in_log() {
+        let mut registry = registry_with_events(11, 20);
+        let (client, _receiver) = client(None, "main");
+        registry.insert(1, client);
+
+        assert!(registry.get_missed_events(1, 10).is_some());
+        assert!(registry.get_missed_events(1, 9).is_none());
+        // From a later run or another bus.
+        assert!(registry.get_missed_events(1, 21).is_none());
+        assert!(registry.get_missed_events(2, 15).is_none());
+    }
+
+    #[test]
+    fn get_missed_events_after_log_overflow() {
+        let mut registry = registry_with_events(1, EVENT_LOG_SIZE as u64 + 1);
+        let (client, _receiver) = client(None, "main");
+        registry.insert(1, client);
+
+        assert!(registry.get_missed_events(1, 0).is_none());
+        assert_eq!(
+            EVENT_LOG_SIZE,
+            registry.get_missed_events(1, 1).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn broadcast_to_audience() {
+        let mut registry = WsRegistry::default();
+        let (first, first_receiver) = client(Some("user"), "main");
+        let (second, second_receiver) = client(None, "other");
+        registry.insert(1, first);
+        registry.insert(2, second);
+
+        registry.broadcast(&event(1, Audience::Users(vec![String::from("user")])));
+        registry.broadcast(&event(2, Audience::Room(String::from("other"))));
+
+        let received: Vec<Message> = first_receiver.try_iter().collect();
+        assert_eq!(vec![1], seqs(&received));
+        let received: Vec<Message> = second_receiver.try_iter().collect();
+        assert_eq!(vec![2], seqs(&received));
     }
 }
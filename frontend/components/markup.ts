@@ -99,6 +99,12 @@ export function markup(m: Markup | Markup[]): string {
         return `<a class="${className}" href="${m.tag.url}" target="_blank">${html}</a>`;
       }
 
+      case 'Emoji':
+        return (
+          `<img class="markup markup_emoji" src="/thumb/${m.tag.md5}" ` +
+          `alt=":${m.tag.shortcode}:" title=":${m.tag.shortcode}:" />`
+        );
+
       case 'Quote':
         return `<span class="markup markup_quote">${html}</span>`;
 
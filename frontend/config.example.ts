@@ -1,5 +1,6 @@
 export const config = {
-  wsUrl: 'ws://localhost:8001',
+  wsUrl: 'ws://localhost:8000/api/v1/ws',
+  eventsUrl: '/api/v1/events',
   ssoOrigin: 'http://localhost:8100',
   sentryDsn: null,
   coubProxyUrl: '/api/v1/oembed/coub/',
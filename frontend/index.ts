@@ -89,7 +89,7 @@ window.sso = new Sso(() => window.api?.getToken());
 window.tiktok = new TikTok();
 window.tenor = new Tenor();
 window.youtube = new YouTube();
-window.ws = new Ws(config.wsUrl);
+window.ws = new Ws(config.wsUrl, config.eventsUrl);
 
 token.subscribe((token) => {
   if (!token) {
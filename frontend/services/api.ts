@@ -1,7 +1,7 @@
 import axios, { AxiosRequestConfig } from 'axios';
 
 import { token } from '../stores/auth';
-import { Post, NotificationDTO } from '../types';
+import { ChatSettings, Post, NotificationDTO } from '../types';
 
 interface SubmitPostRequest {
   readonly name: string;
@@ -18,6 +18,11 @@ interface PostResponse {
   readonly item: Post;
 }
 
+interface SettingsResponse {
+  readonly item: ChatSettings;
+  readonly is_moderator: boolean;
+}
+
 interface NotificationResponse {
   readonly item: NotificationDTO;
 }
@@ -86,21 +91,43 @@ export class Api {
     return response.data.item;
   };
 
+  // Posts are readable anonymously, but some are visible only to their author.
+  private getOptionalAuthConfig = async (): Promise<AxiosRequestConfig> => {
+    const config: AxiosRequestConfig = { headers: {} };
+
+    const token = await this.getToken();
+    if (token) {
+      config.headers['Authorization'] = `Bearer ${token}`;
+    }
+
+    return config;
+  };
+
   public getLatestPosts = async (): Promise<Post[]> => {
-    const response = await axios.get<PostListResponse>('/api/v1/posts');
+    const config = await this.getOptionalAuthConfig();
+    const response = await axios.get<PostListResponse>('/api/v1/posts', config);
     return response.data.items;
   };
 
   public getPostsBefore = async (postId: number): Promise<Post[]> => {
-    const response = await axios.get<PostListResponse>(`/api/v1/posts?before_id=${postId}`);
+    const config = await this.getOptionalAuthConfig();
+    const url = `/api/v1/posts?before_id=${postId}`;
+    const response = await axios.get<PostListResponse>(url, config);
     return response.data.items;
   };
 
   public getPost = async (postId: number): Promise<Post> => {
-    const response = await axios.get<PostResponse>(`/api/v1/posts/${postId}`);
+    const config = await this.getOptionalAuthConfig();
+    const response = await axios.get<PostResponse>(`/api/v1/posts/${postId}`, config);
     return response.data.item;
   };
 
+  public getSettings = async (): Promise<SettingsResponse> => {
+    const config = await this.getOptionalAuthConfig();
+    const response = await axios.get<SettingsResponse>('/api/v1/settings', config);
+    return response.data;
+  };
+
   public getNotifications = async (): Promise<NotificationDTO[]> => {
     const config: AxiosRequestConfig = { headers: {} };
 
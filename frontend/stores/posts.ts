@@ -58,7 +58,7 @@ function processMarkup(markup: Markup, post: Post): Markup {
           return { ...targetPost, reply_from };
         }),
       );
-    } else if (tag.type === 'Link') {
+    } else if (tag.type === 'Link' && tag.embed !== false) {
       if (VOCAROO_PATTERN.test(tag.url)) {
         const matches = tag.url.match(VOCAROO_PATTERN);
         if (matches) {
@@ -144,6 +144,14 @@ export function addPosts(newPosts: Post[]): void {
   });
 }
 
+export function removePost(postId: number): void {
+  posts.update((posts) => {
+    const result = { ...posts };
+    delete result[postId];
+    return result;
+  });
+}
+
 export function setPosts(newPosts: Post[]): void {
   posts.set({});
   addPosts(newPosts);
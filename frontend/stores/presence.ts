@@ -0,0 +1,9 @@
+import { writable } from 'svelte/store';
+
+// Names of the users typing in the current room, sent by the server once a second at most.
+export const typingUserNames = writable<string[]>([]);
+
+export const presence = writable<{ clients: number; users: number }>({
+  clients: 0,
+  users: 0,
+});
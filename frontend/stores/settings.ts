@@ -0,0 +1,16 @@
+import { writable, derived } from 'svelte/store';
+
+import { ChatSettings } from '../types';
+
+export const chatSettings = writable<ChatSettings>({
+  slow_mode_seconds: 0,
+  read_only: false,
+});
+
+// Moderators are exempt from the read-only and slow modes.
+export const isModerator = writable<boolean>(false);
+
+export const postingLocked = derived(
+  [chatSettings, isModerator],
+  ([$chatSettings, $isModerator]) => $chatSettings.read_only && !$isModerator
+);
@@ -10,6 +10,7 @@ export interface File {
   readonly width: null | number;
   readonly height: null | number;
   readonly length: null | number;
+  readonly removed: boolean;
 }
 
 export type EmbedMimeType = 'video/x-coub' | 'video/x-tiktok' | 'video/x-youtube';
@@ -60,6 +61,7 @@ export interface RefLink {
 export interface Link {
   readonly type: 'Link';
   readonly url: string;
+  readonly embed?: boolean;
 }
 
 export interface Dice {
@@ -68,6 +70,12 @@ export interface Dice {
   readonly max: number;
 }
 
+export interface Emoji {
+  readonly type: 'Emoji';
+  readonly shortcode: string;
+  readonly md5: string;
+}
+
 export interface Quote {
   readonly type: 'Quote';
 }
@@ -77,7 +85,7 @@ export interface HTML {
   readonly content: string;
 }
 
-export type Tag = BBCode | Color | RefLink | Link | Dice | Quote | HTML;
+export type Tag = BBCode | Color | RefLink | Link | Dice | Emoji | Quote | HTML;
 
 interface MarkupText {
   readonly type: 'Text';
@@ -101,6 +109,8 @@ export interface Post {
   readonly created_at: string;
   readonly files: File[];
   readonly user_uuid: string | null;
+  readonly room_id: number;
+  readonly bot: boolean;
 
   reply_from?: number[];
   embeds?: string[];
@@ -126,3 +136,8 @@ export interface NotificationDTO {
   readonly read: boolean;
   readonly post: Post;
 }
+
+export interface ChatSettings {
+  readonly slow_mode_seconds: number;
+  readonly read_only: boolean;
+}
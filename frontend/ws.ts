@@ -1,8 +1,10 @@
 import { PostNotification as PostNotificationModel, PostNotification } from './models/Notification';
 import { TokenData } from './services/sso';
 import { token } from './stores/auth';
-import { addPost, setPosts } from './stores/posts';
-import { Post, NotificationDTO } from './types';
+import { addPost, removePost, setPosts } from './stores/posts';
+import { presence, typingUserNames } from './stores/presence';
+import { chatSettings, isModerator } from './stores/settings';
+import { ChatSettings, Post, NotificationDTO } from './types';
 import utils from './utils';
 import NotificationPopups from './stores/NotificationPopups';
 import Notifications from './stores/Notifications';
@@ -21,24 +23,128 @@ interface WsNotificationCreated {
   };
 }
 
-type WsEvent = WsPostCreated | WsNotificationCreated;
+interface WsPostHidden {
+  readonly event: 'post_hidden';
+  readonly data: {
+    readonly id: number;
+  };
+}
+
+interface WsReportCreated {
+  readonly event: 'report_created';
+  readonly data: {
+    readonly item: unknown;
+  };
+}
+
+interface WsSettingsChanged {
+  readonly event: 'settings_changed';
+  readonly data: {
+    readonly item: ChatSettings;
+  };
+}
+
+interface WsTyping {
+  readonly event: 'typing';
+  readonly data: {
+    readonly room: string;
+    readonly user_names: string[];
+  };
+}
+
+interface WsPresence {
+  readonly event: 'presence';
+  readonly data: {
+    readonly clients: number;
+    readonly users: number;
+  };
+}
+
+interface WsResyncRequired {
+  readonly event: 'resync_required';
+}
+
+interface WsAuthenticated {
+  readonly event: 'authenticated';
+  readonly data: {
+    readonly user_uuid: string;
+  };
+}
+
+interface WsAck {
+  readonly event: 'ack';
+  readonly data: {
+    readonly nonce: string;
+    readonly id?: number;
+    readonly error?: unknown;
+  };
+}
+
+interface WsAuthFailed {
+  readonly event: 'auth_failed';
+  readonly data: {
+    readonly message: string;
+  };
+}
+
+// Events broadcast by the server carry a sequence id, replies to the client don't.
+type WsEvent = (
+  | WsAuthenticated
+  | WsAuthFailed
+  | WsAck
+  | WsResyncRequired
+  | WsTyping
+  | WsPresence
+  | WsPostCreated
+  | WsNotificationCreated
+  | WsPostHidden
+  | WsReportCreated
+  | WsSettingsChanged
+) & { readonly seq?: number };
+
+// The server forgets typing users after 6 seconds, so keep telling it while typing.
+const TYPING_INTERVAL = 3000;
+
+// Some proxies break WebSocket connections, the event stream is used after this many failures.
+const MAX_FAILED_ATTEMPTS = 3;
 
 function isWsEvent(data: unknown): data is WsEvent {
   return typeof (data as WsEvent).event !== undefined;
 }
 
-let _token: TokenData | null = null;
-token.subscribe((token) => (_token = token));
-
 export class Ws {
   private ws: null | WebSocket = null;
+  private eventSource: null | EventSource = null;
+  private opened = false;
+  private failedAttempts = 0;
   private keepAliveInterval: null | number = null;
+  // Sequence id of the last event received, used to get the missed ones after reconnecting.
+  private lastSeq: null | number = null;
+  private lastTypingAt: null | number = null;
+  private userName: null | string = null;
+  private authenticated = false;
+  private nextNonce = 1;
+  // Posts sent over the socket, waiting for their acknowledgement.
+  private pendingPosts: {
+    [nonce: string]: { resolve: (id: number) => void; reject: (error: unknown) => void };
+  } = {};
 
-  public constructor(private readonly url: string) {
+  public constructor(private readonly url: string, private readonly eventsUrl: string) {
     this.open();
+
+    // Re-authenticate the open connection after logging in or refreshing the token.
+    token.subscribe((token: TokenData | null) => {
+      this.userName = token?.user_name ?? null;
+      if (this.eventSource) {
+        this.openEventStream();
+      } else if (token) {
+        this.authenticate();
+      }
+    });
   }
 
   private open = () => {
+    this.opened = false;
     this.ws = new WebSocket(this.url);
     this.ws.addEventListener('open', this.onOpen);
     this.ws.addEventListener('close', this.onClose);
@@ -58,6 +164,7 @@ export class Ws {
   private close = () => {
     this.ws?.close();
     this.ws = null;
+    this.authenticated = false;
 
     if (this.keepAliveInterval) {
       clearInterval(this.keepAliveInterval);
@@ -65,7 +172,95 @@ export class Ws {
     }
   };
 
-  private onOpen = () => {
+  // The event stream only receives events, posts and typing go through the HTTP API or nowhere.
+  // The browser reconnects it by itself, resuming from the last event id.
+  private openEventStream = async () => {
+    const token = await window.api?.getToken();
+    const params = new URLSearchParams();
+    if (token) {
+      params.set('token', token);
+    }
+
+    if (this.lastSeq !== null) {
+      params.set('last_event_id', this.lastSeq.toString());
+    }
+
+    this.eventSource?.close();
+    this.eventSource = new EventSource(`${this.eventsUrl}?${params}`);
+    this.eventSource.addEventListener('message', this.onMessage);
+    if (this.lastSeq === null) {
+      this.reload();
+    }
+  };
+
+  private authenticate = async () => {
+    // Authenticated connections also receive events meant only for the current user.
+    const token = await window.api?.getToken();
+    if (!token) {
+      return;
+    }
+
+    this.send({ event: 'auth', data: { token } });
+  };
+
+  public get canPost() {
+    return this.authenticated && this.ws?.readyState === WebSocket.OPEN;
+  }
+
+  // Resolves with the post id once the server acknowledges the post. The post_created event
+  // for it is usually received before that, but may come later when the server runs several
+  // instances.
+  public createPost = (data: { name: string; tripcode: string; message: string }) => {
+    const nonce = `${Date.now()}-${this.nextNonce++}`;
+    return new Promise<number>((resolve, reject) => {
+      this.pendingPosts[nonce] = { resolve, reject };
+      this.send({ event: 'create_post', data: { nonce, ...data } });
+    });
+  };
+
+  public startTyping = () => {
+    const now = Date.now();
+    if (this.lastTypingAt !== null && now - this.lastTypingAt < TYPING_INTERVAL) {
+      return;
+    }
+
+    this.lastTypingAt = now;
+    this.send({ event: 'typing_started' });
+  };
+
+  public stopTyping = () => {
+    if (this.lastTypingAt === null) {
+      return;
+    }
+
+    this.lastTypingAt = null;
+    this.send({ event: 'typing_stopped' });
+  };
+
+  private send = (message: unknown) => {
+    if (this.ws?.readyState !== WebSocket.OPEN) {
+      return;
+    }
+
+    this.ws.send(JSON.stringify(message));
+  };
+
+  private onOpen = async () => {
+    this.opened = true;
+    this.failedAttempts = 0;
+
+    await this.authenticate();
+
+    // Replay the missed events after reconnecting, the server asks for a reload if it can't.
+    if (this.lastSeq !== null) {
+      this.send({ resume_from: this.lastSeq });
+      return;
+    }
+
+    this.reload();
+  };
+
+  private reload = () => {
     // Reload latest posts and notifications after websocket connected.
     window.api?.getLatestPosts().then((posts) => {
       const scroll = utils.isAtBottom();
@@ -76,6 +271,11 @@ export class Ws {
       setPosts(posts);
     });
 
+    window.api?.getSettings().then((response) => {
+      chatSettings.set(response.item);
+      isModerator.set(response.is_moderator);
+    });
+
     // Auth required to get notifications.
     if (!window.sso?.loaded) {
       return;
@@ -88,10 +288,22 @@ export class Ws {
   };
 
   private onClose = (e: CloseEvent) => {
+    this.authenticated = false;
+
+    // Posts sent over the closed connection may or may not have been created.
+    Object.values(this.pendingPosts).forEach((post) => post.reject(new Error('Connection closed')));
+    this.pendingPosts = {};
+
     if (e.code === 1000) {
       return;
     }
 
+    if (!this.opened && ++this.failedAttempts >= MAX_FAILED_ATTEMPTS) {
+      this.close();
+      this.openEventStream();
+      return;
+    }
+
     setTimeout(this.open, 10000);
   };
 
@@ -101,7 +313,48 @@ export class Ws {
       return;
     }
 
+    if (typeof message.seq === 'number') {
+      this.lastSeq = message.seq;
+    }
+
     switch (message.event) {
+      case 'typing': {
+        typingUserNames.set(message.data.user_names.filter((name) => name !== this.userName));
+        break;
+      }
+
+      case 'presence': {
+        presence.set(message.data);
+        break;
+      }
+
+      case 'resync_required': {
+        this.reload();
+        break;
+      }
+
+      case 'authenticated': {
+        this.authenticated = true;
+        break;
+      }
+
+      case 'ack': {
+        const post = this.pendingPosts[message.data.nonce];
+        delete this.pendingPosts[message.data.nonce];
+        if (typeof message.data.id === 'number') {
+          post?.resolve(message.data.id);
+        } else {
+          post?.reject(message.data.error);
+        }
+        break;
+      }
+
+      case 'auth_failed': {
+        this.authenticated = false;
+        console.warn('WebSocket authentication failed:', message.data.message);
+        break;
+      }
+
       case 'post_created': {
         const scroll = utils.isAtBottom();
         if (scroll) {
@@ -113,11 +366,24 @@ export class Ws {
       }
 
       case 'notification_created': {
-        if (message.data.item.user_uuid === _token?.user_uuid) {
-          const notificationModel = PostNotificationModel.createFromDTO(message.data.item);
-          Notifications.add(notificationModel);
-          NotificationPopups.add(notificationModel);
-        }
+        const notificationModel = PostNotificationModel.createFromDTO(message.data.item);
+        Notifications.add(notificationModel);
+        NotificationPopups.add(notificationModel);
+        break;
+      }
+
+      case 'post_hidden': {
+        removePost(message.data.id);
+        break;
+      }
+
+      case 'report_created': {
+        window.eventBus.dispatch('report_created', message.data.item);
+        break;
+      }
+
+      case 'settings_changed': {
+        chatSettings.set(message.data.item);
         break;
       }
     }